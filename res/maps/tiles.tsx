@@ -0,0 +1,19 @@
+<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.2" tiledversion="1.2.4" name="tiles" tilewidth="16" tileheight="16" tilecount="21" columns="21">
+ <image source="../textures/tiles.png" width="336" height="16"/>
+ <tile id="11">
+  <properties>
+   <property name="passable" type="bool" value="false"/>
+  </properties>
+ </tile>
+ <tile id="14">
+  <properties>
+   <property name="passable" type="bool" value="false"/>
+  </properties>
+ </tile>
+ <tile id="18">
+  <properties>
+   <property name="passable" type="bool" value="false"/>
+  </properties>
+ </tile>
+</tileset>
@@ -0,0 +1,341 @@
+//headless world generation, for tuning the noise and structure constants without opening the game
+//usage: bittenoff-worldgen [--seed <n>] [--range <x0> <y0> <x1> <y1>] [--layer <n>] [--format ascii|png|json] [--out <dir>] [--route]
+//--layer picks the cave layer below the surface, e.g. -1
+//--route generates the range and hops across it from the middle of its first area to the middle of its last,
+//the way an npc would, failing if the route planner can't find a way
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use png::HasParameters;
+use ron::de::from_str;
+use serde::Serialize;
+
+use amethyst::utils::application_root_dir;
+
+use bittenoff::game_state::{Anchor, Area, Config, Tile, WorldMap, WorldRng, TILE_SIZE, roll_world_seed, generate_area};
+use bittenoff::worldgen::{BiomeSet, LandmarkSet, PassGenerator, TiledPass, find_doors, plan_route};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Format {
+    Ascii,
+    Png,
+    Json,
+}
+
+struct Options {
+    seed: Option<u64>,
+    range: ((i32, i32), (i32, i32)),
+    layer: i32,
+    format: Format,
+    out: Option<PathBuf>,
+    route: bool,
+}
+
+#[derive(Serialize)]
+struct AreaStats {
+    location: (i32, i32, i32),
+    biomes: BTreeMap<String, usize>,
+    structures: usize,
+    doors: Vec<(usize, usize)>,
+    settlement: Option<(i32, i32)>,
+}
+
+fn usage() -> ! {
+    println!("usage: bittenoff-worldgen [--seed <n>] [--range <x0> <y0> <x1> <y1>] [--layer <n>] [--format ascii|png|json] [--out <dir>] [--route]");
+    std::process::exit(1);
+}
+
+fn parse_args() -> Options {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let mut options = Options {
+        seed: None,
+        range: ((0, 0), (0, 0)),
+        layer: 0,
+        format: Format::Ascii,
+        out: None,
+        route: false,
+    };
+
+    let mut i = 0;
+    while i < args.len() {
+        match args[i].as_str() {
+            "--seed" if i + 1 < args.len() => {
+                options.seed = Some(args[i + 1].parse().unwrap_or_else(|_| usage()));
+                i += 2;
+            }
+            "--range" if i + 4 < args.len() => {
+                let v: Vec<i32> = args[i + 1..i + 5].iter().map(|a| a.parse().unwrap_or_else(|_| usage())).collect();
+                options.range = ((v[0].min(v[2]), v[1].min(v[3])), (v[0].max(v[2]), v[1].max(v[3])));
+                i += 5;
+            }
+            "--layer" if i + 1 < args.len() => {
+                options.layer = args[i + 1].parse().unwrap_or_else(|_| usage());
+                i += 2;
+            }
+            "--format" if i + 1 < args.len() => {
+                options.format = match args[i + 1].as_str() {
+                    "ascii" => Format::Ascii,
+                    "png" => Format::Png,
+                    "json" => Format::Json,
+                    _ => usage(),
+                };
+                i += 2;
+            }
+            "--out" if i + 1 < args.len() => {
+                options.out = Some(PathBuf::from(&args[i + 1]));
+                i += 2;
+            }
+            "--route" => {
+                options.route = true;
+                i += 1;
+            }
+            _ => usage(),
+        }
+    }
+    options
+}
+
+fn tile_char(tile: Tile) -> char {
+    match tile {
+        Tile::Plain => '.',
+        Tile::Grassy => ',',
+        Tile::RockyLight => ':',
+        Tile::GrassyHeavy => '"',
+        Tile::Rocky => '^',
+        Tile::Sandy => '~',
+        Tile::SandySparse => '-',
+        Tile::SandyRocky => 'o',
+        Tile::SandyWeed => 'w',
+        Tile::SandyBoulder => 'O',
+        Tile::WoodFloor => '_',
+        Tile::WoodWall => '#',
+        Tile::Road => '=',
+        Tile::Path => ';',
+        Tile::DeepWater => 'W',
+        Tile::ShallowWater => 'v',
+        Tile::Bridge => 'H',
+        Tile::CaveFloor => ' ',
+        Tile::CaveWall => '%',
+        Tile::StairsDown => '>',
+        Tile::StairsUp => '<',
+        Tile::Size => '?',
+    }
+}
+
+//rows are printed north first, doors as '+'
+fn area_ascii(area: &Area, (w, h): (usize, usize)) -> String {
+    let doors = find_doors(area, w);
+    let mut out = String::new();
+    for row in 0..h {
+        let y = h - 1 - row;
+        for x in 0..w {
+            if doors.contains(&(x, y)) {
+                out.push('+');
+            }else{
+                out.push(tile_char(area.tiles[x + y * w].tile));
+            }
+        }
+        out.push('\n');
+    }
+    out
+}
+
+fn area_stats(area: &Area, location: (i32, i32, i32), (w, _h): (usize, usize), biomes: &BiomeSet) -> AreaStats {
+    let mut histogram = BTreeMap::new();
+    for index in area.biomes.iter() {
+        let name = match biomes.get(*index) {
+            Some(b) => b.name.clone(),
+            None => format!("#{}", index),
+        };
+        *histogram.entry(name).or_insert(0) += 1;
+    }
+    AreaStats {
+        location,
+        biomes: histogram,
+        structures: area.structures.len(),
+        doors: find_doors(area, w),
+        settlement: area.settlement,
+    }
+}
+
+//the walkable tile nearest the middle of the area
+fn middle_tile(area: &Area, (w, h): (usize, usize)) -> Option<(usize, usize)> {
+    let (cx, cy) = (w as i64 / 2, h as i64 / 2);
+    (0..w * h).filter(|i| area.tiles[*i].passable)
+        .min_by_key(|i| ((*i % w) as i64 - cx).abs().max(((*i / w) as i64 - cy).abs()))
+        .map(|i| (i % w, i / w))
+}
+
+//follows plan_route one area at a time from the first area of the range to the last; areas outside the range
+//are left unexplored, as they would be in game
+fn check_route(range: ((i32, i32), (i32, i32)), layer: i32, dim: (usize, usize), world_seed: (f64, f64, f64, f64, f64, f64, f64, f64), world_rng: &WorldRng, generator: &PassGenerator) -> bool {
+    let ((x0, y0), (x1, y1)) = range;
+    let mut world_map = WorldMap::new();
+    for y in y0..=y1 {
+        for x in x0..=x1 {
+            world_map.insert((x, y, layer), generate_area((x, y, layer), dim, world_seed, world_rng, generator));
+        }
+    }
+
+    let (start, goal) = ((x0, y0, layer), (x1, y1, layer));
+    let (mut location, mut tile) = match world_map.get(start).and_then(|a| middle_tile(a, dim)) {
+        Some(t) => (start, t),
+        None => {
+            println!("nowhere to start in {:?}", start);
+            return false;
+        }
+    };
+    let goal = match world_map.get(goal).and_then(|a| middle_tile(a, dim)) {
+        Some((x, y)) => Anchor::new(x, y, goal),
+        None => {
+            println!("nowhere to go in {:?}", goal);
+            return false;
+        }
+    };
+
+    //a route never needs to enter more areas than the range and its margin hold
+    let limit = ((x1 - x0 + 3) * (y1 - y0 + 3)) as usize;
+    for _i in 0..limit {
+        if location == goal.area() {
+            println!("reached {:?}", goal);
+            return true;
+        }
+        match plan_route(&world_map, dim, location, tile, goal) {
+            Some(crossing) => {
+                println!("{:?} {:?} -> edge {} at {:?}, about {} to go", location, tile, crossing.edge, crossing.tile, crossing.cost);
+                let (dx, dy) = crossing.offset();
+                location = (location.0 + dx, location.1 + dy, layer);
+                tile = crossing.arrival;
+            }
+            None => {
+                println!("no route from {:?} {:?} to {:?}", location, tile, goal);
+                return false;
+            }
+        }
+    }
+    println!("gave up after {} areas", limit);
+    false
+}
+
+//the tile sheet as rgba rows, one sprite per tile in Tile order
+fn load_palette(path: &Path) -> (Vec<u8>, usize) {
+    let decoder = png::Decoder::new(File::open(path).expect("Error opening tile texture"));
+    let (info, mut reader) = decoder.read_info().expect("Error reading tile texture");
+    if info.color_type != png::ColorType::RGBA || info.bit_depth != png::BitDepth::Eight {
+        panic!("tile texture must be 8 bit rgba");
+    }
+    let mut buf = vec![0; info.buffer_size()];
+    reader.next_frame(&mut buf).expect("Error decoding tile texture");
+    (buf, info.width as usize)
+}
+
+fn write_area_png(path: &Path, area: &Area, (w, h): (usize, usize), (sheet, sheet_width): (&[u8], usize)) {
+    let px_w = w * TILE_SIZE;
+    let px_h = h * TILE_SIZE;
+    let mut data = vec![0u8; px_w * px_h * 4];
+    for y in 0..h {
+        for x in 0..w {
+            let sprite = area.tiles[x + y * w].tile as usize;
+            //image rows run top down
+            let top = (h - 1 - y) * TILE_SIZE;
+            for py in 0..TILE_SIZE {
+                let src = (py * sheet_width + sprite * TILE_SIZE) * 4;
+                let dst = ((top + py) * px_w + x * TILE_SIZE) * 4;
+                data[dst..dst + TILE_SIZE * 4].copy_from_slice(&sheet[src..src + TILE_SIZE * 4]);
+            }
+        }
+    }
+
+    let file = File::create(path).expect("Error creating png");
+    let mut encoder = png::Encoder::new(BufWriter::new(file), px_w as u32, px_h as u32);
+    encoder.set(png::ColorType::RGBA).set(png::BitDepth::Eight);
+    let mut writer = encoder.write_header().expect("Error writing png header");
+    writer.write_image_data(&data).expect("Error writing png data");
+}
+
+fn main() {
+    let options = parse_args();
+    let app_root = application_root_dir().expect("Error finding application root");
+
+    let contents = fs::read_to_string(app_root.join("config").join("globals.ron"))
+        .expect("Error reading game config file");
+    let config: Config = from_str(&contents)
+        .expect("Error loading game config file");
+    let dim = (config.stage_width as usize / TILE_SIZE + 1, config.stage_height as usize / TILE_SIZE + 1);
+
+    let seed = match options.seed.or(config.world_seed) {
+        Some(s) => s,
+        None => {
+            println!("no seed given and none set in globals.ron");
+            usage();
+        }
+    };
+
+    let contents = fs::read_to_string(app_root.join("config").join("biomes.ron"))
+        .expect("Error reading biomes file");
+    let biomes: BiomeSet = from_str(&contents)
+        .expect("Error loading biomes file");
+    biomes.validate().expect("Error in biomes file");
+    let contents = fs::read_to_string(app_root.join("config").join("landmarks.ron"))
+        .expect("Error reading landmarks file");
+    let landmarks: LandmarkSet = from_str(&contents)
+        .expect("Error loading landmarks file");
+
+    //same seed to noise offsets path as LoadingState
+    let mut world_rng = WorldRng::new(seed);
+    let world_seed = roll_world_seed(&mut world_rng);
+    let generator = PassGenerator::standard(biomes.clone(), TiledPass::load(&landmarks, dim), config.diagonal_policy);
+
+    if options.route {
+        if !check_route(options.range, options.layer, dim, world_seed, &world_rng, &generator) {
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    let ((x0, y0), (x1, y1)) = options.range;
+    let out_dir = options.out.clone().unwrap_or_else(|| PathBuf::from("worldgen"));
+    let palette = if options.format == Format::Png {
+        fs::create_dir_all(&out_dir).expect("Error creating output directory");
+        Some(load_palette(&app_root.join("res").join("textures").join("tiles.png")))
+    }else{
+        None
+    };
+
+    let mut stats = Vec::new();
+    for y in (y0..=y1).rev() {
+        for x in x0..=x1 {
+            let location = (x, y, options.layer);
+            let area = generate_area(location, dim, world_seed, &world_rng, &generator);
+            match options.format {
+                Format::Ascii => {
+                    println!("area {:?}", location);
+                    println!("{}", area_ascii(&area, dim));
+                }
+                Format::Png => {
+                    let (sheet, sheet_width) = palette.as_ref().unwrap();
+                    let path = if options.layer == 0 {
+                        out_dir.join(format!("area_{}_{}.png", x, y))
+                    }else{
+                        out_dir.join(format!("area_{}_{}_{}.png", x, y, options.layer))
+                    };
+                    write_area_png(&path, &area, dim, (sheet, *sheet_width));
+                    println!("wrote {:?}", path);
+                }
+                Format::Json => stats.push(area_stats(&area, location, dim, &biomes)),
+            }
+        }
+    }
+
+    if options.format == Format::Json {
+        let json = serde_json::to_string_pretty(&stats).expect("Error serializing stats");
+        match options.out {
+            Some(path) => fs::write(&path, json).expect("Error writing stats"),
+            None => println!("{}", json),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use amethyst::ecs::prelude::{Component, VecStorage};
+use std::collections::BTreeMap;
+use serde::{Serialize, Deserialize};
+
+use crate::game_state::{Action, GoalType};
+use crate::components::NeedSet;
+
+//a node of a behaviour tree as written in behaviours.ron; composites and decorators hold their children,
+//the rest are leaves that check on the person or carry out an action through their goals
+#[derive(Clone, Debug, Deserialize)]
+pub enum Node {
+    Sequence(Vec<Node>), //children in order until one doesn't succeed
+    Selector(Vec<Node>), //children in order until one doesn't fail
+    Invert(Box<Node>),
+    Succeed(Box<Node>), //succeeds whatever the child does, unless it's still running
+    Cooldown(String, f32, Box<Node>), //fails for the given seconds after the child succeeds, timed under the key
+    NeedBelow(String, f32), //the need is less well met than the level
+    Chosen(Action), //the action scores best against the person's needs
+    HasGoal(GoalType),
+    Onscreen,
+    Resident,
+    IsSet(String), //the key is on the blackboard
+    Set(String, f32),
+    Clear(String),
+    Act(Action), //runs while the goals it sets are under way, succeeds once they're reached and fails if they're given up
+}
+
+impl Node {
+    //every need a leaf looks at has to be in the need set, and every action it weighs up has to be scored there
+    fn validate(&self, needs: &NeedSet) -> Result<(), String> {
+        match self {
+            Node::Sequence(children) | Node::Selector(children) => children.iter().map(|c| c.validate(needs)).collect(),
+            Node::Invert(child) | Node::Succeed(child) | Node::Cooldown(_, _, child) => child.validate(needs),
+            Node::NeedBelow(name, _) if needs.get(name).is_none() => Err(format!("unknown need {}", name)),
+            Node::Chosen(action) if !needs.actions.iter().any(|d| d.action == *action) => Err(format!("action {:?} is not in the need set", action)),
+            Node::Act(Action::Size) | Node::Chosen(Action::Size) => Err("no such action Size".to_string()),
+            _ => Ok(()),
+        }
+    }
+    //the actions of the Act leaves under the node, in the order they're ticked
+    fn acts(&self, out: &mut Vec<Action>) {
+        match self {
+            Node::Sequence(children) | Node::Selector(children) => children.iter().for_each(|c| c.acts(out)),
+            Node::Invert(child) | Node::Succeed(child) | Node::Cooldown(_, _, child) => child.acts(out),
+            Node::Act(action) => out.push(*action),
+            _ => {}
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Status {
+    Success,
+    Failure,
+    Running,
+}
+
+//every behaviour tree by name, as loaded from behaviours.ron, and which of them people get when nothing else
+//names one for them
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct BehaviourSet {
+    #[serde(default)]
+    pub default: Option<String>,
+    #[serde(default)]
+    pub residents: Option<String>, //for people spawned in a settlement
+    pub trees: BTreeMap<String, Node>,
+}
+
+impl BehaviourSet {
+    //named are the trees asked for from elsewhere, e.g. by landmark spawn points; a tree acts out each action in one
+    //place only, since all an Act leaf knows its goals by is their action
+    pub fn validate(&self, needs: &NeedSet, named: &[&str]) -> Result<(), String> {
+        let defaults = self.default.iter().chain(self.residents.iter()).map(|n| n.as_str());
+        for name in defaults.chain(named.iter().cloned()) {
+            if self.get(name).is_none() {
+                return Err(format!("no tree named {}", name));
+            }
+        }
+        for (name, tree) in self.trees.iter() {
+            tree.validate(needs).map_err(|e| format!("{} in tree {}", e, name))?;
+            let mut acts = Vec::new();
+            tree.acts(&mut acts);
+            if let Some((_, action)) = acts.iter().enumerate().find(|(i, a)| acts[..*i].contains(a)) {
+                return Err(format!("Act({:?}) appears more than once in tree {}", action, name));
+            }
+        }
+        Ok(())
+    }
+    pub fn get(&self, name: &str) -> Option<&Node> {
+        self.trees.get(name)
+    }
+    //the tree somebody runs: the one named for them, else the residents' or the default one; None leaves them
+    //going by their needs alone
+    pub fn tree_for<'a>(&'a self, named: Option<&'a str>, resident: bool) -> Option<&'a str> {
+        let fallback = if resident { self.residents.as_ref().or_else(|| self.default.as_ref()) } else { self.default.as_ref() };
+        named.or_else(|| fallback.map(|n| n.as_str())).filter(|n| self.get(n).is_some())
+    }
+    //a saved behaviour as it is, unless its tree has gone from behaviours.ron since; then it's swapped for the tree
+    //the person would get now, or dropped so they go by their needs
+    pub fn restore(&self, behaviour: Behaviour, resident: bool) -> Option<Behaviour> {
+        if self.get(behaviour.get_tree()).is_some() {
+            return Some(behaviour);
+        }
+        self.tree_for(None, resident).map(Behaviour::new)
+    }
+}
+
+//what a tree remembers about the one person running it between ticks
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct Blackboard {
+    clock: f32, //seconds the tree has been run for
+    values: BTreeMap<String, f32>,
+}
+
+impl Blackboard {
+    pub fn clock(&self) -> f32 {
+        self.clock
+    }
+    pub fn tick(&mut self, dt: f32) {
+        self.clock += dt;
+    }
+    pub fn get(&self, key: &str) -> Option<f32> {
+        self.values.get(key).cloned()
+    }
+    pub fn set(&mut self, key: &str, value: f32) {
+        self.values.insert(key.to_string(), value);
+    }
+    pub fn clear(&mut self, key: &str) {
+        self.values.remove(key);
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Behaviour {
+    tree: String,
+    blackboard: Blackboard,
+}
+
+impl Behaviour {
+    pub fn new(tree: &str) -> Behaviour {
+        Behaviour {
+            tree: tree.to_string(),
+            blackboard: Blackboard::default(),
+        }
+    }
+    pub fn get_tree(&self) -> &str {
+        &self.tree
+    }
+    pub fn blackboard(&mut self) -> &mut Blackboard {
+        &mut self.blackboard
+    }
+}
+
+impl Component for Behaviour {
+    type Storage = VecStorage<Self>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn needs() -> NeedSet {
+        ron::de::from_str(include_str!("../../config/needs.ron")).unwrap()
+    }
+
+    #[test]
+    fn shipped_trees_validate() {
+        let set: BehaviourSet = ron::de::from_str(include_str!("../../config/behaviours.ron")).unwrap();
+        assert!(set.validate(&needs(), &[]).is_ok());
+    }
+
+    #[test]
+    fn validate_rejects_missing_trees_and_unknown_needs() {
+        let mut set = BehaviourSet::default();
+        set.trees.insert("guard".to_string(), Node::Act(Action::Wander));
+        assert!(set.validate(&needs(), &["guard"]).is_ok());
+        assert!(set.validate(&needs(), &["gaurd"]).is_err());
+        set.default = Some("wanderer".to_string());
+        assert!(set.validate(&needs(), &[]).is_err());
+
+        let mut set = BehaviourSet::default();
+        let node = Node::Selector(vec![Node::Act(Action::Wander), Node::Invert(Box::new(Node::NeedBelow("thirst".to_string(), 0.5)))]);
+        set.trees.insert("resident".to_string(), node);
+        assert!(set.validate(&needs(), &[]).is_err());
+    }
+
+    #[test]
+    fn validate_rejects_an_action_acted_twice_in_a_tree() {
+        let mut set = BehaviourSet::default();
+        let hungry = Node::Sequence(vec![Node::NeedBelow("hunger".to_string(), 0.4), Node::Act(Action::Eat)]);
+        set.trees.insert("glutton".to_string(), Node::Selector(vec![hungry.clone(), Node::Act(Action::Wander)]));
+        assert!(set.validate(&needs(), &[]).is_ok());
+        set.trees.insert("glutton".to_string(), Node::Selector(vec![hungry, Node::Succeed(Box::new(Node::Act(Action::Eat)))]));
+        assert!(set.validate(&needs(), &[]).is_err());
+    }
+
+    #[test]
+    fn named_trees_come_before_the_defaults() {
+        let mut set = BehaviourSet::default();
+        for name in ["guard", "resident", "wanderer"].iter() {
+            set.trees.insert(name.to_string(), Node::Act(Action::Wander));
+        }
+        assert_eq!(set.tree_for(None, true), None);
+        set.default = Some("wanderer".to_string());
+        assert_eq!(set.tree_for(None, true), Some("wanderer"));
+        set.residents = Some("resident".to_string());
+        assert_eq!(set.tree_for(None, true), Some("resident"));
+        assert_eq!(set.tree_for(None, false), Some("wanderer"));
+        assert_eq!(set.tree_for(Some("guard"), true), Some("guard"));
+        assert_eq!(set.tree_for(Some("nobody"), false), None);
+    }
+
+    #[test]
+    fn restored_behaviours_fall_back_when_their_tree_is_gone() {
+        let mut set = BehaviourSet::default();
+        set.trees.insert("guard".to_string(), Node::Act(Action::Wander));
+        let mut guard = Behaviour::new("guard");
+        guard.blackboard().set("posted", 1.0);
+        assert_eq!(set.restore(guard, false).map(|mut b| b.blackboard().get("posted")), Some(Some(1.0)));
+        assert!(set.restore(Behaviour::new("hermit"), true).is_none());
+
+        set.trees.insert("resident".to_string(), Node::Act(Action::Rest));
+        set.residents = Some("resident".to_string());
+        assert_eq!(set.restore(Behaviour::new("hermit"), true).map(|b| b.get_tree().to_string()), Some("resident".to_string()));
+        assert!(set.restore(Behaviour::new("hermit"), false).is_none());
+    }
+}
@@ -1,8 +1,9 @@
 use amethyst::ecs::prelude::{Component, VecStorage};
 use uuid::Uuid;
+use serde::{Serialize, Deserialize};
 use crate::game_state::{EntityType};
 
-#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Id{
     id: Uuid,
     etype: EntityType,
@@ -8,8 +8,12 @@ pub use self::offscreen::Offscreen;
 pub use self::sub_ui::SubUi;
 pub use self::particle::Particle;
 pub use self::particle::DeathType as ParticleDeathType;
-pub use self::hunger::Hunger;
+pub use self::needs::{Needs, NeedSet, NeedDef, ActionDef, Curve};
 pub use self::plant::Plant;
+pub use self::resident::Resident;
+pub use self::behaviour::{Behaviour, BehaviourSet, Blackboard};
+pub use self::behaviour::Node as BehaviourNode;
+pub use self::behaviour::Status as BehaviourStatus;
 mod fps;
 mod tile;
 mod player;
@@ -19,5 +23,7 @@ mod offscreen;
 mod physical;
 mod sub_ui;
 mod particle;
-mod hunger;
-mod plant;
\ No newline at end of file
+mod needs;
+mod plant;
+mod resident;
+mod behaviour;
\ No newline at end of file
@@ -1,14 +1,26 @@
 use amethyst::ecs::prelude::{Component, VecStorage};
 use std::collections::BinaryHeap;
+use std::collections::binary_heap::PeekMut;
 use std::cmp::Ordering;
+use serde::{Serialize, Deserialize};
 
-use crate::game_state::{Anchor, GoalType};
+use crate::game_state::{Anchor, Action, GoalPriority, GoalType, Preemption, GOAL_RETRY_DELAY, MAX_GOAL_FAILURES};
 
-#[derive(Clone, Eq, PartialEq)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Goal {
     pub priority: usize,
     pub point: Anchor,
     pub gtype: GoalType,
+    #[serde(default)]
+    pub preemption: Preemption,
+    #[serde(default)]
+    pub timeout: f32, //in seconds of being worked on, none if 0
+    #[serde(default)]
+    age: f32,
+    #[serde(default)]
+    failures: u32,
+    #[serde(default)]
+    wait: f32, //seconds left before trying again after a failure
 }
 
 impl Goal {
@@ -17,8 +29,16 @@ impl Goal {
             priority,
             point,
             gtype,
+            preemption: gtype.preemption(),
+            timeout: gtype.timeout(),
+            age: 0.0,
+            failures: 0,
+            wait: 0.0,
         }
     }
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
     pub fn pos(&self) -> (f32, f32) {
         self.point.real_local()
     }
@@ -29,6 +49,15 @@ impl Goal {
     }
 }
 
+//the timers change while a goal sits in the queue, so only what it is counts
+impl PartialEq for Goal {
+    fn eq(&self, other: &Goal) -> bool {
+        self.priority == other.priority && self.point == other.point && self.gtype == other.gtype
+    }
+}
+
+impl Eq for Goal {}
+
 // The priority queue depends on `Ord`.
 // Explicitly implement the trait so the queue becomes a min-heap
 // instead of a max-heap.
@@ -49,12 +78,22 @@ impl PartialOrd for Goal {
     }
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Mover{
     pos_goals: BinaryHeap<Goal>,
     step_vec: Vec<Anchor>,
     path_cost: usize,
     base_speed: f32,
     last_step: (usize, usize),
+    //a path was asked for and hasn't come back yet; requests aren't saved, so neither is this
+    #[serde(skip)]
+    path_pending: bool,
+    //seconds spent stuck behind somebody else
+    #[serde(skip)]
+    blocked_time: f32,
+    //a goal let go of without being reached since the last one was added or reached
+    #[serde(default)]
+    given_up: Option<GoalType>,
 }
 
 impl Mover{
@@ -65,6 +104,9 @@ impl Mover{
             path_cost: 0,
             base_speed: speed,
             last_step: (usize::max_value(), usize::max_value()),
+            path_pending: false,
+            blocked_time: 0.0,
+            given_up: None,
         }
     }
 
@@ -72,6 +114,21 @@ impl Mover{
     pub fn speed(&self) -> f32 {
         self.base_speed
     }
+    pub fn is_path_pending(&self) -> bool {
+        self.path_pending
+    }
+    pub fn set_path_pending(&mut self, pending: bool) {
+        self.path_pending = pending;
+    }
+    pub fn blocked_time(&self) -> f32 {
+        self.blocked_time
+    }
+    pub fn add_blocked_time(&mut self, dt: f32) {
+        self.blocked_time += dt;
+    }
+    pub fn reset_blocked_time(&mut self) {
+        self.blocked_time = 0.0;
+    }
     pub fn is_step_vec_empty(&self) -> bool {
         self.step_vec.is_empty()
     }
@@ -83,48 +140,114 @@ impl Mover{
         //println!("setting step vec to {:?}", vec);
         self.step_vec = vec;
         self.path_cost = cost;
+        self.last_step = (usize::max_value(), usize::max_value());
     }
     pub fn get_step(&self) -> Option<Anchor> {
         //println!("step vec len: {}", self.step_vec.len());
         if self.step_vec.is_empty() {
             None
         }else{
-            Some(self.step_vec[0].clone())
+            Some(self.step_vec[0])
         }
     }
     pub fn path_cost(&self) -> usize {
         self.path_cost
     }
+    pub fn steps_left(&self) -> usize {
+        self.step_vec.len()
+    }
     pub fn path(&self) -> Vec<Anchor> {
         self.step_vec.clone()
     }
-    pub fn pop_step(&mut self) -> Option<Anchor> {
+    //swaps the next count steps for others, e.g. a way around somebody, along with the cost of the path left
+    pub fn replace_steps(&mut self, count: usize, steps: Vec<Anchor>, cost: usize) {
+        let count = count.min(self.step_vec.len());
+        self.step_vec.splice(0..count, steps);
+        self.path_cost = cost;
+    }
+    //cost is what the step just taken counted for in the path cost
+    pub fn pop_step(&mut self, cost: usize) -> Option<Anchor> {
         if self.step_vec.is_empty() {
             None
         }else{
-            let mut cost = 10;
-            if self.last_step != (usize::max_value(), usize::max_value()) {
-                if self.last_step.0 != self.step_vec[0].pos.0 && self.last_step.1 != self.step_vec[1].pos.1 {
-                    cost = 14;
-                }
-            }
             if self.path_cost >= cost {
                 self.path_cost -= cost;
             }else{
                 self.path_cost = 0;
             }
-            Some(self.step_vec.remove(0))
+            let step = self.step_vec.remove(0);
+            self.last_step = step.local();
+            Some(step)
+        }
+    }
+    //the tile the last step of the current path ended on, none before the first one
+    pub fn last_step(&self) -> Option<(usize, usize)> {
+        if self.last_step == (usize::max_value(), usize::max_value()) {
+            None
+        }else{
+            Some(self.last_step)
         }
     }
+    //a more urgent goal interrupts the one being worked on, which waits its turn or is let go as it says
     pub fn add_goal(&mut self, goal: Goal){
+        let interrupted = match self.pos_goals.peek() {
+            Some(current) if goal.priority < current.priority => Some(current.preemption),
+            _ => None,
+        };
+        self.given_up = None;
+        if let Some(preemption) = interrupted {
+            if preemption == Preemption::Drop {
+                self.given_up = self.pos_goals.pop().map(|g| g.gtype);
+            }
+            self.clear_step_vec();
+        }
         self.pos_goals.push(goal);
     }
+    //ages the goal being worked on and counts down its wait after a failure; one past its timeout is given up
+    pub fn tick_goal(&mut self, dt: f32) {
+        let mut timed_out = false;
+        if let Some(mut goal) = self.pos_goals.peek_mut() {
+            goal.age += dt;
+            goal.wait = (goal.wait - dt).max(0.0);
+            if goal.timeout > 0.0 && goal.age >= goal.timeout {
+                self.given_up = Some(PeekMut::pop(goal).gtype);
+                timed_out = true;
+            }
+        }
+        if timed_out {
+            self.clear_step_vec();
+        }
+    }
+    //the goal being worked on couldn't be reached: it waits longer after every try and is given up after a few
+    pub fn fail_goal(&mut self) {
+        if let Some(mut goal) = self.pos_goals.peek_mut() {
+            goal.failures += 1;
+            goal.wait = GOAL_RETRY_DELAY * 2.0f32.powi(goal.failures as i32 - 1);
+            if goal.failures >= MAX_GOAL_FAILURES {
+                self.given_up = Some(PeekMut::pop(goal).gtype);
+            }
+        }
+        self.clear_step_vec();
+    }
+    //true while the goal being worked on waits to be tried again
+    pub fn is_goal_waiting(&self) -> bool {
+        match self.pos_goals.peek() {
+            Some(g) => g.wait > 0.0,
+            None => false,
+        }
+    }
     pub fn get_goal(&self) -> Option<Anchor> {
         let goal = self.pos_goals.peek();
 
         match goal {
             None => None,
-            Some(g) => Some(g.point.clone()),
+            Some(g) => Some(g.point),
+        }
+    }
+    pub fn get_goal_priority(&self) -> usize {
+        match self.pos_goals.peek() {
+            None => GoalPriority::Size as usize,
+            Some(g) => g.priority,
         }
     }
     pub fn get_goal_type(&self) -> GoalType {
@@ -144,11 +267,27 @@ impl Mover{
         }
         out
     }
+    //true if any goal waiting to be worked on carries out the action
+    pub fn has_goal_for(&self, action: Action) -> bool {
+        self.pos_goals.iter().any(|g| g.gtype.action() == action)
+    }
+    pub fn given_up(&self) -> Option<GoalType> {
+        self.given_up
+    }
+    //lets go of every goal that carries out the action, the walk too if it was headed for one
+    pub fn drop_goals_for(&mut self, action: Action) {
+        if self.get_goal_type().action() == action {
+            self.clear_step_vec();
+        }
+        let goals = std::mem::replace(&mut self.pos_goals, BinaryHeap::new());
+        self.pos_goals = goals.into_iter().filter(|g| g.gtype.action() != action).collect();
+    }
     pub fn pop_goal(&mut self) -> Option<Anchor> {
+        self.given_up = None;
         let goal = self.pos_goals.pop();
         match goal {
             None => None,
-            Some(g) => Some(g.point.clone()),
+            Some(g) => Some(g.point),
         }
     }
          
@@ -156,4 +295,72 @@ impl Mover{
 
 impl Component for Mover{
     type Storage = VecStorage<Self>;
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn goal(gtype: GoalType, priority: GoalPriority, x: usize) -> Goal {
+        Goal::new(priority as usize, Anchor::new(x, 0, (0, 0, 0)), gtype)
+    }
+
+    #[test]
+    fn urgent_goals_requeue_or_drop_the_one_they_interrupt() {
+        let mut mover = Mover::new(1.0);
+        mover.add_goal(goal(GoalType::SimpleIdle, GoalPriority::SimpleIdle, 1));
+        mover.set_step_vec(vec![Anchor::new(1, 0, (0, 0, 0))], 1);
+        mover.add_goal(goal(GoalType::RestGoal, GoalPriority::RestGoal, 2));
+        //the walk to the old goal is no use for the new one
+        assert!(mover.is_step_vec_empty());
+        assert_eq!(mover.get_goal_type(), GoalType::RestGoal);
+        assert_eq!(mover.given_up(), None);
+        mover.pop_goal();
+        assert_eq!(mover.get_goal_type(), GoalType::SimpleIdle);
+
+        //a meal search is let go of once a meal turns up
+        let mut mover = Mover::new(1.0);
+        mover.add_goal(goal(GoalType::MealSearch, GoalPriority::MealSearch, 1));
+        mover.add_goal(goal(GoalType::MealGoal, GoalPriority::MealGoal, 2));
+        assert_eq!(mover.given_up(), Some(GoalType::MealSearch));
+        assert!(!mover.has_goal_type(GoalType::MealSearch));
+        mover.pop_goal();
+        assert_eq!(mover.get_goal(), None);
+
+        //less urgent goals wait behind the current one without disturbing it
+        let mut mover = Mover::new(1.0);
+        mover.add_goal(goal(GoalType::MealSearch, GoalPriority::MealSearch, 1));
+        mover.set_step_vec(vec![Anchor::new(1, 0, (0, 0, 0))], 1);
+        mover.add_goal(goal(GoalType::SimpleIdle, GoalPriority::SimpleIdle, 2));
+        assert!(!mover.is_step_vec_empty());
+        assert_eq!(mover.get_goal_type(), GoalType::MealSearch);
+    }
+
+    #[test]
+    fn failed_goals_wait_longer_each_time_and_are_given_up() {
+        let mut mover = Mover::new(1.0);
+        mover.add_goal(goal(GoalType::RestGoal, GoalPriority::RestGoal, 1));
+        for failure in 1..MAX_GOAL_FAILURES {
+            mover.fail_goal();
+            let wait = GOAL_RETRY_DELAY * 2.0f32.powi(failure as i32 - 1);
+            mover.tick_goal(wait - 0.1);
+            assert!(mover.is_goal_waiting());
+            mover.tick_goal(0.2);
+            assert!(!mover.is_goal_waiting());
+            assert!(mover.has_goal_type(GoalType::RestGoal));
+        }
+        mover.fail_goal();
+        assert_eq!(mover.get_goal(), None);
+        assert_eq!(mover.given_up(), Some(GoalType::RestGoal));
+    }
+
+    #[test]
+    fn goals_past_their_timeout_are_given_up() {
+        let mut mover = Mover::new(1.0);
+        mover.add_goal(goal(GoalType::MealGoal, GoalPriority::MealGoal, 1));
+        mover.tick_goal(GoalType::MealGoal.timeout() - 1.0);
+        assert!(mover.has_goal_type(GoalType::MealGoal));
+        mover.tick_goal(1.0);
+        assert_eq!(mover.get_goal(), None);
+        assert_eq!(mover.given_up(), Some(GoalType::MealGoal));
+    }
+}
@@ -0,0 +1,245 @@
+use amethyst::ecs::prelude::{Component, VecStorage};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+use crate::components::Id;
+use crate::game_state::Action;
+
+//how pressing a need is at a given level; levels and urgencies both run from 0 to 1
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub enum Curve {
+    Linear,
+    Power(f32), //quiet while the need is mostly met, rising steeply as it runs out
+    Logistic(f32, f32), //steepness, and the level at which the need is half urgent
+    Threshold(f32), //fully urgent below the level, not at all above it
+}
+
+impl Curve {
+    pub fn urgency(self, level: f32) -> f32 {
+        let lack = 1.0 - level.max(0.0).min(1.0);
+        match self {
+            Curve::Linear => lack,
+            Curve::Power(p) => lack.powf(p),
+            Curve::Logistic(steepness, mid) => 1.0 / (1.0 + (steepness * (level - mid)).exp()),
+            Curve::Threshold(t) => if level < t { 1.0 } else { 0.0 },
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct NeedDef {
+    pub name: String,
+    pub capacity: f32, //in seconds till empty at a rate of 1
+    pub rate: f32, //per second
+    #[serde(default)]
+    pub offscreen_relief: f32, //chance per second offscreen of the need being met
+    pub curve: Curve,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ActionDef {
+    pub action: Action,
+    #[serde(default)]
+    pub base: f32, //score with every need met
+    #[serde(default)]
+    pub serves: Vec<(String, f32)>, //needs the action meets, weighing how much each one counts
+}
+
+//every need a person has and the actions that meet them, as loaded from needs.ron
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct NeedSet {
+    pub needs: Vec<NeedDef>,
+    pub actions: Vec<ActionDef>,
+}
+
+impl NeedSet {
+    //needs have to hold something, and every need an action serves has to be defined or it would never count
+    //towards the action
+    pub fn validate(&self) -> Result<(), String> {
+        for def in self.needs.iter() {
+            if def.capacity <= 0.0 {
+                return Err(format!("need {} has no capacity", def.name));
+            }
+        }
+        for def in self.actions.iter() {
+            for (name, _) in def.serves.iter() {
+                if self.get(name).is_none() {
+                    return Err(format!("action {:?} serves unknown need {}", def.action, name));
+                }
+            }
+        }
+        Ok(())
+    }
+    pub fn get(&self, name: &str) -> Option<&NeedDef> {
+        self.needs.iter().find(|n| n.name == name)
+    }
+    //needs for somebody new, each somewhere between half and fully met
+    pub fn spawn<R: Rng>(&self, rng: &mut R) -> Needs {
+        Needs {
+            needs: self.needs.iter().map(|def| Need {
+                name: def.name.clone(),
+                capacity: def.capacity,
+                rate: def.rate,
+                current: (rng.gen::<f32>() * 0.5 + 0.5) * def.capacity,
+            }).collect(),
+            target: Id::nil(),
+        }
+    }
+    //an action's base score plus the weighted urgency of every need it meets
+    pub fn score(&self, def: &ActionDef, needs: &Needs) -> f32 {
+        let mut score = def.base;
+        for (name, weight) in def.serves.iter() {
+            if let Some(need) = self.get(name) {
+                score += weight * need.curve.urgency(needs.level(name));
+            }
+        }
+        score
+    }
+    //the best scoring action of those allowed, if any are
+    pub fn choose<F: Fn(Action) -> bool>(&self, needs: &Needs, allowed: F) -> Option<Action> {
+        let mut best: Option<(Action, f32)> = None;
+        for def in self.actions.iter().filter(|d| allowed(d.action)) {
+            let score = self.score(def, needs);
+            match best {
+                Some((_, s)) if s >= score => (),
+                _ => best = Some((def.action, score)),
+            }
+        }
+        best.map(|(a, _)| a)
+    }
+    //names of the needs an action meets
+    pub fn served(&self, action: Action) -> Vec<&str> {
+        self.actions.iter()
+            .filter(|d| d.action == action)
+            .flat_map(|d| d.serves.iter().map(|(name, _)| name.as_str()))
+            .collect()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Need {
+    name: String,
+    capacity: f32,
+    rate: f32, //per second
+    current: f32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Needs {
+    needs: Vec<Need>,
+    target: Id, //what the action under way is about, e.g. the plant to eat from
+}
+
+impl Needs {
+    //how well met a need is, from 0 to 1; needs this person doesn't have are always met
+    pub fn level(&self, name: &str) -> f32 {
+        match self.needs.iter().find(|n| n.name == name) {
+            Some(n) if n.capacity > 0.0 => n.current / n.capacity,
+            _ => 1.0,
+        }
+    }
+    //runs every need down by its rate
+    pub fn decay(&mut self, dt: f32) {
+        for need in self.needs.iter_mut() {
+            need.current = (need.current - need.rate * dt).max(0.0);
+        }
+    }
+    pub fn fill(&mut self, name: &str) {
+        if let Some(need) = self.needs.iter_mut().find(|n| n.name == name) {
+            need.current = need.capacity;
+        }
+    }
+    //fills every need the action meets
+    pub fn satisfy(&mut self, set: &NeedSet, action: Action) {
+        for name in set.served(action) {
+            self.fill(name);
+        }
+    }
+    pub fn names(&self) -> Vec<String> {
+        self.needs.iter().map(|n| n.name.clone()).collect()
+    }
+    pub fn set_target(&mut self, id: Id) {
+        self.target = id;
+    }
+    pub fn get_target(&self) -> Id {
+        self.target
+    }
+}
+
+impl Component for Needs {
+    type Storage = VecStorage<Self>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn need(name: &str, curve: Curve) -> NeedDef {
+        NeedDef {
+            name: name.to_string(),
+            capacity: 100.0,
+            rate: 1.0,
+            offscreen_relief: 0.0,
+            curve,
+        }
+    }
+
+    fn action(action: Action, base: f32, serves: &[(&str, f32)]) -> ActionDef {
+        ActionDef {
+            action,
+            base,
+            serves: serves.iter().map(|(n, w)| (n.to_string(), *w)).collect(),
+        }
+    }
+
+    fn set() -> NeedSet {
+        NeedSet {
+            needs: vec![need("hunger", Curve::Linear), need("rest", Curve::Threshold(0.3))],
+            actions: vec![action(Action::Eat, 0.0, &[("hunger", 1.0)]), action(Action::Rest, 0.0, &[("rest", 1.0)]), action(Action::Wander, 0.2, &[])],
+        }
+    }
+
+    fn needs(hunger: f32, rest: f32) -> Needs {
+        Needs {
+            needs: vec![
+                Need { name: "hunger".to_string(), capacity: 100.0, rate: 1.0, current: hunger * 100.0 },
+                Need { name: "rest".to_string(), capacity: 100.0, rate: 1.0, current: rest * 100.0 },
+            ],
+            target: Id::nil(),
+        }
+    }
+
+    #[test]
+    fn curves_run_from_met_to_urgent() {
+        for curve in [Curve::Linear, Curve::Power(2.0), Curve::Threshold(0.5)].iter() {
+            assert_eq!(curve.urgency(1.0), 0.0);
+            assert_eq!(curve.urgency(0.0), 1.0);
+        }
+        assert!((Curve::Linear.urgency(0.25) - 0.75).abs() < 1e-6);
+        assert!((Curve::Power(2.0).urgency(0.5) - 0.25).abs() < 1e-6);
+        assert!((Curve::Logistic(10.0, 0.4).urgency(0.4) - 0.5).abs() < 1e-6);
+        assert!(Curve::Logistic(10.0, 0.4).urgency(0.1) > Curve::Logistic(10.0, 0.4).urgency(0.7));
+        assert_eq!(Curve::Threshold(0.5).urgency(0.49), 1.0);
+        assert_eq!(Curve::Threshold(0.5).urgency(0.5), 0.0);
+        //levels out of range count as the nearest end
+        assert_eq!(Curve::Linear.urgency(1.5), 0.0);
+    }
+
+    #[test]
+    fn choose_picks_the_most_pressing_allowed_action() {
+        let set = set();
+        assert_eq!(set.choose(&needs(1.0, 1.0), |_| true), Some(Action::Wander));
+        assert_eq!(set.choose(&needs(0.3, 1.0), |_| true), Some(Action::Eat));
+        assert_eq!(set.choose(&needs(0.3, 0.2), |_| true), Some(Action::Rest));
+        assert_eq!(set.choose(&needs(0.3, 0.2), |a| a != Action::Rest), Some(Action::Eat));
+        assert_eq!(set.choose(&needs(0.3, 0.2), |_| false), None);
+    }
+
+    #[test]
+    fn validate_rejects_unknown_needs() {
+        assert!(set().validate().is_ok());
+        let mut set = set();
+        set.actions.push(action(Action::Socialize, 0.0, &[("company", 1.0)]));
+        assert!(set.validate().is_err());
+    }
+}
@@ -1,5 +1,7 @@
 use amethyst::ecs::prelude::{Component, VecStorage};
+use serde::{Serialize, Deserialize};
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Offscreen {
     offscreen_time: f32,    
 }
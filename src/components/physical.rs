@@ -1,24 +1,32 @@
 use amethyst::ecs::prelude::{Component, VecStorage};
 use crate::game_state::TILE_SIZE;
+use serde::{Serialize, Deserialize};
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Physical{
     real_pos: (f32, f32),
-    area_pos: (i32, i32),
+    area_pos: (i32, i32, i32),
     offset: (f32, f32),
+    //radians, 0 facing east and turning anticlockwise, for picking animation frames
+    #[serde(default)]
+    heading: f32,
 }
 
 impl Physical{
-    pub fn new(real_pos: (f32, f32), area_pos: (i32, i32)) -> Physical {
+    pub fn new(real_pos: (f32, f32), area_pos: (i32, i32, i32)) -> Physical {
         Physical {
             real_pos,
             area_pos,
             offset: (0.0, 0.0),
+            heading: 0.0,
         }
     }
-    pub fn get_location(&self) -> (i32, i32) {
+    pub fn get_location(&self) -> (i32, i32, i32) {
         self.area_pos
     }
+    pub fn get_layer(&self) -> i32 {
+        self.area_pos.2
+    }
     pub fn mut_area_x(&mut self, i: i32) {
         self.area_pos.0 += i;
     }
@@ -31,6 +39,15 @@ impl Physical{
     pub fn set_area_y(&mut self, y: i32) {
         self.area_pos.1 = y;
     }
+    pub fn set_layer(&mut self, layer: i32) {
+        self.area_pos.2 = layer;
+    }
+    pub fn get_heading(&self) -> f32 {
+        self.heading
+    }
+    pub fn set_heading(&mut self, heading: f32) {
+        self.heading = heading;
+    }
     pub fn get_real_position(&self) -> (f32, f32) {
         self.real_pos
     }
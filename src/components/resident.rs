@@ -0,0 +1,29 @@
+use amethyst::ecs::prelude::{Component, VecStorage};
+use serde::{Serialize, Deserialize};
+
+//marks a person as living in the settlement planned for a region
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Resident {
+    settlement: (i32, i32),
+    #[serde(default)]
+    areas: Vec<(i32, i32)>, //the areas the settlement reaches into, planned once when the resident is spawned
+}
+
+impl Resident {
+    pub fn new(settlement: (i32, i32), areas: Vec<(i32, i32)>) -> Resident {
+        Resident {
+            settlement,
+            areas,
+        }
+    }
+    pub fn get_settlement(&self) -> (i32, i32) {
+        self.settlement
+    }
+    pub fn get_areas(&self) -> &[(i32, i32)] {
+        &self.areas
+    }
+}
+
+impl Component for Resident {
+    type Storage = VecStorage<Self>;
+}
@@ -7,20 +7,21 @@ use crate::components;
 use std::thread;
 use std::thread::JoinHandle;
 use std::fs;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::cmp::Ordering as CmpOrdering;
 
 use std::f32::consts::PI;
 
-use rand::Rng;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
 
 use std::sync::{Arc};
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 
-use serde::Deserialize;
+use serde::{Serialize, Deserialize};
 use ron::de::from_str;
 
-use noise::{Seedable, NoiseFn, Perlin, Billow};
-
 use amethyst::ecs::prelude::{Read, Entity, Entities, WriteStorage};
 use amethyst::{
     assets::{AssetStorage, Handle, Loader},
@@ -33,17 +34,23 @@ use amethyst::{
 };
 
 
-use crate::components::{Id, Particle, ParticleDeathType};
+use crate::components::{Id, Particle, ParticleDeathType, NeedSet, BehaviourSet};
+use crate::worldgen::{GenContext, NavGraph, DiagonalPolicy, Portal, PassGenerator, WorldGenerator, BiomeSet, LandmarkSet, TiledPass, stairs_down};
+use crate::save::EntitySave;
 
 pub const PLAYER_WIDTH: usize = 1;
 pub const PLAYER_HEIGHT: usize = 1;
 pub const PLAYER_SPEED: f32 = 120.0;
 
-pub const PERSON_NUM: u32 = 25;
+pub const PERSON_WIDTH: usize = 1; //footprint in tiles, like the player's
+pub const PERSON_HEIGHT: usize = 1;
+pub const SEPARATION_SPEED: f32 = 30.0; //how quickly overlapping people drift apart, in pixels per second
+pub const AVOID_WAIT_TIME: f32 = 0.75; //in seconds a blocked mover waits before walking around whoever is in the way
+pub const DETOUR_LOOKAHEAD: usize = 4; //steps along the path a detour rejoins it
+pub const WAYPOINT_RADIUS: f32 = 4.0; //in pixels, how close to a waypoint counts as having passed it
+pub const ARRIVAL_RADIUS: f32 = 0.5; //in pixels, the same for the end of a path
 
-pub const DEFAULT_HUNGER_RATE: f32 = 1.0; 
-pub const DEFAULT_HUNGER_CAPACITY: f32 = 60.0 * 10.0; //in seconds / DEFAULT_HUNGER_RATE till starving
-pub const BASE_OFFSCREEN_HUNGER_RELIEF_CHANCE: f32 = 0.001; //chance per second offscreen
+pub const PERSON_NUM: u32 = 25;
 
 pub const PLANT_NUM_LOWER: usize = 15;
 pub const PLANT_NUM_UPPER: usize = 20;
@@ -60,15 +67,54 @@ pub const ADJUSTMENT_ZOOM_FACTOR: f64 = 0.25;
 pub const NOISE_DISPLACEMENT: f64 = 0.5;
 
 pub const OFFSCREEN_UNKNOWN_PATH_WAIT_TIME: f32 = 10.0 * 100.0 / DEFAULT_BASE_SPEED; //in seconds
+pub const PATH_BUDGET: f32 = 0.002; //seconds of pathfinding per frame, at least one path always gets found
+pub const MEAL_GOAL_TIMEOUT: f32 = 60.0; //in seconds spent on a goal before giving it up
+pub const MEAL_SEARCH_TIMEOUT: f32 = 120.0;
+pub const IDLE_GOAL_TIMEOUT: f32 = 90.0;
+pub const GOAL_RETRY_DELAY: f32 = 2.0; //in seconds after a goal couldn't be reached, doubling with every failure
+pub const MAX_GOAL_FAILURES: u32 = 3; //failed tries before a goal is given up
 
 pub const STRUCTURE_RESOLUTION_FACTOR: f32 = 0.25;
 pub const BIOME_RESOLUTION_FACTOR: f32 = 0.1;
 
-pub const BIOME_NUM: u32 = 2;
-pub const BIOME_TILESET_SIZE: u32 = 5;
+pub const SETTLEMENT_REGION_AREAS: i32 = 3; //settlement regions are this many areas across
+pub const SETTLEMENT_CHANCE: f32 = 0.4;
+pub const SETTLEMENT_RADIUS_LOWER: i64 = 18; //in tiles
+pub const SETTLEMENT_RADIUS_UPPER: i64 = 34;
+pub const ROAD_EDGE_CHANCE: f32 = 0.5; //chance a road crosses any one area edge
+pub const LAKE_RESOLUTION_FACTOR: f64 = 0.8; //basin noise periods per area
+pub const LAKE_LEVEL: f64 = 0.35; //basin depth, less elevation, past which the ground is flooded
+pub const SHORE_DEPTH: f64 = 0.1; //band of shallow water around lakes
+pub const RIVER_SPACING: i64 = 16; //in tiles, each square this size may hold one river source
+pub const RIVER_SOURCE_CHANCE: f32 = 0.15;
+pub const RIVER_SOURCE_LEVEL: f64 = 0.45; //rivers rise above this elevation
+pub const RIVER_STEP: f64 = 3.0; //in tiles a river runs between looking for lower ground
+pub const RIVER_LENGTH: usize = 80; //steps before a river that hasn't found a lake or a hollow gives out
+pub const RIVER_WIDTH: f64 = 5.0; //in tiles, once it has run its full length
+pub const CAVE_DEPTH: i32 = 2; //layers of caves below the surface
+pub const CAVE_ENTRANCE_CHANCE: f32 = 0.3; //per area and layer
+pub const CAVE_ENTRANCE_TRIES: usize = 8; //spots looked at for an entrance on the surface before giving up
+pub const CAVE_FILL: f64 = 0.0; //noise above which cave tiles start out as rock
+pub const CAVE_SMOOTHING_STEPS: usize = 4;
+
 
 pub const MAP_SEED_RANGE: i32 = i32::max_value();
 
+pub const SAVE_PATH: &str = "saves/world.ron";
+pub const EXPORT_DIR: &str = "res/maps/export";
+
+#[derive(Debug, Clone, Copy)]
+pub enum RngStream {
+    Structures = 0,
+    Spawn,
+    Settlements,
+    RoadsEast,
+    RoadsNorth,
+    Caves,
+    Rivers,
+    Size,
+}
+
 #[derive(Debug, Clone, Copy, FromPrimitive)]
 pub enum KeyCheck {
     Enter = 0,
@@ -86,7 +132,7 @@ pub enum SpellComponent {
     Size,
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum EntityType {
     Plant,
     Person,
@@ -112,7 +158,7 @@ impl UiState {
     }
 }
 
-#[derive(Clone, Copy, FromPrimitive, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, FromPrimitive, Debug, Serialize, Deserialize)]
 pub enum Tile {
     Plain = 0,
     Grassy,
@@ -126,10 +172,57 @@ pub enum Tile {
     SandyBoulder,
     WoodFloor,
     WoodWall,
+    Road,
+    Path,
+    DeepWater,
+    ShallowWater,
+    Bridge,
+    CaveFloor,
+    CaveWall,
+    StairsDown,
+    StairsUp,
     Size,
 }
 
-#[derive(Clone, Copy, Debug)]
+impl Tile {
+    pub fn default_passable(&self) -> bool {
+        match self {
+            Tile::WoodWall | Tile::DeepWater | Tile::CaveWall => false,
+            _ => true,
+        }
+    }
+    //cost of stepping onto the tile, 10 being open ground; both path costs and walking speed scale with it.
+    //nothing is cheaper than a road, route estimates count on that
+    pub fn move_cost(&self) -> usize {
+        match self {
+            Tile::Road | Tile::Bridge => 6,
+            Tile::Path => 8,
+            Tile::WoodFloor => 9,
+            Tile::Grassy | Tile::SandySparse => 11,
+            Tile::RockyLight | Tile::CaveFloor => 12,
+            Tile::Sandy | Tile::SandyWeed => 13,
+            Tile::GrassyHeavy => 14,
+            Tile::Rocky | Tile::StairsDown | Tile::StairsUp => 15,
+            Tile::SandyRocky => 16,
+            Tile::SandyBoulder => 18,
+            Tile::ShallowWater => 20,
+            _ => 10,
+        }
+    }
+    //cost of one step onto the tile, straight or diagonal
+    pub fn step_cost(&self, diagonal: bool) -> usize {
+        let base = if diagonal { 14 } else { 10 };
+        base * self.move_cost() / 10
+    }
+    pub fn is_water(&self) -> bool {
+        match self {
+            Tile::DeepWater | Tile::ShallowWater => true,
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct TileBlock {
     pub tile: Tile,
     pub passable: bool,
@@ -148,33 +241,102 @@ impl TileBlock {
 pub enum GoalPriority {
     MealGoal,
     MealSearch,
+    RestGoal,
+    SocialGoal,
     SimpleIdle,
     Size,
 }
 
-#[derive(PartialEq, Eq, Clone, Copy, FromPrimitive, Debug)]
+#[derive(PartialEq, Eq, Clone, Copy, FromPrimitive, Debug, Serialize, Deserialize)]
 pub enum GoalType {
     MealGoal,
     MealSearch,
+    RestGoal,
+    SocialGoal,
     SimpleIdle,
     Size,
 }
 
-#[derive(Eq, Debug, Hash)]
+//what a person can decide to do about their needs, each carried out through goals
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Action {
+    Eat,
+    Rest,
+    Wander,
+    Socialize,
+    Size,
+}
+
+impl Action {
+    //the least urgent goal the action sets, which is what it has to outrank to interrupt another
+    pub fn priority(self) -> usize {
+        match self {
+            Action::Eat => GoalPriority::MealSearch as usize,
+            Action::Rest => GoalPriority::RestGoal as usize,
+            Action::Socialize => GoalPriority::SocialGoal as usize,
+            Action::Wander => GoalPriority::SimpleIdle as usize,
+            Action::Size => GoalPriority::Size as usize,
+        }
+    }
+}
+
+impl GoalType {
+    //the action a goal of this type is part of
+    pub fn action(self) -> Action {
+        match self {
+            GoalType::MealGoal | GoalType::MealSearch => Action::Eat,
+            GoalType::RestGoal => Action::Rest,
+            GoalType::SocialGoal => Action::Socialize,
+            GoalType::SimpleIdle => Action::Wander,
+            GoalType::Size => Action::Size,
+        }
+    }
+    //what a goal of this type does when something more urgent comes along
+    pub fn preemption(self) -> Preemption {
+        match self {
+            //a meal search is only ever interrupted by the meal it was looking for
+            GoalType::MealSearch => Preemption::Drop,
+            _ => Preemption::Requeue,
+        }
+    }
+    //seconds a goal of this type is worked on before it's given up
+    pub fn timeout(self) -> f32 {
+        match self {
+            GoalType::MealGoal => MEAL_GOAL_TIMEOUT,
+            GoalType::MealSearch => MEAL_SEARCH_TIMEOUT,
+            _ => IDLE_GOAL_TIMEOUT,
+        }
+    }
+}
+
+//what happens to a goal when a more urgent one interrupts it
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Serialize, Deserialize)]
+pub enum Preemption {
+    Requeue, //taken up again once the more urgent one is done
+    Drop,
+    Size,
+}
+
+impl Default for Preemption {
+    fn default() -> Self {
+        Preemption::Requeue
+    }
+}
+
+//a tile position in some area; the out of bounds positions just past each edge stand for leaving the area
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Hash, Serialize, Deserialize)]
 pub struct Anchor{
-    pub pos: (usize, usize, i32, i32),
-    pub succ: Vec<(usize, usize)>,
+    pub pos: (usize, usize, i32, i32, i32),
 }
 
 impl Anchor {
-    pub fn new(x: usize, y: usize, lx: i32, ly: i32) -> Anchor {
+    pub fn new(x: usize, y: usize, (lx, ly, layer): (i32, i32, i32)) -> Anchor {
         Anchor {
-            pos: (x, y, lx, ly),
-            succ: Vec::new(),
+            pos: (x, y, lx, ly, layer),
         }
     }
     pub fn real_local(&self) -> (f32, f32) {
-        let (x, y, _, _) = self.pos;
+        let (x, y, _, _, _) = self.pos;
         let mut nx = (TILE_SIZE / 2 + x * TILE_SIZE) as f32;
         if x == usize::max_value() {
             nx = (-(TILE_SIZE as i32) / 2) as f32; 
@@ -188,62 +350,126 @@ impl Anchor {
     pub fn local(&self) -> (usize, usize) {
         (self.pos.0, self.pos.1)
     }
-    pub fn area(&self) -> (i32, i32) {
-        (self.pos.2, self.pos.3)
+    pub fn area(&self) -> (i32, i32, i32) {
+        (self.pos.2, self.pos.3, self.pos.4)
     }
-    pub fn set_area(&mut self, new_area: (i32, i32)) {
+    pub fn set_area(&mut self, new_area: (i32, i32, i32)) {
         self.pos.2 = new_area.0;
         self.pos.3 = new_area.1;
+        self.pos.4 = new_area.2;
     }
 }
 
-impl Clone for Anchor {
-    fn clone(&self) -> Anchor {
-        Anchor {
-            pos: self.pos,
-            succ: self.succ.to_vec(),
-        }
-    }
+//where a hand authored area wants a person or plant
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct SpawnPoint {
+    pub pos: (usize, usize),
+    pub etype: EntityType,
+    #[serde(default)]
+    pub behaviour: Option<String>, //tree for the person spawned here, instead of the default one
 }
 
-impl PartialEq for Anchor {
-    fn eq(&self, other: &Self) -> bool {
-        self.pos == other.pos
+impl SpawnPoint {
+    pub fn new(pos: (usize, usize), etype: EntityType) -> SpawnPoint {
+        SpawnPoint {
+            pos,
+            etype,
+            behaviour: None,
+        }
     }
 }
 
-#[derive(Default, Clone)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct Area{
     pub tiles: Vec<TileBlock>,
-    pub anchor_points: Vec<Anchor>,
+    pub nav: NavGraph,
+    #[serde(default)]
+    pub portals: Vec<Portal>,
     pub structures: Vec<Rect>,
     pub spawned: bool,
-    pub n: usize,
-    pub e: usize,
-    pub w: usize,
-    pub s: usize,
-    pub nw: usize,
-    pub ne: usize,
-    pub sw: usize,
-    pub se: usize,
+    #[serde(default)]
+    pub biomes: Vec<usize>,
+    #[serde(default)]
+    pub spawn_points: Vec<SpawnPoint>,
+    #[serde(default)]
+    pub settlement: Option<(i32, i32)>,
 }
 
 impl Area{
     pub fn new() -> Area{
         Area {
             tiles: Vec::new(),
-            anchor_points: Vec::new(),
+            biomes: Vec::new(),
+            spawn_points: Vec::new(),
+            nav: NavGraph::default(),
+            portals: Vec::new(),
             structures: Vec::new(),
             spawned: false,
-            n: usize::max_value(),
-            e: usize::max_value(),
-            w: usize::max_value(),
-            s: usize::max_value(),
-            nw: usize::max_value(),
-            ne: usize::max_value(),
-            sw: usize::max_value(),
-            se: usize::max_value(),
+            settlement: None,
+        }
+    }
+}
+
+//area coordinate offset for a direction, y going north
+pub fn dir_offset(dir: char) -> (i32, i32) {
+    match dir {
+        'n' => (0, 1),
+        'e' => (1, 0),
+        's' => (0, -1),
+        'w' => (-1, 0),
+        _ => (0, 0),
+    }
+}
+
+//every explored area keyed by its coordinate and layer; a coordinate only ever holds one area
+#[derive(Default, Clone, Serialize, Deserialize)]
+pub struct WorldMap {
+    areas: BTreeMap<(i32, i32, i32), Area>,
+}
+
+impl WorldMap {
+    pub fn new() -> WorldMap {
+        WorldMap {
+            areas: BTreeMap::new(),
+        }
+    }
+    pub fn get(&self, location: (i32, i32, i32)) -> Option<&Area> {
+        self.areas.get(&location)
+    }
+    pub fn get_mut(&mut self, location: (i32, i32, i32)) -> Option<&mut Area> {
+        self.areas.get_mut(&location)
+    }
+    pub fn contains(&self, location: (i32, i32, i32)) -> bool {
+        self.areas.contains_key(&location)
+    }
+    //keeps the area already stored at the location, if any, and returns whichever one is stored
+    pub fn insert(&mut self, location: (i32, i32, i32), area: Area) -> &mut Area {
+        self.areas.entry(location).or_insert(area)
+    }
+    pub fn neighbour(&self, location: (i32, i32, i32), offset: (i32, i32)) -> Option<&Area> {
+        self.get((location.0 + offset.0, location.1 + offset.1, location.2))
+    }
+    //all eight surrounding areas on the same layer that have been generated
+    pub fn neighbours(&self, location: (i32, i32, i32)) -> Vec<((i32, i32, i32), &Area)> {
+        let mut out = Vec::new();
+        for dy in -1..2 {
+            for dx in -1..2 {
+                if dx == 0 && dy == 0 {
+                    continue;
+                }
+                let coord = (location.0 + dx, location.1 + dy, location.2);
+                if let Some(area) = self.get(coord) {
+                    out.push((coord, area));
+                }
+            }
         }
+        out
+    }
+    pub fn len(&self) -> usize {
+        self.areas.len()
+    }
+    pub fn iter(&self) -> impl Iterator<Item = (&(i32, i32, i32), &Area)> {
+        self.areas.iter()
     }
 }
 
@@ -252,14 +478,16 @@ pub struct Map{
     pub width: usize, 
     pub height: usize,
     pub world_seed: (f64, f64, f64, f64, f64, f64, f64, f64), // 0-4 tile-wise noise, 5-6 biome area-wise noise, 
-    pub location: (i32, i32),
+    pub location: (i32, i32, i32), //x, y and layer, 0 being the surface and caves below it
     pub tiles: Vec<TileBlock>,
-    pub anchor_points: Vec<Anchor>,
+    pub biomes: Vec<usize>,
+    pub nav: NavGraph,
     pub structures: Vec<Rect>,
+    pub spawn_points: Vec<SpawnPoint>,
+    pub settlement: Option<(i32, i32)>,
     pub spawned: bool,
     pub entities: Vec<Id>,
-    pub world_map: Vec<Area>,
-    pub area_index: usize,
+    pub world_map: WorldMap,
     pub rerolled: bool,
 }
 
@@ -269,14 +497,16 @@ impl Map {
             width,
             height, 
             world_seed: (0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0),
-            location: (0, 0),
+            location: (0, 0, 0),
             tiles: vec![TileBlock::new(Tile::Size, true); width * height],
-            anchor_points: Vec::new(),
+            biomes: vec![0; width * height],
+            nav: NavGraph::default(),
             structures: Vec::new(),
+            spawn_points: Vec::new(),
+            settlement: None,
             spawned: false,
             entities: vec![Id::nil(); width * height],
-            world_map: Vec::new(),
-            area_index: 0,
+            world_map: WorldMap::new(),
             rerolled: false,
         }   
     }
@@ -288,6 +518,101 @@ impl Map {
             true
         }
     }
+    //out of bounds tiles cost the same as open ground
+    pub fn move_cost(&self, tile: (usize, usize)) -> usize {
+        if tile.0 < self.width && tile.1 < self.height {
+            self.tiles[tile.0 + tile.1 * self.width].tile.move_cost()
+        }else{
+            10
+        }
+    }
+    //the path cost of walking from one tile to another, as the navigation graph counts it for neighbours;
+    //further apart, it's the straight and diagonal steps between them at the cost of the tile walked onto
+    pub fn step_cost(&self, from: (usize, usize), to: (usize, usize)) -> usize {
+        if to.0 >= self.width || to.1 >= self.height {
+            return 10;
+        }
+        let dx = if from.0 > to.0 { from.0 - to.0 } else { to.0 - from.0 };
+        let dy = if from.1 > to.1 { from.1 - to.1 } else { to.1 - from.1 };
+        let tile = self.tiles[to.0 + to.1 * self.width].tile;
+        let diagonal = dx.min(dy);
+        (dx.max(dy) - diagonal) * tile.step_cost(false) + diagonal * tile.step_cost(true)
+    }
+    pub fn biome_at(&self, tile: (usize, usize)) -> usize {
+        let index = tile.0 + tile.1 * self.width;
+        if index < self.biomes.len() {
+            self.biomes[index]
+        }else{
+            0
+        }
+    }
+}
+
+//a path an entity is waiting on, from the tile it stood on when it asked
+#[derive(Clone, Copy, Debug)]
+pub struct PathRequest {
+    pub entity: Entity,
+    pub priority: usize,
+    pub location: (i32, i32, i32),
+    pub start: (usize, usize),
+    pub goal: Anchor,
+    order: usize,
+}
+
+//most urgent first, the same way goals are ordered, and first come first served after that
+impl Ord for PathRequest {
+    fn cmp(&self, other: &PathRequest) -> CmpOrdering {
+        other.priority.cmp(&self.priority)
+            .then_with(|| other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for PathRequest {
+    fn partial_cmp(&self, other: &PathRequest) -> Option<CmpOrdering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for PathRequest {
+    fn eq(&self, other: &PathRequest) -> bool {
+        self.order == other.order
+    }
+}
+
+impl Eq for PathRequest {}
+
+//paths waiting to be found; systems submit requests and PathfindingSystem works through them a few per frame
+#[derive(Default)]
+pub struct PathQueue {
+    requests: BinaryHeap<PathRequest>,
+    submitted: usize,
+}
+
+impl PathQueue {
+    //an entity only waits on one path; asking again replaces what it asked for before
+    pub fn submit(&mut self, entity: Entity, priority: usize, location: (i32, i32, i32), start: (usize, usize), goal: Anchor) {
+        if self.requests.iter().any(|r| r.entity == entity) {
+            self.requests = self.requests.drain().filter(|r| r.entity != entity).collect();
+        }
+        self.requests.push(PathRequest {
+            entity,
+            priority,
+            location,
+            start,
+            goal,
+            order: self.submitted,
+        });
+        self.submitted += 1;
+    }
+    pub fn pop(&mut self) -> Option<PathRequest> {
+        self.requests.pop()
+    }
+    pub fn len(&self) -> usize {
+        self.requests.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.requests.is_empty()
+    }
 }
 
 #[derive(PartialEq, Copy, Clone, Debug)]
@@ -418,7 +743,7 @@ impl Dimensions {
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Rect {
     pub x: usize,
     pub y: usize,
@@ -488,6 +813,21 @@ impl Rect {
     pub fn center(&self) -> (usize, usize) {
         (self.x + self.w / 2, self.y + self.h / 2)
     }
+
+    //edge tiles without the corners, i.e. everywhere a door can go
+    pub fn perimeter(&self) -> Vec<(usize, usize)> {
+        let mut perimeter = Vec::new();
+        for y in self.y..(self.y + self.h) {
+            for x in self.x..(self.x + self.w) {
+                let edge_x = x == self.x || x == self.x + self.w - 1;
+                let edge_y = y == self.y || y == self.y + self.h - 1;
+                if (edge_x || edge_y) && !(edge_x && edge_y) {
+                    perimeter.push((x, y));
+                }
+            }
+        }
+        perimeter
+    }
 }
 
 #[derive(Clone, Debug, Deserialize, Default)]
@@ -497,11 +837,76 @@ pub struct Config{
     pub spritesheet_name: String,
     pub fullscreen: bool,
     pub fps_limit: u32,
+    #[serde(default)]
+    pub world_seed: Option<u64>,
+    #[serde(default)]
+    pub diagonal_policy: DiagonalPolicy, //whether people may step diagonally past wall corners
+}
+
+//seeded source of randomness for everything that shapes the world; ChaCha8 is named outright since StdRng may
+//change algorithm between rand releases and reroll every world
+#[derive(Clone, Debug)]
+pub struct WorldRng {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl WorldRng {
+    pub fn new(seed: u64) -> WorldRng {
+        WorldRng {
+            seed,
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+    pub fn rng(&mut self) -> &mut ChaCha8Rng {
+        &mut self.rng
+    }
+    //an area's rng only depends on the world seed and the area location, so areas come out the same whatever order they are visited in
+    pub fn area_rng(&self, location: (i32, i32), stream: RngStream) -> ChaCha8Rng {
+        let mut h = mix_seed(self.seed);
+        h = mix_seed(h ^ (location.0 as u32 as u64));
+        h = mix_seed(h ^ ((location.1 as u32 as u64) << 32));
+        h = mix_seed(h ^ stream as u64);
+        ChaCha8Rng::seed_from_u64(h)
+    }
+    //the same as area_rng on the surface, with the layer mixed in below it
+    pub fn layer_rng(&self, (x, y, layer): (i32, i32, i32), stream: RngStream) -> ChaCha8Rng {
+        if layer == 0 {
+            return self.area_rng((x, y), stream);
+        }
+        let mut h = mix_seed(self.seed ^ ((layer as u32 as u64) << 16));
+        h = mix_seed(h ^ (x as u32 as u64));
+        h = mix_seed(h ^ ((y as u32 as u64) << 32));
+        h = mix_seed(h ^ stream as u64);
+        ChaCha8Rng::seed_from_u64(h)
+    }
+}
+
+impl Default for WorldRng {
+    fn default() -> Self {
+        WorldRng::new(0)
+    }
+}
+
+//splitmix64 finalizer
+fn mix_seed(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
 }
 
 #[derive(Default)]
 pub struct LoadingState{
     pub config_path: String,
+    pub biomes_path: String,
+    pub landmarks_path: String,
+    pub needs_path: String,
+    pub behaviours_path: String,
+    pub seed_override: Option<u64>,
     pub loading: Arc<AtomicBool>,
     pub load_thread: Option<JoinHandle<(Config)>>,
     sprite_sheet_handle: Option<Handle<SpriteSheet>>
@@ -512,533 +917,90 @@ pub struct PlayState{
     //sprite_sheet_handle: Option<Handle<SpriteSheet>>,
 }
 
-pub fn update_world_seed(map: &mut Map, dir: char){
-    match dir {
-        'n' => {
-            map.world_seed.1 += 1.0 / ZOOM_FACTOR;
-            map.world_seed.3 += 1.0 / ADJUSTMENT_ZOOM_FACTOR;
-            map.location.1 += 1;
-        }
-        'w' => {
-            map.world_seed.0 -= 1.0 / ZOOM_FACTOR;
-            map.world_seed.2 -= 1.0 / ADJUSTMENT_ZOOM_FACTOR;
-            map.location.0 -= 1;
-        }
-        'e' => {
-            map.world_seed.0 += 1.0 / ZOOM_FACTOR;
-            map.world_seed.2 += 1.0 / ADJUSTMENT_ZOOM_FACTOR;
-            map.location.0 += 1;
-        }
-        's' => {
-            map.world_seed.1 -= 1.0 / ZOOM_FACTOR;
-            map.world_seed.3 -= 1.0 / ADJUSTMENT_ZOOM_FACTOR;
-            map.location.1 -= 1;
-        }
-
-        _ => {}
-    }
+//noise is sampled in global tile coordinates, so moving only changes the location
+pub fn update_location(map: &mut Map, dir: char){
+    let (dx, dy) = dir_offset(dir);
+    map.location.0 += dx;
+    map.location.1 += dy;
     //println!("area is {:?}", map.location);
 }
 
-pub fn generate_structures(area: &mut Area, location: (i32, i32), seed: (f64, f64), dim: (usize, usize)) {
-
-    //println!("calling generate_structures");
-
-    let (map_x, map_y) = location;
-    let (seed_x, seed_y) = seed;
-    let (map_width, map_height) = dim;
-    let billow = Billow::new();
-    let noise_check = billow.get([map_x as f64 * STRUCTURE_RESOLUTION_FACTOR as f64 + seed_x, map_y as f64 * STRUCTURE_RESOLUTION_FACTOR as f64 + seed_y]);
-
-    let mut rect_squad = Vec::<Rect>::new();
-    
-    rect_squad.push(Rect::new((1, 1), (map_width-1, map_height-1)));
-
-    let mut structure_num = ((noise_check.abs() - 0.4).max(0.0) / 0.1) as usize;
-
-    //println!("setup complete; structure_num {}", structure_num);
-
-    while rect_squad.len() < structure_num {
-        let mut temp = Vec::new();
-
-        let mut rng = rand::thread_rng();
-        let randi = rng.gen_range(0, rect_squad.len());
-
-        //println!("randi is {}", randi);
-
-        let chosen_rect = rect_squad[randi];
-
-        //println!("selecting rectangle {:?}", chosen_rect);
-
-        if chosen_rect.w < 5 || chosen_rect.h < 5 {
-            continue;
-        }else{
-            let dir = rng.gen_range(0, 2);
-            let mut split_x = 0;
-            let mut split_y = 0;
-
-            if dir == 0 {
-                //horizontal split
-                let mut lb = chosen_rect.x + chosen_rect.w / 3;
-                let mut rb = chosen_rect.x + chosen_rect.w * 2 / 3;
-                if chosen_rect.w / 3 < 5 && chosen_rect.w / 2 >= 5 {
-                    lb = chosen_rect.x + chosen_rect.w / 2;
-                    rb = chosen_rect.x + chosen_rect.w / 2 + 1;
-                }else if chosen_rect.w / 2 < 5{
-                    structure_num -= 1;
-                    continue;
-                }
-                //println!("left bound {} right bound {}", lb, rb);
-
-                split_x = rng.gen_range(lb, rb);
-            }else{
-                //vertical split
-                let mut lb = chosen_rect.y + chosen_rect.h / 3;
-                let mut ub = chosen_rect.y + chosen_rect.h * 2 / 3;
-                if chosen_rect.h / 3 < 5 && chosen_rect.h / 2 >= 5 {
-                    lb = chosen_rect.y + chosen_rect.h / 2;
-                    ub = chosen_rect.y + chosen_rect.h / 2 + 1;
-                }else if chosen_rect.h / 2 < 5{
-                    structure_num -= 1;
-                    continue;
-                }
-
-                //println!("lower bound {} upper bound {}", lb, ub);
-
-                split_y = rng.gen_range(lb, ub);
-            }
-            temp.append(&mut chosen_rect.split(split_x, split_y));
-        }
-
-        rect_squad.remove(randi);
-        rect_squad.append(&mut temp);
-    }
-    
-    //println!("rectangles split up!");
-
-    for mut rect in rect_squad {
-        //println!("shrinking rectangles");
-
-        let mut rng = rand::thread_rng();
-        let shrinkage = rng.gen_range(2 + (10 - structure_num), 5 + 1 * (10 - structure_num));
-
-        //println!("shrinkage {}", shrinkage);
-
-        rect.shrink((shrinkage as f32 * 1.5) as usize, shrinkage);
-
-        area.structures.push(rect.clone());
-        //println!("transferring rect tiles..");
-
-        let mut perimeter = Vec::new();
-        for y in rect.y..(rect.y + rect.h) {
-            for x in rect.x..(rect.x + rect.w) {
-                //println!("replacing tile at {:?}", (x, y));
-                if (x == rect.x || x == rect.x + rect.w - 1) || (y == rect.y || y == rect.y + rect.h - 1) {
-                    if !((x == rect.x || x == rect.x + rect.w - 1) && (y == rect.y || y == rect.y + rect.h - 1)) {
-                        perimeter.push((x, y));
-                    }
-                    area.tiles[x + y * map_width] = TileBlock::new(Tile::WoodWall, false);
-                }else{
-                    area.tiles[x + y * map_width] = TileBlock::new(Tile::WoodFloor, true);
-                }
-                
-            }
-        }
-
-        //println!("tiles transferred!");
-
-        let randi = rng.gen_range(0, perimeter.len());
-        
-        //println!("door tile index selected! {}", randi);
-
-        let (doorx, doory) = perimeter[randi];
-
-        //println!("door tile is {:?}", (doorx, doory));
-
-        area.tiles[doorx + doory * map_width] = TileBlock::new(Tile::WoodFloor, true);
-
-        //println!("door placed!");
-    }
-
-    //println!("structures generated!");
+//goes down (negative) or up through the layers, staying over the same area
+pub fn update_layer(map: &mut Map, dl: i32){
+    map.location.2 += dl;
 }
 
-pub fn load_map(map: &mut Map, to_load: (Option<Area>, usize)) {
-    //println!("loading area to map from {}", to_load.1);
+//copies the area stored at location into the live map
+pub fn load_map(map: &mut Map, location: (i32, i32, i32)) {
+    //println!("loading area to map from {:?}", location);
 
-    let mut area_pointer = &mut (Area::new());
-    match to_load.0 {
-        Some(a) => {
-            map.world_map.push(a);
-            area_pointer = &mut map.world_map[to_load.1];
-        }
+    let area_pointer = match map.world_map.get_mut(location) {
+        Some(a) => a,
         None => {
-            area_pointer = &mut map.world_map[to_load.1];
+            println!("no area generated at {:?}", location);
+            return;
         }
-    }
+    };
 
     for i in 0..map.tiles.len() {
         map.tiles[i] = (*area_pointer).tiles[i];
     }
-    for i in 0..map.anchor_points.len() {
-        map.anchor_points[i] = (*area_pointer).anchor_points[i].clone();
-    }
+    map.biomes = (*area_pointer).biomes.clone();
+    map.nav = (*area_pointer).nav.clone();
 
     map.structures = (*area_pointer).structures.clone();
+    map.spawn_points = (*area_pointer).spawn_points.clone();
+    map.settlement = (*area_pointer).settlement;
     map.spawned = (*area_pointer).spawned;
     (*area_pointer).spawned = true;
-    map.area_index = to_load.1;
+    map.location = location;
     map.rerolled = true;
 }
 
-pub fn regenerate_map(map: &mut Map, area_index: usize, direction: char) -> (Option<Area>, usize){
-    let old_map_len = map.world_map.len();
-
-    let mut new_area_index = &mut (Area::new()).n;
-
-    let mut diagonal_a = usize::max_value(); //n/w lean
-    let mut diagonal_b = usize::max_value(); //s/e lean
-
-    let mut diagonal_c = usize::max_value(); //n/w lean
-    let mut diagonal_d = usize::max_value(); //s/e lean
-
-    {
-        if direction == 'e' {
-            diagonal_a = map.world_map[area_index].n;
-            diagonal_b = map.world_map[area_index].s;
-
-            diagonal_c = map.world_map[area_index].ne;
-            diagonal_d = map.world_map[area_index].se;
-
-            new_area_index = &mut ((map.world_map[area_index]).e);
-        }else if direction == 'w' {
-            diagonal_a = map.world_map[area_index].n;
-            diagonal_b = map.world_map[area_index].s;
-            
-            diagonal_c = map.world_map[area_index].nw;
-            diagonal_d = map.world_map[area_index].sw;
-
-            new_area_index = &mut ((map.world_map[area_index]).w);
-        }else if direction == 's' {
-            diagonal_a = map.world_map[area_index].w;
-            diagonal_b = map.world_map[area_index].e;
+//noise offsets for the starting area, drawn from the world rng
+pub fn roll_world_seed(world_rng: &mut WorldRng) -> (f64, f64, f64, f64, f64, f64, f64, f64) {
+    let rng = world_rng.rng();
+    (rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64)
+}
 
-            diagonal_c = map.world_map[area_index].sw;
-            diagonal_d = map.world_map[area_index].se;
+//runs the generator for a single area
+pub fn generate_area(location: (i32, i32, i32), dim: (usize, usize), world_seed: (f64, f64, f64, f64, f64, f64, f64, f64), world_rng: &WorldRng, generator: &PassGenerator) -> Area {
+    let mut ctx = GenContext::new(location, dim, world_seed, world_rng);
+    generator.generate(&mut ctx)
+}
 
-            new_area_index = &mut ((map.world_map[area_index]).s);
-        }else if direction == 'n' {
-            diagonal_a = map.world_map[area_index].w;
-            diagonal_b = map.world_map[area_index].e;
-            
-            diagonal_c = map.world_map[area_index].nw;
-            diagonal_d = map.world_map[area_index].ne;
-            
-            new_area_index = &mut ((map.world_map[area_index]).n);
-        }   
+//generates the area at the map's current location unless it was already explored
+pub fn regenerate_map(map: &mut Map, world_rng: &WorldRng, generator: &PassGenerator) {
+    if map.world_map.contains(map.location) {
+        //println!("no new area needed; found area {:?}", map.location);
+        return;
     }
-    
-    //println!("area lookup is {}", *new_area_index);
-
-
-    if *new_area_index == usize::max_value() {
-        //println!("creating new area");
-
-        let mut area = Area::new();
-
-        let mut rng = rand::thread_rng();
-
-        let w = map.width;
-        let h = map.height;
-        
-
-        let perlin = Perlin::new();
-        let billow = Billow::new();
-
-        //perlin.set_seed(rng.gen::<u32>());
-        let bxseed = map.world_seed.4;
-        let byseed = map.world_seed.5;
-        let xseed = map.world_seed.0;
-        let yseed = map.world_seed.1;
-        let aseed = map.world_seed.2;
-        let bseed = map.world_seed.3;
-
-
-        for y in 0..h {
-            for x in 0..w {
-                let noise = perlin.get([xseed + x as f64 / w as f64 / ZOOM_FACTOR, yseed + y as f64 / h as f64 / ZOOM_FACTOR]);
-                let adjustment = billow.get([aseed + x as f64 / w as f64 / ADJUSTMENT_ZOOM_FACTOR, bseed + y as f64 / h as f64 / ADJUSTMENT_ZOOM_FACTOR]) * NOISE_DISPLACEMENT;
-                //(rng.gen::<f64>() - rng.gen::<f64>()) * NOISE_DISPLACEMENT;//
-                let biome_mod = (perlin.get([bxseed + (map.location.0 as f64 + x as f64 / w as f64 as f64) * BIOME_RESOLUTION_FACTOR as f64, byseed + (map.location.1 as f64 + y as f64 / h as f64) * BIOME_RESOLUTION_FACTOR as f64]).abs() * BIOME_NUM as f64) as u32;
-                
-                let mut tilefloat = noise_ease(noise + adjustment);
-                
-                if tilefloat >= 1.0 {
-                    tilefloat = 0.99;
-                }else if tilefloat < 0.0 {
-                    tilefloat = 0.0;
-                }
-
-                let tile = num::FromPrimitive::from_u32(biome_mod * BIOME_TILESET_SIZE + (tilefloat * (BIOME_TILESET_SIZE as f64)) as u32).unwrap();
-                area.tiles.push(TileBlock::new(tile, true));
-            }
-        }
-
-        generate_structures(&mut area, map.location, (map.world_seed.6, map.world_seed.7), (map.width, map.height));
-        
-        for y in 0..h {
-            for x in 0..w {
-                map.tiles[x + y * w] = area.tiles[x + y * w];
-            }
-        }
-
-
-        //adding out of bounds anchor points
-        let west = Anchor::new(usize::max_value(), 0, map.location.0, map.location.1);
-        let east = Anchor::new(w, 0, map.location.0, map.location.1);
-        let north = Anchor::new(0, h, map.location.0, map.location.1);
-        let south = Anchor::new(0, usize::max_value(), map.location.0, map.location.1);
-
-        area.anchor_points.push(west);
-        area.anchor_points.push(east);
-        area.anchor_points.push(north);
-        area.anchor_points.push(south);
-
-        for ty in 0..h {
-            for tx in 0..w {
-                let mut anchor = Anchor::new(tx, ty, map.location.0, map.location.1);
-                if tx == 0 {
-                    //add west to succ
-                    anchor.succ.push((0, 10));
-                }else if tx == w - 1 {
-                    //add east to succ
-                    anchor.succ.push((1, 10));
-                }
-                if ty == 0 {
-                    //add south to succ
-                    anchor.succ.push((3, 10));
-                } else if ty == h - 1 {
-                    //add north to succ
-                    anchor.succ.push((2, 10));
-                }
-                if map.tiles[tx + ty * w].passable {
-                    for y in -1..2 {
-                        let py = anchor.pos.1 as i32 + y;
-                        if py < 0 {
-                            continue;
-                        }
-                        let ny = py as usize;
-                        if ny >= h {
-                            break;
-                        }
-                        for x in -1..2 {
-                            if x == 0 && y == 0 {
-                                continue;
-                            }
-                            let px = anchor.pos.0 as i32 + x;
-                            if px < 0 {
-                                continue;
-                            }
-                            let nx = px as usize;
-                            if nx >= w {
-                                break;
-                            }
-                            let index = nx + ny * w;
-                            let mut cost = 10;
-                            if x != 0 && y != 0 {
-                                cost = 14;
-                            }
-                            if map.tiles[index].passable {
-                                anchor.succ.push((index + 4, cost));
-                            }
-                        }
-                    }
-                }
-                area.anchor_points.push(anchor);
-            }
-        }
-
-        //link the list backwards
-        if direction == 'n' {
-            area.sw = diagonal_a;
-            area.se = diagonal_b;
-            area.w = diagonal_c;
-            area.e = diagonal_d;
-            area.s = map.area_index;
-        }else if direction == 'e' {
-            area.nw = diagonal_a;
-            area.sw = diagonal_b;
-            area.n = diagonal_c;
-            area.s = diagonal_d;
-            area.w = map.area_index;
-        }else if direction == 'w' {
-            area.ne = diagonal_a;
-            area.se = diagonal_b;
-            area.n = diagonal_c;
-            area.s = diagonal_d;
-            area.e = map.area_index;
-        }else if direction == 's' {
-            area.nw = diagonal_a;
-            area.ne = diagonal_b;
-            area.w = diagonal_c;
-            area.e = diagonal_d;
-            area.n = map.area_index;
-        }
 
-        //link the list forwards
-        *new_area_index = old_map_len;
-        //map.world_map.push(area);
-        (Some(area), *new_area_index)
-    }else{
-        //println!("no new area needed; found area {}", *new_area_index);
+    //println!("creating new area");
 
-        //let area = &map.world_map[*new_area_index];
-
-        //for i in 0..map.tiles.len() {
-        //    map.tiles[i] = (*area).tiles[i];
-        //}
-        (None, *new_area_index)
+    let mut ctx = GenContext::new(map.location, (map.width, map.height), map.world_seed, world_rng);
+    //a cave layer goes under the stairs of the layer above, read off it when that's been explored already
+    let (lx, ly, layer) = map.location;
+    if let Some(above) = map.world_map.get((lx, ly, layer + 1)).filter(|_| layer < 0) {
+        ctx.entrances_above = Some(stairs_down(above, map.width));
     }
-}
-
-fn noise_ease(raw: f64) -> f64{
-    let abs = raw.abs();
+    let area = generator.generate(&mut ctx);
 
-    abs
+    map.world_map.insert(map.location, area);
 }
 
 fn generate_map(world: &mut World){
+    let world_rng = world.read_resource::<WorldRng>();
+    let generator = world.read_resource::<PassGenerator>();
     let mut map = world.write_resource::<Map>();
 
-    let mut area = Area::new();
-
-    let mut rng = rand::thread_rng();
-
-    let w = map.width;
-    let h = map.height;
-
-    let perlin = Perlin::new();
-
-    let billow = Billow::new();
-
-    //perlin.set_seed(rng.gen::<u32>());
-
-    let bxseed = map.world_seed.4;
-    let byseed = map.world_seed.5;
-    let xseed = map.world_seed.0;
-    let yseed = map.world_seed.1;
-    let aseed = map.world_seed.2;
-    let bseed = map.world_seed.3;
+    println!("map dim: {:?}", (map.width, map.height));
 
-    println!("map dim: {:?}", (w, h));
-
-    for y in 0..h {
-        for x in 0..w {
-            let noise = perlin.get([xseed + x as f64 / w as f64 / ZOOM_FACTOR, yseed + y as f64 / h as f64 / ZOOM_FACTOR]);
-            let adjustment = billow.get([aseed + x as f64 / w as f64 / ADJUSTMENT_ZOOM_FACTOR, bseed + y as f64 / h as f64 / ADJUSTMENT_ZOOM_FACTOR]) * NOISE_DISPLACEMENT;//(rng.gen::<f64>() - rng.gen::<f64>()) * NOISE_DISPLACEMENT;
-
-            let biome_mod = (perlin.get([bxseed + (map.location.0 as f64 + x as f64 / w as f64 as f64) * BIOME_RESOLUTION_FACTOR as f64, byseed + (map.location.1 as f64 + y as f64 / h as f64) * BIOME_RESOLUTION_FACTOR as f64]).abs() * BIOME_NUM as f64) as u32;
-
-            let mut tilefloat = noise_ease(noise + adjustment);
-            
-            if tilefloat >= 1.0 {
-                tilefloat = 0.99;
-            }else if tilefloat < 0.0 {
-                tilefloat = 0.0;
-            }
-
-            let tile = num::FromPrimitive::from_u32(biome_mod * BIOME_TILESET_SIZE + (tilefloat * (BIOME_TILESET_SIZE as f64)) as u32).unwrap();
-            //println!("tile {:?}", tile);
-            area.tiles.push(TileBlock::new(tile, true));
-            
-            
-        }
-    }
-
-    generate_structures(&mut area, map.location, (map.world_seed.6, map.world_seed.7), (map.width, map.height));
-
-    map.structures = area.structures.clone();
-    map.spawned = area.spawned;
-    area.spawned = true;
-
-    for y in 0..h {
-        for x in 0..w {
-            map.tiles[x + y * w] = area.tiles[x + y * w];
-        }
-    }
+    regenerate_map(&mut map, &world_rng, &generator);
+    let location = map.location;
+    load_map(&mut map, location);
 
     println!("map tiles transferred");
-
-    //adding out of bounds anchor points
-    let west = Anchor::new(usize::max_value(), 0, map.location.0, map.location.1);
-    let east = Anchor::new(w, 0, map.location.0, map.location.1);
-    let north = Anchor::new(0, h, map.location.0, map.location.1);
-    let south = Anchor::new(0, usize::max_value(), map.location.0, map.location.1);
-
-    map.anchor_points.push(west.clone());
-    map.anchor_points.push(east.clone());
-    map.anchor_points.push(north.clone());
-    map.anchor_points.push(south.clone());
-    area.anchor_points.push(west);
-    area.anchor_points.push(east);
-    area.anchor_points.push(north);
-    area.anchor_points.push(south);
-
-    for ty in 0..h {
-        for tx in 0..w {
-            let mut anchor = Anchor::new(tx, ty, map.location.0, map.location.1);
-            if tx == 0 {
-                //add west to succ
-                anchor.succ.push((0, 10));
-            }else if tx == w - 1 {
-                //add east to succ
-                anchor.succ.push((1, 10));
-            }
-            if ty == 0 {
-                //add south to succ
-                anchor.succ.push((3, 10));
-            } else if ty == h - 1 {
-                //add north to succ
-                anchor.succ.push((2, 10));
-            }
-            for y in -1..2 {
-                let py = anchor.pos.1 as i32 + y;
-                if py < 0 {
-                    continue;
-                }
-                let ny = py as usize;
-                if ny >= h {
-                    break;
-                }
-                for x in -1..2 {
-                    if x == 0 && y == 0 {
-                        continue;
-                    }
-                    let px = anchor.pos.0 as i32 + x;
-                    if px < 0 {
-                        continue;
-                    }
-                    let nx = px as usize;
-                    if nx >= w {
-                        break;
-                    }
-                    let index = nx + ny * w;
-                    let mut cost = 10;
-                    if x != 0 && y != 0 {
-                        cost = 14;
-                    }
-                    //println!("made it here to index {}", index);
-                    if map.tiles[index].passable {
-                        anchor.succ.push((index + 4, cost));
-                    }
-                }
-            }
-            map.anchor_points.push(anchor.clone());
-            area.anchor_points.push(anchor);           
-        }
-    }
-
-    map.world_map.push(area);
 }
 
 fn initialise_spritesheet_handles(world: &mut World) {
@@ -1138,18 +1100,20 @@ fn initialise_player(world: &mut World, sprite_sheet: Handle<SpriteSheet>){
             height: PLAYER_HEIGHT,
         })
         .with(components::Id::new(EntityType::Person))
-        .with(components::Physical::new((s_w / 2.0, s_h / 2.0), (0, 0)))
+        .with(components::Physical::new((s_w / 2.0, s_h / 2.0), (0, 0, 0)))
         .with(local_transform)
         .build();
 }
 
-pub fn spawn_person(cux: usize, cuy: usize, ax: i32, ay: i32, handles: &Read<SpriteSheetHandles>, ents: &mut Entities, phys: &mut WriteStorage<components::Physical>, 
+pub fn spawn_person(cux: usize, cuy: usize, location: (i32, i32, i32), handles: &Read<SpriteSheetHandles>, ents: &mut Entities, phys: &mut WriteStorage<components::Physical>, 
     movers: &mut WriteStorage<components::Mover>, ids: &mut WriteStorage<Id>, offs: &mut WriteStorage<components::Offscreen>, 
-    trans: &mut WriteStorage<Transform>, srs: &mut WriteStorage<SpriteRender>, hungs: &mut WriteStorage<components::Hunger>) {
+    trans: &mut WriteStorage<Transform>, srs: &mut WriteStorage<SpriteRender>, needs: &mut WriteStorage<components::Needs>, need_set: &NeedSet,
+    behaviours: &mut WriteStorage<components::Behaviour>, behaviour_set: &BehaviourSet,
+    residents: &mut WriteStorage<components::Resident>, resident: Option<components::Resident>, tree: Option<&str>, rng: &mut ChaCha8Rng) {
     let mut local_transform = Transform::default();
     local_transform.set_translation_xyz(-100.0, 0.0, 0.0);
 
-    let local_physical = components::Physical::new(((cux * TILE_SIZE) as f32, (cuy * TILE_SIZE) as f32), (ax, ay));
+    let local_physical = components::Physical::new(((cux * TILE_SIZE) as f32, (cuy * TILE_SIZE) as f32), location);
     let local_render = SpriteRender {
         sprite_sheet: handles.get(SpriteSheetLabel::Person).unwrap().clone(),
         sprite_number: 1,
@@ -1158,27 +1122,31 @@ pub fn spawn_person(cux: usize, cuy: usize, ax: i32, ay: i32, handles: &Read<Spr
     let local_mover = components::Mover::new(DEFAULT_BASE_SPEED);
     let local_off = components::Offscreen::new();
 
-    let mut rng = rand::thread_rng();
-    let hung = rng.gen::<f32>() * 0.5 + 0.5;
-
-    let local_hunger = components::Hunger::new(DEFAULT_HUNGER_CAPACITY, DEFAULT_HUNGER_RATE, hung * DEFAULT_HUNGER_CAPACITY);
+    let local_needs = need_set.spawn(rng);
 
-    ents.build_entity()
+    let mut builder = ents.build_entity()
         .with(local_transform, trans)
         .with(local_physical, phys)
         .with(local_render, srs)
         .with(local_ids, ids)
         .with(local_mover, movers)
         .with(local_off, offs)
-        .with(local_hunger, hungs)
-        .build();
+        .with(local_needs, needs);
+
+    if let Some(tree) = behaviour_set.tree_for(tree, resident.is_some()) {
+        builder = builder.with(components::Behaviour::new(tree), behaviours);
+    }
+    if let Some(resident) = resident {
+        builder = builder.with(resident, residents);
+    }
+    builder.build();
 }
 
-pub fn spawn_plant(cux: usize, cuy: usize, ax: i32, ay: i32, handles: &Read<SpriteSheetHandles>, ents: &mut Entities, phys: &mut WriteStorage<components::Physical>, plants: &mut WriteStorage<components::Plant>, ids: &mut WriteStorage<Id>, offs: &mut WriteStorage<components::Offscreen>, trans: &mut WriteStorage<Transform>, srs: &mut WriteStorage<SpriteRender>) {
+pub fn spawn_plant(cux: usize, cuy: usize, location: (i32, i32, i32), handles: &Read<SpriteSheetHandles>, ents: &mut Entities, phys: &mut WriteStorage<components::Physical>, plants: &mut WriteStorage<components::Plant>, ids: &mut WriteStorage<Id>, offs: &mut WriteStorage<components::Offscreen>, trans: &mut WriteStorage<Transform>, srs: &mut WriteStorage<SpriteRender>, rng: &mut ChaCha8Rng) {
     let mut local_transform = Transform::default();
     local_transform.set_translation_xyz(-100.0, 0.0, 0.0);
 
-    let local_physical = components::Physical::new(((cux * TILE_SIZE) as f32, (cuy * TILE_SIZE) as f32), (ax, ay));
+    let local_physical = components::Physical::new(((cux * TILE_SIZE) as f32, (cuy * TILE_SIZE) as f32), location);
     
     //println!("handles Plants -> {:?}", handles.get(SpriteSheetLabel::Plants));
 
@@ -1190,7 +1158,6 @@ pub fn spawn_plant(cux: usize, cuy: usize, ax: i32, ay: i32, handles: &Read<Spri
     let local_ids = Id::new(EntityType::Plant);
     let local_off = components::Offscreen::new();
 
-    let mut rng = rand::thread_rng();
     let ripeness = rng.gen::<f32>();
     let fruit_rate = (rng.gen::<f32>() * 0.4 + 0.8) * DEFAULT_BASE_FRUIT_RATE;
 
@@ -1206,6 +1173,54 @@ pub fn spawn_plant(cux: usize, cuy: usize, ax: i32, ay: i32, handles: &Read<Spri
         .build();
 }
 
+//rebuilds a person or plant from a save, keeping its id and simulation state
+pub fn restore_entity(save: EntitySave, handles: &Read<SpriteSheetHandles>, ents: &mut Entities, phys: &mut WriteStorage<components::Physical>,
+    movers: &mut WriteStorage<components::Mover>, plants: &mut WriteStorage<components::Plant>, ids: &mut WriteStorage<Id>, offs: &mut WriteStorage<components::Offscreen>,
+    trans: &mut WriteStorage<Transform>, srs: &mut WriteStorage<SpriteRender>, needs: &mut WriteStorage<components::Needs>,
+    behaviours: &mut WriteStorage<components::Behaviour>, behaviour_set: &BehaviourSet, residents: &mut WriteStorage<components::Resident>) {
+    let mut local_transform = Transform::default();
+    local_transform.set_translation_xyz(-100.0, 0.0, 0.0);
+
+    let local_render = match save.id.get_type() {
+        EntityType::Plant => SpriteRender {
+            sprite_sheet: handles.get(SpriteSheetLabel::Plants).unwrap().clone(),
+            sprite_number: 0,
+        },
+        _ => SpriteRender {
+            sprite_sheet: handles.get(SpriteSheetLabel::Person).unwrap().clone(),
+            sprite_number: 1,
+        },
+    };
+
+    let mut builder = ents.build_entity()
+        .with(local_transform, trans)
+        .with(save.physical, phys)
+        .with(local_render, srs)
+        .with(save.id, ids);
+
+    if let Some(mover) = save.mover {
+        builder = builder.with(mover, movers);
+    }
+    if let Some(n) = save.needs {
+        builder = builder.with(n, needs);
+    }
+    let resident = save.resident.is_some();
+    if let Some(b) = save.behaviour.and_then(|b| behaviour_set.restore(b, resident)) {
+        builder = builder.with(b, behaviours);
+    }
+    if let Some(plant) = save.plant {
+        builder = builder.with(plant, plants);
+    }
+    if let Some(off) = save.offscreen {
+        builder = builder.with(off, offs);
+    }
+    if let Some(resident) = save.resident {
+        builder = builder.with(resident, residents);
+    }
+
+    builder.build();
+}
+
 fn load_sprite_sheet(world: &mut World, name: &str) -> Handle<SpriteSheet> {
     //loading spritesheet
     let texture_handle = {
@@ -1258,14 +1273,22 @@ impl SimpleState for LoadingState {
             
             //NOTICE Map is defined here
             let mut map = Map::new(loaded.stage_width as usize / TILE_SIZE + 1, loaded.stage_height as usize / TILE_SIZE + 1);
+            let dim = (map.width, map.height);
+            let diagonals = loaded.diagonal_policy;
             
-            //seeding map world seed
-            let mut rng = rand::thread_rng();
-            map.world_seed = (rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64, rng.gen::<f64>() * MAP_SEED_RANGE as f64);
+            //seeding map world seed; command line seed wins over globals.ron, otherwise roll one
+            let seed = match self.seed_override.or(loaded.world_seed) {
+                Some(s) => s,
+                None => rand::thread_rng().gen::<u64>(),
+            };
+            println!("World seed: {}", seed);
+            let mut world_rng = WorldRng::new(seed);
+            map.world_seed = roll_world_seed(&mut world_rng);
 
             println!("Loaded config: {:?}", loaded);
             data.world.insert(loaded);
             data.world.insert(map);
+            data.world.insert(world_rng);
 
 
             
@@ -1273,6 +1296,40 @@ impl SimpleState for LoadingState {
 
             initialise_spritesheet_handles(world);
 
+            let contents = fs::read_to_string(&self.biomes_path)
+                .expect("Error reading biomes file");
+            let biomes: BiomeSet = from_str(&contents)
+                .expect("Error loading biomes file");
+            biomes.validate().expect("Error in biomes file");
+            println!("Loaded {} biomes", biomes.biomes.len());
+
+            let contents = fs::read_to_string(&self.landmarks_path)
+                .expect("Error reading landmarks file");
+            let landmarks: LandmarkSet = from_str(&contents)
+                .expect("Error loading landmarks file");
+
+            let landmark_pass = TiledPass::load(&landmarks, dim);
+
+            let contents = fs::read_to_string(&self.needs_path)
+                .expect("Error reading needs file");
+            let needs: NeedSet = from_str(&contents)
+                .expect("Error loading needs file");
+            needs.validate().expect("Error in needs file");
+            println!("Loaded {} needs", needs.needs.len());
+            world.insert(needs);
+
+            let contents = fs::read_to_string(&self.behaviours_path)
+                .expect("Error reading behaviours file");
+            let behaviours: BehaviourSet = from_str(&contents)
+                .expect("Error loading behaviours file");
+            behaviours.validate(&world.read_resource::<NeedSet>(), &landmark_pass.behaviours())
+                .expect("Error in behaviours file");
+            println!("Loaded {} behaviour trees", behaviours.trees.len());
+            world.insert(behaviours);
+
+            world.insert(PassGenerator::standard(biomes.clone(), landmark_pass, diagonals));
+            world.insert(biomes);
+
             generate_map(*world);
 
             self.sprite_sheet_handle.replace(load_sprite_sheet(*world, "tiles"));
@@ -1322,4 +1379,100 @@ impl SimpleState for PlayState {
         dim.height = data.world.fetch::<ScreenDimensions>().height();
         Trans::None
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::ecs::prelude::{Builder, World, WorldExt};
+
+    fn draws(mut rng: ChaCha8Rng) -> Vec<u64> {
+        (0..4).map(|_| rng.gen()).collect()
+    }
+
+    #[test]
+    fn area_rngs_depend_only_on_seed_location_and_stream() {
+        let world = WorldRng::new(7);
+        let mut used = WorldRng::new(7);
+        used.rng().gen::<u64>();
+        assert_eq!(draws(world.area_rng((2, -3), RngStream::Structures)), draws(used.area_rng((2, -3), RngStream::Structures)));
+
+        let base = draws(world.area_rng((2, -3), RngStream::Structures));
+        assert_ne!(base, draws(world.area_rng((-3, 2), RngStream::Structures)));
+        assert_ne!(base, draws(world.area_rng((2, -3), RngStream::Spawn)));
+        assert_ne!(base, draws(WorldRng::new(8).area_rng((2, -3), RngStream::Structures)));
+    }
+
+    #[test]
+    fn world_rngs_draw_the_same_numbers_as_always() {
+        let mut world = WorldRng::new(7);
+        assert_eq!(world.rng().gen::<u64>(), 2910824217569608635);
+        assert_eq!(draws(world.area_rng((2, -3), RngStream::Structures))[0], 2413971679896140500);
+    }
+
+    #[test]
+    fn layer_rngs_match_area_rngs_on_the_surface_only() {
+        let world = WorldRng::new(7);
+        let surface = draws(world.area_rng((1, 1), RngStream::Spawn));
+        assert_eq!(surface, draws(world.layer_rng((1, 1, 0), RngStream::Spawn)));
+        assert_ne!(surface, draws(world.layer_rng((1, 1, -1), RngStream::Spawn)));
+        assert_ne!(draws(world.layer_rng((1, 1, -1), RngStream::Spawn)), draws(world.layer_rng((1, 1, -2), RngStream::Spawn)));
+        assert_eq!(draws(world.layer_rng((1, 1, -1), RngStream::Spawn)), draws(WorldRng::new(7).layer_rng((1, 1, -1), RngStream::Spawn)));
+    }
+
+    fn marked(spawned: bool) -> Area {
+        let mut area = Area::new();
+        area.spawned = spawned;
+        area
+    }
+
+    #[test]
+    fn world_map_keeps_the_area_already_stored() {
+        let mut world = WorldMap::new();
+        world.insert((0, 0, 0), marked(true));
+        assert!(world.insert((0, 0, 0), marked(false)).spawned);
+        assert!(world.get((0, 0, 0)).unwrap().spawned);
+
+        //layers are kept apart, and only generated areas count as neighbours
+        world.insert((0, 0, -1), marked(false));
+        world.insert((1, 1, 0), marked(false));
+        assert!(!world.get((0, 0, -1)).unwrap().spawned);
+        assert!(world.contains((1, 1, 0)) && !world.contains((1, 0, 0)));
+        let around: Vec<_> = world.neighbours((0, 0, 0)).into_iter().map(|(l, _)| l).collect();
+        assert_eq!(around, vec![(1, 1, 0)]);
+        assert!(world.neighbour((0, 0, 0), (1, 1)).is_some());
+    }
+
+    fn popped(queue: &mut PathQueue) -> Vec<(Entity, usize)> {
+        let mut out = Vec::new();
+        while let Some(request) = queue.pop() {
+            out.push((request.entity, request.goal.local().0));
+        }
+        out
+    }
+
+    #[test]
+    fn path_requests_come_out_most_urgent_first() {
+        let mut world = World::new();
+        let people: Vec<Entity> = (0..4).map(|_| world.create_entity().build()).collect();
+        let mut queue = PathQueue::default();
+        for (i, (person, priority)) in people.iter().zip([3, 1, 3, 0].iter()).enumerate() {
+            queue.submit(*person, *priority, (0, 0, 0), (0, 0), Anchor::new(i, 0, (0, 0, 0)));
+        }
+        assert_eq!(queue.len(), 4);
+        //equal priorities keep the order they came in
+        assert_eq!(popped(&mut queue), vec![(people[3], 3), (people[1], 1), (people[0], 0), (people[2], 2)]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn asking_again_replaces_the_earlier_request() {
+        let mut world = World::new();
+        let (a, b) = (world.create_entity().build(), world.create_entity().build());
+        let mut queue = PathQueue::default();
+        queue.submit(a, 1, (0, 0, 0), (0, 0), Anchor::new(1, 0, (0, 0, 0)));
+        queue.submit(b, 2, (0, 0, 0), (0, 0), Anchor::new(2, 0, (0, 0, 0)));
+        queue.submit(a, 3, (0, 0, 0), (0, 0), Anchor::new(3, 0, (0, 0, 0)));
+        assert_eq!(queue.len(), 2);
+        assert_eq!(popped(&mut queue), vec![(b, 2), (a, 3)]);
+    }
+}
@@ -0,0 +1,11 @@
+extern crate amethyst;
+#[macro_use]
+extern crate num_derive;
+
+extern crate noise;
+
+pub mod game_state;
+pub mod systems;
+pub mod components;
+pub mod worldgen;
+pub mod save;
@@ -1,14 +1,7 @@
 extern crate amethyst;
-#[macro_use]
-extern crate num_derive;
 
-extern crate noise;
-
-mod game_state;
-mod systems;
-mod components;
-
-use game_state::*;
+use bittenoff::{game_state, systems};
+use bittenoff::game_state::*;
 
 use std::fs;
 use std::fs::File;
@@ -33,13 +26,17 @@ use amethyst::{
 use amethyst::winit::{Event, EventsLoop, Window, WindowEvent, ControlFlow};
 
 
-use crate::game_state::Config;
+use bittenoff::game_state::Config;
 
 fn main() -> amethyst::Result<()> {
     let app_root = application_root_dir()?;
     let binding_path = app_root.join("config").join("bindings.ron");
     let display_config_path = app_root.join("config").join("display.ron");
     let game_config_path = app_root.join("config").join("globals.ron");
+    let biomes_path = app_root.join("config").join("biomes.ron");
+    let landmarks_path = app_root.join("config").join("landmarks.ron");
+    let needs_path = app_root.join("config").join("needs.ron");
+    let behaviours_path = app_root.join("config").join("behaviours.ron");
 
     let contents = fs::read_to_string(display_config_path.to_str().unwrap())
         .expect("Error reading display config file");
@@ -72,14 +69,18 @@ fn main() -> amethyst::Result<()> {
         .with(systems::MapSystem, "map_update_system", &[])
         .with(systems::SpawnSystem, "spawn_system", &[])
         .with(systems::PlayerMoveSystem, "player_move_system", &["input_system"])
-        .with(systems::PlayerLocalitySystem, "player_locality_system", &[])
+        .with(systems::PlayerLocalitySystem::new(), "player_locality_system", &[])
         .with(systems::PlayerActionSystem::new(), "player_action_system", &[])
-        .with(systems::SimpleIdle, "simple_idle_system", &[])
-        .with(systems::HungerGoalSystem, "hunger_goal_system", &[])
+        .with(systems::SaveSystem::new(), "save_system", &["input_system"])
+        .with(systems::ExportSystem::new(), "export_system", &["input_system"])
+        .with(systems::NeedsSystem, "needs_system", &[])
+        .with(systems::NeedsGoalSystem, "needs_goal_system", &["needs_system"])
+        .with(systems::BehaviourSystem, "behaviour_system", &["needs_system"])
         .with(systems::PhysicalSystem, "physical_system", &[])
         .with(systems::CounterSystem, "fps_system", &[])
         .with(systems::MoveSystem, "move_system", &[])
         .with(systems::RudderSystem, "rudder_system", &[])
+        .with(systems::PathfindingSystem, "pathfinding_system", &["rudder_system"])
         .with(systems::OffscreenSystem, "offscreen_system", &[])
         .with(systems::PlantSystem, "plant_system", &[])
         .with(systems::UiDisplaySystem, "ui_display_system", &[])
@@ -99,9 +100,25 @@ fn main() -> amethyst::Result<()> {
 
     let mut load_state = LoadingState::default();
     load_state.config_path = game_config_path.to_str().unwrap().to_string();
+    load_state.biomes_path = biomes_path.to_str().unwrap().to_string();
+    load_state.landmarks_path = landmarks_path.to_str().unwrap().to_string();
+    load_state.needs_path = needs_path.to_str().unwrap().to_string();
+    load_state.behaviours_path = behaviours_path.to_str().unwrap().to_string();
+    load_state.seed_override = seed_from_args();
 
     let mut game = Application::new(app_root, load_state, game_data)?;
     game.run();
     
     Ok(())
 }
+
+//`--seed <n>` on the command line overrides the world seed in globals.ron
+fn seed_from_args() -> Option<u64> {
+    let args: Vec<String> = std::env::args().collect();
+    for i in 0..args.len() {
+        if args[i] == "--seed" && i + 1 < args.len() {
+            return args[i + 1].parse::<u64>().ok();
+        }
+    }
+    None
+}
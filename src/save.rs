@@ -0,0 +1,333 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use serde::{Serialize, Deserialize};
+use ron::de::from_str;
+use ron::ser::to_string;
+
+use amethyst::utils::application_root_dir;
+
+use crate::game_state::{Map, WorldMap, WorldRng, load_map};
+use crate::components::{Id, Physical, Mover, Needs, Plant, Offscreen, Resident, Behaviour};
+
+//bump whenever the layout of WorldSave or the meaning of its fields changes
+pub const SAVE_VERSION: u32 = 10;
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Serialize(ron::ser::Error),
+    Deserialize(ron::de::Error),
+    Version(u32),
+    Dimensions((usize, usize)),
+}
+
+//just enough of a save to tell which version wrote it, read first so older layouts are still recognised
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl From<ron::ser::Error> for SaveError {
+    fn from(e: ron::ser::Error) -> Self {
+        SaveError::Serialize(e)
+    }
+}
+
+impl From<ron::de::Error> for SaveError {
+    fn from(e: ron::de::Error) -> Self {
+        SaveError::Deserialize(e)
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PlayerSave {
+    pub area_pos: (i32, i32, i32),
+    pub real_pos: (f32, f32),
+}
+
+//one simulated person or plant; components it does not have are left out
+#[derive(Clone, Serialize, Deserialize)]
+pub struct EntitySave {
+    pub id: Id,
+    pub physical: Physical,
+    pub mover: Option<Mover>,
+    pub needs: Option<Needs>,
+    #[serde(default)]
+    pub behaviour: Option<Behaviour>,
+    pub plant: Option<Plant>,
+    pub offscreen: Option<Offscreen>,
+    #[serde(default)]
+    pub resident: Option<Resident>,
+}
+
+impl EntitySave {
+    pub fn capture(id: &Id, physical: &Physical, mover: Option<&Mover>, needs: Option<&Needs>, behaviour: Option<&Behaviour>, plant: Option<&Plant>, offscreen: Option<&Offscreen>, resident: Option<&Resident>) -> EntitySave {
+        EntitySave {
+            id: *id,
+            physical: physical.clone(),
+            mover: mover.cloned(),
+            needs: needs.cloned(),
+            behaviour: behaviour.cloned(),
+            plant: plant.cloned(),
+            offscreen: offscreen.cloned(),
+            resident: resident.cloned(),
+        }
+    }
+}
+
+//the explored world: every generated area plus where the player stands in it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WorldSave {
+    pub version: u32,
+    pub seed: u64,
+    pub world_seed: (f64, f64, f64, f64, f64, f64, f64, f64),
+    pub dim: (usize, usize),
+    pub location: (i32, i32, i32),
+    pub world_map: WorldMap,
+    pub player: PlayerSave,
+    pub entities: Vec<EntitySave>,
+}
+
+impl WorldSave {
+    pub fn capture(map: &Map, world_rng: &WorldRng, player: &Physical, entities: Vec<EntitySave>) -> WorldSave {
+        WorldSave {
+            version: SAVE_VERSION,
+            seed: world_rng.seed(),
+            world_seed: map.world_seed,
+            dim: (map.width, map.height),
+            location: map.location,
+            world_map: map.world_map.clone(),
+            player: PlayerSave {
+                area_pos: player.get_location(),
+                real_pos: player.get_real_position(),
+            },
+            entities,
+        }
+    }
+
+    //restores the map and the player, handing back the entities for the caller to rebuild
+    pub fn restore(self, map: &mut Map, world_rng: &mut WorldRng, player: &mut Physical) -> Result<Vec<EntitySave>, SaveError> {
+        if self.dim != (map.width, map.height) {
+            return Err(SaveError::Dimensions(self.dim));
+        }
+
+        *world_rng = WorldRng::new(self.seed);
+
+        map.world_seed = self.world_seed;
+        map.world_map = self.world_map;
+        load_map(map, self.location);
+
+        player.set_area_x(self.player.area_pos.0);
+        player.set_area_y(self.player.area_pos.1);
+        player.set_layer(self.player.area_pos.2);
+        player.set_x(self.player.real_pos.0);
+        player.set_y(self.player.real_pos.1);
+
+        Ok(self.entities)
+    }
+}
+
+//save paths are relative to the application root, like the config files
+pub fn save_path(path: &str) -> Result<PathBuf, SaveError> {
+    Ok(application_root_dir()?.join(path))
+}
+
+pub fn write_save(path: &str, save: &WorldSave) -> Result<(), SaveError> {
+    let path = save_path(path)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, to_string(save)?)?;
+    Ok(())
+}
+
+pub fn read_save(path: &str) -> Result<WorldSave, SaveError> {
+    let contents = fs::read_to_string(save_path(path)?)?;
+    let header: SaveHeader = from_str(&contents)?;
+    if header.version != SAVE_VERSION {
+        return Err(SaveError::Version(header.version));
+    }
+    Ok(from_str(&contents)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use crate::game_state::{Area, Anchor, Tile, TileBlock, Rect, SpawnPoint, EntityType, GoalPriority, GoalType, roll_world_seed};
+    use crate::components::{Goal, NeedSet};
+    use crate::worldgen::{DiagonalPolicy, build_nav, build_portals};
+
+    const DIM: (usize, usize) = (6, 4);
+
+    //saves go to the system temp dir, one file per test so they can run side by side
+    fn temp_save(name: &str) -> String {
+        std::env::temp_dir().join(format!("bittenoff-{}.ron", name)).to_str().unwrap().to_string()
+    }
+
+    fn area(tile: Tile) -> Area {
+        let (w, h) = DIM;
+        let mut area = Area::new();
+        for i in 0..w * h {
+            let wall = i % 5 == 3;
+            area.tiles.push(TileBlock::new(if wall { Tile::WoodWall } else { tile }, !wall));
+        }
+        area.structures.push(Rect::new((1, 1), (2, 2)));
+        build_nav(&mut area, DIM, DiagonalPolicy::default());
+        area
+    }
+
+    //a person part way through a walk with another goal waiting behind it, and a plant
+    fn entities() -> Vec<EntitySave> {
+        let mut mover = Mover::new(1.5);
+        mover.add_goal(Goal::new(GoalPriority::SimpleIdle as usize, Anchor::new(1, 2, (1, 0, 0)), GoalType::SimpleIdle));
+        mover.add_goal(Goal::new(GoalPriority::MealGoal as usize, Anchor::new(4, 0, (0, 0, 0)), GoalType::MealGoal));
+        mover.set_step_vec(vec![Anchor::new(2, 1, (0, 0, 0)), Anchor::new(3, 0, (0, 0, 0))], 24);
+
+        let need_set: NeedSet = from_str(include_str!("../config/needs.ron")).unwrap();
+        let mut needs = need_set.spawn(&mut ChaCha8Rng::seed_from_u64(3));
+        needs.set_target(Id::new(EntityType::Plant));
+        let mut behaviour = Behaviour::new("resident");
+        behaviour.blackboard().tick(12.5);
+        behaviour.blackboard().set("acting Rest", 4.0);
+        let person = Id::new(EntityType::Person);
+        let mut offscreen = Offscreen::new();
+        offscreen.tick(3.0);
+
+        let resident = Resident::new((0, 0), vec![(0, 0), (1, 0)]);
+
+        let plant = Id::new(EntityType::Plant);
+        vec![
+            EntitySave::capture(&person, &Physical::new((40.0, 72.0), (0, 0, 0)), Some(&mover), Some(&needs), Some(&behaviour), None, None, Some(&resident)),
+            EntitySave::capture(&plant, &Physical::new((100.0, 20.0), (1, 0, 0)), None, None, None, Some(&Plant::new(true, 0.1, 0.6)), Some(&offscreen), None),
+        ]
+    }
+
+    fn saved_map(seed: u64) -> (Map, WorldRng) {
+        let world_rng = WorldRng::new(seed);
+        let mut map = Map::new(DIM.0, DIM.1);
+        map.world_seed = (1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0);
+        map.world_map.insert((0, 0, 0), area(Tile::Grassy));
+        map.world_map.insert((1, 0, 0), area(Tile::Sandy));
+        map.world_map.insert((0, 0, -1), area(Tile::CaveFloor));
+        load_map(&mut map, (0, 0, -1));
+        (map, world_rng)
+    }
+
+    //writes the save and reads it back into a fresh map, rng and player
+    fn round_trip(name: &str, save: &WorldSave) -> (Map, WorldRng, Physical, Vec<EntitySave>) {
+        let path = temp_save(name);
+        write_save(&path, save).unwrap();
+
+        let mut restored_map = Map::new(DIM.0, DIM.1);
+        let mut restored_rng = WorldRng::new(0);
+        let mut restored_player = Physical::new((0.0, 0.0), (0, 0, 0));
+        let restored = read_save(&path).unwrap().restore(&mut restored_map, &mut restored_rng, &mut restored_player).unwrap();
+        let _ = fs::remove_file(&path);
+        (restored_map, restored_rng, restored_player, restored)
+    }
+
+    #[test]
+    fn saves_come_back_as_they_were_written() {
+        let (map, world_rng) = saved_map(11);
+        let player = Physical::new((50.0, 30.0), (0, 0, -1));
+        let save = WorldSave::capture(&map, &world_rng, &player, entities());
+        let (restored_map, restored_rng, restored_player, restored) = round_trip("round-trip", &save);
+
+        assert_eq!(restored_rng.seed(), 11);
+        assert_eq!(restored_map.world_seed, map.world_seed);
+        assert_eq!(restored_map.location, (0, 0, -1));
+        assert_eq!(to_string(&restored_map.world_map).unwrap(), to_string(&map.world_map).unwrap());
+        assert_eq!(to_string(&restored_map.tiles).unwrap(), to_string(&map.tiles).unwrap());
+        assert_eq!(restored_player.get_location(), player.get_location());
+        assert_eq!(restored_player.get_real_position(), player.get_real_position());
+        assert_eq!(to_string(&restored).unwrap(), to_string(&save.entities).unwrap());
+
+        //the goals come back in order, with the walk still under way
+        let mover = restored[0].mover.as_ref().unwrap();
+        assert_eq!(mover.get_goal_type(), GoalType::MealGoal);
+        assert_eq!((mover.path().len(), mover.path_cost()), (2, 24));
+    }
+
+    #[test]
+    fn generated_area_details_come_back() {
+        let (mut map, mut world_rng) = saved_map(13);
+        map.world_seed = roll_world_seed(&mut world_rng);
+        for (location, layer) in [((0, 0, 0), 0), ((0, 0, -1), -1)].iter() {
+            let mut area = area(if *layer < 0 { Tile::CaveFloor } else { Tile::Grassy });
+            area.biomes = vec![layer.abs() as usize; DIM.0 * DIM.1];
+            area.spawn_points.push(SpawnPoint::new((4, 2), EntityType::Person));
+            area.settlement = Some((0, *layer));
+            build_portals(&mut area, DIM);
+            map.world_map.insert(*location, area);
+        }
+        let save = WorldSave::capture(&map, &world_rng, &Physical::new((0.0, 0.0), (0, 0, -1)), Vec::new());
+        let (restored_map, _, _, _) = round_trip("area-details", &save);
+
+        assert_eq!(restored_map.world_seed, map.world_seed);
+        assert_eq!(to_string(&restored_map.world_map).unwrap(), to_string(&map.world_map).unwrap());
+    }
+
+    #[test]
+    fn failed_goals_still_wait_after_a_reload() {
+        let (map, world_rng) = saved_map(17);
+        let mut mover = Mover::new(1.5);
+        mover.add_goal(Goal::new(GoalPriority::RestGoal as usize, Anchor::new(4, 0, (0, 0, 0)), GoalType::RestGoal));
+        mover.fail_goal();
+        let person = Id::new(EntityType::Person);
+        let entities = vec![EntitySave::capture(&person, &Physical::new((40.0, 72.0), (0, 0, 0)), Some(&mover), None, None, None, None, None)];
+        let save = WorldSave::capture(&map, &world_rng, &Physical::new((0.0, 0.0), (0, 0, -1)), entities);
+        let (_, _, _, restored) = round_trip("failed-goal", &save);
+
+        let mover = restored[0].mover.as_ref().unwrap();
+        assert_eq!(mover.get_goal_type(), GoalType::RestGoal);
+        assert!(mover.is_goal_waiting());
+    }
+
+    #[test]
+    fn saves_of_another_version_are_rejected() {
+        let (map, world_rng) = saved_map(5);
+        let mut save = WorldSave::capture(&map, &world_rng, &Physical::new((0.0, 0.0), (0, 0, -1)), Vec::new());
+        save.version = SAVE_VERSION + 1;
+        let path = temp_save("other-version");
+        write_save(&path, &save).unwrap();
+        match read_save(&path) {
+            Err(SaveError::Version(v)) => assert_eq!(v, SAVE_VERSION + 1),
+            _ => panic!("a save of another version was read"),
+        }
+        let _ = fs::remove_file(&path);
+    }
+
+    //a world written by the first save format: areas kept in a list and found by index, locations without a layer
+    //and nobody living in them
+    const FIRST_FORMAT_SAVE: &str = "(version:1,seed:5,world_seed:(0.5,1.5,2.5,3.5,4.5,5.5,6.5,7.5),dim:(6,4),\
+        location:(0,0),area_index:0,world_map:[(tiles:[],anchor_points:[],structures:[],spawned:true,\
+        n:1,e:2,w:3,s:4,nw:5,ne:6,sw:7,se:8)],player:(area_pos:(0,0),real_pos:(50.0,30.0)))";
+
+    #[test]
+    fn saves_in_an_older_format_are_rejected_by_version() {
+        let path = temp_save("older-format");
+        fs::write(save_path(&path).unwrap(), FIRST_FORMAT_SAVE).unwrap();
+        match read_save(&path) {
+            Err(SaveError::Version(v)) => assert_eq!(v, 1),
+            _ => panic!("a save in an older format was read"),
+        }
+        let _ = fs::remove_file(&path);
+    }
+
+    //saves from before a bump can't be read any more, so the version only ever changes on purpose, along with
+    //this test
+    #[test]
+    fn save_version_is_bumped_deliberately() {
+        assert_eq!(SAVE_VERSION, 10);
+    }
+}
@@ -0,0 +1,337 @@
+use amethyst::{
+    core::timing::Time,
+    ecs::prelude::{Entities, Entity, Join, Read, ReadStorage, System, WriteStorage},
+};
+use crate::game_state::{Map, Action};
+use crate::components::{Id, Mover, Needs, NeedSet, Physical, Plant, Resident, Behaviour, BehaviourSet, BehaviourNode, BehaviourStatus, Blackboard};
+use super::needs::{Surroundings, carry_out};
+
+//runs the behaviour tree of everybody who has one, once a frame from the root
+pub struct BehaviourSystem;
+
+impl<'s> System<'s> for BehaviourSystem{
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Behaviour>,
+        WriteStorage<'s, Needs>,
+        WriteStorage<'s, Mover>,
+        ReadStorage<'s, Physical>,
+        ReadStorage<'s, Id>,
+        ReadStorage<'s, Plant>,
+        ReadStorage<'s, Resident>,
+        Read<'s, Map>,
+        Read<'s, NeedSet>,
+        Read<'s, BehaviourSet>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (entities, mut behaviours, mut needs, mut movers, physicals, ids, plants, residents, map, need_set, behaviour_set, time): Self::SystemData) {
+        let around = Surroundings::new(&map, &entities, &physicals, &ids, &needs, &plants);
+
+        for (entity, behaviour, needs, mover, phys, resident) in (&entities, &mut behaviours, &mut needs, &mut movers, &physicals, residents.maybe()).join() {
+            let tree = match behaviour_set.get(behaviour.get_tree()) {
+                Some(t) => t,
+                None => continue,
+            };
+            let board = behaviour.blackboard();
+            board.tick(time.delta_seconds());
+            let mut agent = Agent {
+                entity,
+                needs,
+                mover,
+                phys,
+                resident,
+                board,
+            };
+            tick(tree, &mut agent, &around, &need_set);
+        }
+    }
+}
+
+//the person a tree is being run for
+struct Agent<'a> {
+    entity: Entity,
+    needs: &'a mut Needs,
+    mover: &'a mut Mover,
+    phys: &'a Physical,
+    resident: Option<&'a Resident>,
+    board: &'a mut Blackboard,
+}
+
+fn status(check: bool) -> BehaviourStatus {
+    if check {
+        BehaviourStatus::Success
+    }else{
+        BehaviourStatus::Failure
+    }
+}
+
+//the blackboard key an Act leaf notes its goals under; BehaviourSet::validate keeps each action to one Act a tree,
+//so no two leaves share a key
+fn act_key(action: Action) -> &'static str {
+    match action {
+        Action::Eat => "acting Eat",
+        Action::Rest => "acting Rest",
+        Action::Wander => "acting Wander",
+        Action::Socialize => "acting Socialize",
+        Action::Size => "acting Size",
+    }
+}
+
+//calls off what the Act leaves under a node set going, goals and all; a branch that stops being ticked starts
+//afresh when it's next run
+fn halt(node: &BehaviourNode, agent: &mut Agent) {
+    match node {
+        BehaviourNode::Sequence(children) | BehaviourNode::Selector(children) => children.iter().for_each(|c| halt(c, agent)),
+        BehaviourNode::Invert(child) | BehaviourNode::Succeed(child) | BehaviourNode::Cooldown(_, _, child) => halt(child, agent),
+        BehaviourNode::Act(action) => {
+            let key = act_key(*action);
+            if agent.board.get(key).is_some() {
+                agent.board.clear(key);
+                agent.mover.drop_goals_for(*action);
+            }
+        }
+        _ => {}
+    }
+}
+
+//runs a node and its children; composites start over from their first child every tick, so a branch that stops
+//applying gives way to whichever comes first that does
+fn tick(node: &BehaviourNode, agent: &mut Agent, around: &Surroundings, need_set: &NeedSet) -> BehaviourStatus {
+    match node {
+        BehaviourNode::Sequence(children) => {
+            for (i, child) in children.iter().enumerate() {
+                let s = tick(child, agent, around, need_set);
+                if s != BehaviourStatus::Success {
+                    children[i + 1..].iter().for_each(|c| halt(c, agent));
+                    return s;
+                }
+            }
+            BehaviourStatus::Success
+        }
+        BehaviourNode::Selector(children) => {
+            for (i, child) in children.iter().enumerate() {
+                let s = tick(child, agent, around, need_set);
+                if s != BehaviourStatus::Failure {
+                    children[i + 1..].iter().for_each(|c| halt(c, agent));
+                    return s;
+                }
+            }
+            BehaviourStatus::Failure
+        }
+        BehaviourNode::Invert(child) => match tick(child, agent, around, need_set) {
+            BehaviourStatus::Success => BehaviourStatus::Failure,
+            BehaviourStatus::Failure => BehaviourStatus::Success,
+            BehaviourStatus::Running => BehaviourStatus::Running,
+        },
+        BehaviourNode::Succeed(child) => match tick(child, agent, around, need_set) {
+            BehaviourStatus::Running => BehaviourStatus::Running,
+            _ => BehaviourStatus::Success,
+        },
+        BehaviourNode::Cooldown(key, seconds, child) => {
+            if let Some(until) = agent.board.get(key) {
+                if agent.board.clock() < until {
+                    halt(child, agent);
+                    return BehaviourStatus::Failure;
+                }
+            }
+            let s = tick(child, agent, around, need_set);
+            if s == BehaviourStatus::Success {
+                let until = agent.board.clock() + seconds;
+                agent.board.set(key, until);
+            }
+            s
+        }
+        BehaviourNode::NeedBelow(name, level) => status(agent.needs.level(name) < *level),
+        BehaviourNode::Chosen(action) => {
+            let onscreen = agent.phys.get_location() == around.map().location;
+            status(need_set.choose(agent.needs, |a| onscreen || a == Action::Wander) == Some(*action))
+        }
+        BehaviourNode::HasGoal(gtype) => status(agent.mover.has_goal_type(*gtype)),
+        BehaviourNode::Onscreen => status(agent.phys.get_location() == around.map().location),
+        BehaviourNode::Resident => status(agent.resident.is_some()),
+        BehaviourNode::IsSet(key) => status(agent.board.get(key).is_some()),
+        BehaviourNode::Set(key, value) => {
+            agent.board.set(key, *value);
+            BehaviourStatus::Success
+        }
+        BehaviourNode::Clear(key) => {
+            agent.board.clear(key);
+            BehaviourStatus::Success
+        }
+        //the mover and the rudder see the goals through; the leaf runs for as long as they're at it, noting on the
+        //blackboard that it set them so it can tell when they're done with
+        BehaviourNode::Act(action) => {
+            let key = act_key(*action);
+            if agent.board.get(key).is_some() && !agent.mover.has_goal_for(*action) {
+                agent.board.clear(key);
+                return status(agent.mover.given_up().map(|g| g.action()) != Some(*action));
+            }
+            match carry_out(*action, agent.entity, agent.needs, agent.mover, agent.phys, agent.resident, around) {
+                Some(_) => {
+                    let clock = agent.board.clock();
+                    agent.board.set(key, clock);
+                    BehaviourStatus::Running
+                }
+                None => {
+                    agent.board.clear(key);
+                    BehaviourStatus::Failure
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::ecs::prelude::{Builder, World, WorldExt};
+    use crate::game_state::{Area, GoalType, Tile, TileBlock, MAX_GOAL_FAILURES};
+    use crate::components::{NeedDef, Curve};
+    use crate::worldgen::{DiagonalPolicy, build_nav};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    struct Fixture {
+        entity: Entity,
+        needs: Needs,
+        mover: Mover,
+        phys: Physical,
+        board: Blackboard,
+        need_set: NeedSet,
+        map: Map,
+    }
+
+    impl Fixture {
+        fn new() -> Fixture {
+            let need_set = NeedSet {
+                needs: vec![NeedDef { name: "hunger".to_string(), capacity: 10.0, rate: 1.0, offscreen_relief: 0.0, curve: Curve::Linear }],
+                actions: Vec::new(),
+            };
+            let mut map = Map::new(5, 5);
+            let mut area = Area::new();
+            area.tiles = vec![TileBlock::new(Tile::Plain, true); 25];
+            build_nav(&mut area, (5, 5), DiagonalPolicy::default());
+            map.nav = area.nav;
+
+            Fixture {
+                entity: World::new().create_entity().build(),
+                needs: need_set.spawn(&mut ChaCha8Rng::seed_from_u64(1)),
+                mover: Mover::new(1.0),
+                phys: Physical::new((32.0, 32.0), (0, 0, 0)),
+                board: Blackboard::default(),
+                need_set,
+                map,
+            }
+        }
+        fn tick(&mut self, node: &BehaviourNode) -> BehaviourStatus {
+            let around = Surroundings::empty(&self.map);
+            let mut agent = Agent {
+                entity: self.entity,
+                needs: &mut self.needs,
+                mover: &mut self.mover,
+                phys: &self.phys,
+                resident: None,
+                board: &mut self.board,
+            };
+            tick(node, &mut agent, &around, &self.need_set)
+        }
+    }
+
+    fn is_set(key: &str) -> BehaviourNode {
+        BehaviourNode::IsSet(key.to_string())
+    }
+
+    fn set(key: &str) -> BehaviourNode {
+        BehaviourNode::Set(key.to_string(), 1.0)
+    }
+
+    #[test]
+    fn composites_stop_at_the_first_child_that_settles_them() {
+        let mut f = Fixture::new();
+        assert_eq!(f.tick(&BehaviourNode::Sequence(vec![set("a"), is_set("b"), set("c")])), BehaviourStatus::Failure);
+        assert!(f.board.get("a").is_some() && f.board.get("c").is_none());
+        assert_eq!(f.tick(&BehaviourNode::Selector(vec![is_set("b"), set("b"), set("c")])), BehaviourStatus::Success);
+        assert!(f.board.get("b").is_some() && f.board.get("c").is_none());
+        assert_eq!(f.tick(&BehaviourNode::Invert(Box::new(is_set("b")))), BehaviourStatus::Failure);
+        assert_eq!(f.tick(&BehaviourNode::Succeed(Box::new(is_set("z")))), BehaviourStatus::Success);
+    }
+
+    #[test]
+    fn cooldowns_fail_until_their_time_is_up() {
+        let mut f = Fixture::new();
+        let node = BehaviourNode::Cooldown("c".to_string(), 5.0, Box::new(set("x")));
+        assert_eq!(f.tick(&node), BehaviourStatus::Success);
+        f.board.tick(4.0);
+        assert_eq!(f.tick(&node), BehaviourStatus::Failure);
+        f.board.tick(2.0);
+        assert_eq!(f.tick(&node), BehaviourStatus::Success);
+    }
+
+    #[test]
+    fn need_below_reads_the_need_level() {
+        let mut f = Fixture::new();
+        let node = BehaviourNode::NeedBelow("hunger".to_string(), 0.5);
+        f.needs.fill("hunger");
+        assert_eq!(f.tick(&node), BehaviourStatus::Failure);
+        f.needs.decay(10.0);
+        assert_eq!(f.tick(&node), BehaviourStatus::Success);
+    }
+
+    #[test]
+    fn act_runs_until_its_goal_is_reached_or_given_up() {
+        let mut f = Fixture::new();
+        let node = BehaviourNode::Act(Action::Rest);
+        assert_eq!(f.tick(&node), BehaviourStatus::Running);
+        assert!(f.mover.has_goal_type(GoalType::RestGoal));
+        assert_eq!(f.tick(&node), BehaviourStatus::Running);
+        f.mover.pop_goal();
+        assert_eq!(f.tick(&node), BehaviourStatus::Success);
+
+        assert_eq!(f.tick(&node), BehaviourStatus::Running);
+        for _i in 0..MAX_GOAL_FAILURES {
+            f.mover.fail_goal();
+        }
+        assert_eq!(f.tick(&node), BehaviourStatus::Failure);
+    }
+
+    #[test]
+    fn acts_a_branch_stops_ticking_start_afresh() {
+        let mut f = Fixture::new();
+        let node = BehaviourNode::Selector(vec![
+            BehaviourNode::Sequence(vec![is_set("tired"), BehaviourNode::Act(Action::Rest)]),
+            BehaviourNode::Act(Action::Wander),
+        ]);
+        f.board.set("tired", 1.0);
+        assert_eq!(f.tick(&node), BehaviourStatus::Running);
+        assert!(f.board.get(act_key(Action::Rest)).is_some());
+
+        //the rest is cut short while the condition doesn't hold, so it isn't taken as done once it holds again
+        f.board.clear("tired");
+        assert_eq!(f.tick(&node), BehaviourStatus::Running);
+        assert!(f.board.get(act_key(Action::Rest)).is_none());
+        assert!(!f.mover.has_goal_for(Action::Rest));
+        assert!(f.mover.has_goal_type(GoalType::SimpleIdle));
+        f.board.set("tired", 1.0);
+        assert_eq!(f.tick(&node), BehaviourStatus::Running);
+        assert!(f.mover.has_goal_type(GoalType::RestGoal));
+        assert!(f.board.get(act_key(Action::Wander)).is_none());
+        assert!(!f.mover.has_goal_for(Action::Wander));
+    }
+
+    #[test]
+    fn goals_stay_on_the_layer_underground_people_stand_on() {
+        let mut f = Fixture::new();
+        f.phys = Physical::new((32.0, 32.0), (0, 0, -1));
+        f.map.location = (0, 0, -1);
+        for action in [Action::Rest, Action::Eat, Action::Wander].iter() {
+            for _i in 0..10 {
+                assert_eq!(f.tick(&BehaviourNode::Act(*action)), BehaviourStatus::Running);
+                assert_eq!(f.mover.get_goal().map(|g| g.area().2), Some(-1));
+                f.mover.pop_goal();
+                f.tick(&BehaviourNode::Act(*action));
+            }
+        }
+    }
+}
+
@@ -0,0 +1,73 @@
+use amethyst::{
+    core::timing::Time,
+    ecs::prelude::{Join, Read, ReadStorage, System},
+    input::{InputHandler, StringBindings},
+    utils::application_root_dir,
+};
+use crate::components::{Player, Physical, Id, Behaviour};
+use crate::game_state::{Map, SpawnPoint, EXPORT_DIR};
+use crate::worldgen::export_tmx;
+
+//dumps the current area and the people and plants in it to a Tiled map, people keeping their behaviour trees
+pub struct ExportSystem{
+    pub input_lockout: f32,
+}
+
+impl ExportSystem {
+    pub fn new() -> ExportSystem {
+        ExportSystem {
+            input_lockout: 0.0,
+        }
+    }
+}
+
+impl<'s> System<'s> for ExportSystem{
+    type SystemData = (
+        ReadStorage<'s, Player>,
+        ReadStorage<'s, Physical>,
+        ReadStorage<'s, Id>,
+        ReadStorage<'s, Behaviour>,
+        Read<'s, Map>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (players, physicals, ids, behaviours, map, input, time): Self::SystemData) {
+        self.input_lockout -= time.delta_seconds();
+        if self.input_lockout > 0.0 {
+            return;
+        }
+        self.input_lockout = 0.0;
+
+        if !input.action_is_down("export").unwrap_or(false) {
+            return;
+        }
+        self.input_lockout = 0.5;
+
+        let area = match map.world_map.get(map.location) {
+            Some(a) => a,
+            None => return,
+        };
+
+        let mut entities = Vec::new();
+        for (phys, id, behaviour, ()) in (&physicals, &ids, behaviours.maybe(), !&players).join() {
+            if phys.get_location() == map.location {
+                let mut point = SpawnPoint::new(phys.get_tile_position(), id.get_type());
+                point.behaviour = behaviour.map(|b| b.get_tree().to_string());
+                entities.push(point);
+            }
+        }
+
+        let dir = match application_root_dir() {
+            Ok(root) => root.join(EXPORT_DIR),
+            Err(e) => {
+                println!("failed to export area: {:?}", e);
+                return;
+            }
+        };
+        match export_tmx(&dir, area, map.location, (map.width, map.height), &entities) {
+            Ok(path) => println!("area exported to {:?}", path),
+            Err(e) => println!("failed to export area: {:?}", e),
+        }
+    }
+}
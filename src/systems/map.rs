@@ -6,10 +6,11 @@ use amethyst::{
 };
 use amethyst::ecs::prelude::{Entity, Entities};
 use crate::game_state::{Map, SpriteSheetHandles, SpriteSheetLabel, 
-    Config, Dimensions, KeyCheck, DEFAULT_BASE_SPEED, TILE_SIZE,
-    PLANT_NUM_LOWER, PLANT_NUM_UPPER,
+    Config, Dimensions, KeyCheck, EntityType, DEFAULT_BASE_SPEED, TILE_SIZE,
+    PLANT_NUM_LOWER, PLANT_NUM_UPPER, WorldRng, RngStream,
     spawn_person, spawn_plant};
-use crate::components::{Tile, Mover, Id, Physical, Offscreen, Hunger, Plant};
+use crate::components::{Tile, Mover, Id, Physical, Offscreen, Needs, NeedSet, Behaviour, BehaviourSet, Plant, Resident};
+use crate::worldgen::{BiomeSet, PassGenerator, owning_area};
 
 use rand::Rng;
 
@@ -43,21 +44,51 @@ impl<'s> System<'s> for SpawnSystem{
         WriteStorage<'s, Mover>,
         WriteStorage<'s, Offscreen>,
         WriteStorage<'s, Physical>,
-        WriteStorage<'s, Hunger>,
+        WriteStorage<'s, Needs>,
+        WriteStorage<'s, Behaviour>,
         WriteStorage<'s, Id>,
         WriteStorage<'s, Plant>,
+        WriteStorage<'s, Resident>,
         Entities<'s>,
         Read<'s, SpriteSheetHandles>,
+        Read<'s, WorldRng>,
+        Read<'s, BiomeSet>,
+        Read<'s, NeedSet>,
+        Read<'s, BehaviourSet>,
+        Read<'s, PassGenerator>,
     );
 
-    fn run(&mut self, (mut map, mut trans, mut srs, mut movers, mut offs, mut phys, mut hungs, mut ids, mut plants, mut ents, handles): Self::SystemData) {
+    fn run(&mut self, (mut map, mut trans, mut srs, mut movers, mut offs, mut phys, mut needs, mut behaviours, mut ids, mut plants, mut residents, mut ents, handles, world_rng, biomes, need_set, behaviour_set, generator): Self::SystemData) {
         if !map.spawned && (map.width != 0 && map.height != 0) {
             //spawning plants
 
             if !handles.is_empty() {
 
-                let mut rng = rand::thread_rng();
+                //nothing lives underground yet
+                if map.location.2 != 0 {
+                    map.spawned = true;
+                    return;
+                }
+
+                let mut rng = world_rng.layer_rng(map.location, RngStream::Spawn);
+
+                //hand authored areas place their own people and plants
+                if !map.spawn_points.is_empty() {
+                    for point in map.spawn_points.clone() {
+                        let (cux, cuy) = point.pos;
+                        match point.etype {
+                            EntityType::Person => spawn_person(cux, cuy, map.location, &handles, &mut ents, &mut phys, &mut movers, &mut ids, &mut offs, &mut trans, &mut srs, &mut needs, &need_set, &mut behaviours, &behaviour_set, &mut residents, None, point.behaviour.as_deref(), &mut rng),
+                            EntityType::Plant => spawn_plant(cux, cuy, map.location, &handles, &mut ents, &mut phys, &mut plants, &mut ids, &mut offs, &mut trans, &mut srs, &mut rng),
+                            _ => {}
+                        }
+                    }
+                    map.spawned = true;
+                    return;
+                }
+
                 let plant_num = rng.gen_range(PLANT_NUM_LOWER, PLANT_NUM_UPPER);
+                //lush biomes grow more plants, barren ones fewer
+                let plant_num = (plant_num as f32 * biomes.mean_plant_weight(&map.biomes)).round() as usize;
                 //let plant_num = 1;
                 for _i in 0..plant_num {
                     //let cux = 10;
@@ -102,12 +133,32 @@ impl<'s> System<'s> for SpawnSystem{
                     }
                     
 
-                    spawn_plant(cux, cuy, map.location.0, map.location.1, &handles, &mut ents, &mut phys, &mut plants, &mut ids, &mut offs, &mut trans, &mut srs);
+                    spawn_plant(cux, cuy, map.location, &handles, &mut ents, &mut phys, &mut plants, &mut ids, &mut offs, &mut trans, &mut srs, &mut rng);
                 }
             
+                //settlement buildings each house one resident
+                if let Some(region) = map.settlement {
+                    let dim = (map.width, map.height);
+                    let settlement = generator.settlements().plan(&world_rng, region, dim);
+                    let areas = settlement.as_ref().map(|s| s.areas(dim)).unwrap_or_default();
+                    let buildings = settlement.as_ref().map(|s| s.buildings.clone()).unwrap_or_default();
+                    for building in buildings {
+                        //buildings crossing an edge are carved into every area they touch, but housed only in one
+                        let (area, (cux, cuy)) = owning_area(building.rect.center(), dim);
+                        if area != (map.location.0, map.location.1) || !map.is_passable((cux, cuy)) {
+                            continue;
+                        }
+                        spawn_person(cux, cuy, map.location, &handles, &mut ents, &mut phys, &mut movers, &mut ids, &mut offs, &mut trans, &mut srs, &mut needs, &need_set, &mut behaviours, &behaviour_set, &mut residents, Some(Resident::new(region, areas.clone())), None, &mut rng);
+                    }
+                    map.spawned = true;
+                    return;
+                }
+
                 //spawning persons
                 for rect in map.structures.clone() {
-                    let mut rng = rand::thread_rng();
+                    if rng.gen::<f32>() >= biomes.person_weight(map.biome_at(rect.center())) {
+                        continue;
+                    }
 
                     let mut adx = rng.gen_range(-1, 2);
                     let mut ady = rng.gen_range(-1, 2);
@@ -141,7 +192,7 @@ impl<'s> System<'s> for SpawnSystem{
                     let ax = map.location.0 + adx;
                     let ay = map.location.1 + ady;
 
-                    spawn_person(cux, cuy, ax, ay, &handles, &mut ents, &mut phys, &mut movers, &mut ids, &mut offs, &mut trans, &mut srs, &mut hungs);                
+                    spawn_person(cux, cuy, (ax, ay, map.location.2), &handles, &mut ents, &mut phys, &mut movers, &mut ids, &mut offs, &mut trans, &mut srs, &mut needs, &need_set, &mut behaviours, &behaviour_set, &mut residents, None, None, &mut rng);                
                     
                 }
                 map.spawned = true;
@@ -3,7 +3,7 @@ pub use self::player::MapSystem as PlayerLocalitySystem;
 pub use self::player::ActionSystem as PlayerActionSystem;
 pub use self::mover::MoveSystem;
 pub use self::mover::RudderSystem;
-pub use self::mover::SimpleIdle;
+pub use self::mover::PathfindingSystem;
 pub use self::physical::PhysicalSystem;
 pub use self::fps::CounterSystem;
 pub use self::map::MapSystem;
@@ -13,9 +13,12 @@ pub use self::ui::UiDisplaySystem;
 pub use self::ui::UiControlSystem;
 pub use self::particle::ParticleDisplaySystem;
 pub use self::map::SpawnSystem;
-pub use self::hunger::HungerSystem;
-pub use self::hunger::GoalSystem as HungerGoalSystem;
+pub use self::needs::DecaySystem as NeedsSystem;
+pub use self::needs::GoalSystem as NeedsGoalSystem;
+pub use self::behaviour::BehaviourSystem;
 pub use self::plant::PlantSystem;
+pub use self::save::SaveSystem;
+pub use self::export::ExportSystem;
 mod player;
 mod fps;
 mod mover;
@@ -24,5 +27,8 @@ mod physical;
 mod offscreen;
 mod ui;
 mod particle;
-mod hunger;
-mod plant;
\ No newline at end of file
+mod needs;
+mod behaviour;
+mod plant;
+mod save;
+mod export;
\ No newline at end of file
@@ -1,69 +1,136 @@
 use amethyst::{
     core::transform::Transform,
     core::timing::Time,
-    ecs::prelude::{Join, Read, ReadStorage, System, SystemData, Write, WriteStorage},
+    ecs::prelude::{Entities, Entity, Join, Read, ReadStorage, System, SystemData, Write, WriteStorage},
     input::{InputHandler, StringBindings},
 };
-use crate::game_state::{Config, Map, Anchor, GoalPriority, GoalType, EntityType};
-use crate::components::{Id, Mover, Goal, Physical, Plant, Hunger};
+use std::time::Instant;
+use crate::game_state::{Config, Map, Anchor, PathQueue, Action, GoalType, EntityType, TILE_SIZE, PATH_BUDGET,
+    PERSON_WIDTH, PERSON_HEIGHT, SEPARATION_SPEED, AVOID_WAIT_TIME, DETOUR_LOOKAHEAD, WAYPOINT_RADIUS, ARRIVAL_RADIUS};
+use crate::components::{Id, Mover, Physical, Player, Plant, Needs, NeedSet};
+use crate::worldgen::plan_route;
 
-use pathfinding::prelude::astar;
 use pathfinding::prelude::absdiff;
 
 use angular::atan2;
-use rand::Rng;
+
+//someone who takes up room: where they are and half their footprint, in pixels
+pub(crate) struct Body {
+    entity: Entity,
+    pos: (f32, f32),
+    half: (f32, f32),
+}
+
+impl Body {
+    //the player and people take up room; plants and the like stay where they are and get walked past
+    pub(crate) fn of(entity: Entity, phys: &Physical, player: Option<&Player>, mover: Option<&Mover>) -> Option<Body> {
+        let (w, h) = match (player, mover) {
+            (Some(p), _) => (p.width, p.height),
+            (None, Some(_)) => (PERSON_WIDTH, PERSON_HEIGHT),
+            _ => return None,
+        };
+        Some(Body {
+            entity,
+            pos: phys.get_real_position(),
+            half: ((w * TILE_SIZE) as f32 / 2.0, (h * TILE_SIZE) as f32 / 2.0),
+        })
+    }
+}
+
+fn distance((x, y): (f32, f32), (ox, oy): (f32, f32)) -> f32 {
+    ((x - ox) * (x - ox) + (y - oy) * (y - oy)).sqrt()
+}
+
+fn overlaps((x, y): (f32, f32), half: (f32, f32), body: &Body) -> bool {
+    (x - body.pos.0).abs() < half.0 + body.half.0 && (y - body.pos.1).abs() < half.1 + body.half.1
+}
+
+fn dist2((x, y): (f32, f32), body: &Body) -> f32 {
+    (x - body.pos.0) * (x - body.pos.0) + (y - body.pos.1) * (y - body.pos.1)
+}
+
+//true if moving from pos to next walks further into somebody
+pub(crate) fn walks_into(entity: Entity, pos: (f32, f32), next: (f32, f32), half: (f32, f32), bodies: &[Body]) -> bool {
+    bodies.iter().any(|b| b.entity != entity && overlaps(next, half, b) && dist2(next, b) < dist2(pos, b))
+}
 
 pub struct MoveSystem;
 
 impl<'s> System<'s> for MoveSystem{
     type SystemData = (
+        Entities<'s>,
         WriteStorage<'s, Physical>,
         WriteStorage<'s, Mover>,
+        ReadStorage<'s, Player>,
         Read<'s, Config>,
         Read<'s, Time>,
+        Read<'s, Map>,
     );
 
-    fn run(&mut self, (mut physicals, mut movers, config, time): Self::SystemData) {
+    fn run(&mut self, (entities, mut physicals, mut movers, players, config, time, map): Self::SystemData) {
+        //everyone walking around the area on screen, as they stood at the start of the frame
+        let bodies: Vec<Body> = (&entities, &physicals, players.maybe(), movers.maybe()).join()
+            .filter(|(_, phys, _, _)| phys.get_location() == map.location)
+            .filter_map(|(entity, phys, player, mover)| Body::of(entity, phys, player, mover))
+            .collect();
+        let dt = time.delta_seconds();
+
         //println!("moving!");
-        for (mover, phys) in (&mut movers, &mut physicals).join(){
+        for (entity, mover, phys) in (&entities, &mut movers, &mut physicals).join(){
             //setting position to latest move step
             //println!("next move is {:?}", mover.get_move());
             match mover.get_step() {
                 Some(a) => {
                     //println!("next move: {:?} curr goal: {:?}", a.pos, mover.get_goal());
                     if a.area() == phys.get_location() {
-                        if a.local() == phys.get_tile_position() {
+                        //waypoints along the way count as passed a little early so corners get rounded off, the last one
+                        //has to be reached; a path starts on the tile the mover stands on, wherever on it that is
+                        let mut a = a;
+                        let radius = if mover.steps_left() > 1 { WAYPOINT_RADIUS } else { ARRIVAL_RADIUS };
+                        let starting = mover.last_step().is_none() && a.local() == phys.get_tile_position();
+                        if distance(phys.get_real_position(), a.real_local()) <= radius || starting {
                             //println!("reached goal successfully!");
-                            mover.pop_step();
+                            //the first step of a path is the tile it starts on, which costs nothing;
+                            //the map only knows the tiles of the area on screen
+                            let cost = match mover.last_step() {
+                                Some(prev) if a.area() == map.location => map.step_cost(prev, a.local()),
+                                Some(_) => 10,
+                                None => 0,
+                            };
+                            mover.pop_step(cost);
+                            //on to the next waypoint in the same frame, so walking doesn't stutter
+                            match mover.get_step() {
+                                Some(next) if next.area() == phys.get_location() => a = next,
+                                _ => continue,
+                            }
                         }
                         let (ox, oy) = phys.get_real_position();
                         let (ex, ey) = a.real_local();
-                        if ox < ex {
-                            let mut hor_mv = mover.speed() * time.delta_seconds();
-                            if hor_mv > ex - ox {
-                                hor_mv = ex - ox;
-                            }
-                            phys.mut_x(hor_mv); 
-                        }else if ox > ex {
-                            let mut hor_mv = -mover.speed() * time.delta_seconds();
-                            if hor_mv < ex - ox {
-                                hor_mv = ex - ox;
-                            }
-                            phys.mut_x(hor_mv);
+                        let (dx, dy) = (ex - ox, ey - oy);
+                        let dist = distance((ox, oy), (ex, ey));
+                        if dist == 0.0 {
+                            continue;
                         }
-                        if oy < ey {
-                            let mut ver_mv = mover.speed() * time.delta_seconds();
-                            if ver_mv > ey - oy {
-                                ver_mv = ey - oy;
-                            }
-                            phys.mut_y(ver_mv);
-                        }else if oy > ey {
-                            let mut ver_mv = -mover.speed() * time.delta_seconds();
-                            if ver_mv < ey - oy {
-                                ver_mv = ey - oy;
-                            }
+
+                        //rough ground underfoot is slower, at the same rate it costs more in a path
+                        let mut speed = mover.speed();
+                        if a.area() == map.location {
+                            speed = speed * 10.0 / map.move_cost(phys.get_tile_position()) as f32;
+                        }
+                        //straight at the waypoint, at the same speed whichever way that is
+                        let travel = (speed * dt).min(dist);
+                        let (hor_mv, ver_mv) = (dx / dist * travel, dy / dist * travel);
+                        phys.set_heading(atan2(dy, dx).in_radians());
+
+                        //nobody else is drawn in other areas, so there's nobody to avoid there
+                        if phys.get_location() != map.location {
+                            phys.mut_x(hor_mv);
                             phys.mut_y(ver_mv);
+                            continue;
                         }
+                        let (hor_mv, ver_mv) = avoid(entity, mover, (ox, oy), (hor_mv, ver_mv), &bodies, &map, dt);
+                        phys.mut_x(hor_mv);
+                        phys.mut_y(ver_mv);
                     }else{
                         println!("wrong area, recalculate path");
                         mover.clear_step_vec();
@@ -72,12 +139,107 @@ impl<'s> System<'s> for MoveSystem{
                 None => {
                     //println!("change goal, no moves detected");
                     //mover.pop_goal();
+                    //people standing around still make room for each other
+                    if phys.get_location() == map.location {
+                        let (px, py) = avoid(entity, mover, phys.get_real_position(), (0.0, 0.0), &bodies, &map, dt);
+                        phys.mut_x(px);
+                        phys.mut_y(py);
+                    }
                 }
             }
         }
     }
 }
 
+//bends a mover's next move around the people near it: overlapping people drift apart, anyone in the way
+//gets passed on the right or the left, and if neither works the mover waits, then walks around them
+fn avoid(entity: Entity, mover: &mut Mover, pos: (f32, f32), (mx, my): (f32, f32), bodies: &[Body], map: &Map, dt: f32) -> (f32, f32) {
+    let half = ((PERSON_WIDTH * TILE_SIZE) as f32 / 2.0, (PERSON_HEIGHT * TILE_SIZE) as f32 / 2.0);
+    let open = |(x, y): (f32, f32)| x >= 0.0 && y >= 0.0 && map.is_passable(Physical::into_tile_position((x, y)));
+
+    //people standing on each other get pushed apart, the one with the lower id giving way when they're exactly on top
+    let (mut px, mut py) = (0.0, 0.0);
+    for body in bodies.iter().filter(|b| b.entity != entity && overlaps(pos, half, b)) {
+        let (dx, dy) = (pos.0 - body.pos.0, pos.1 - body.pos.1);
+        let d = (dx * dx + dy * dy).sqrt();
+        if d > 0.0 {
+            px += dx / d * SEPARATION_SPEED * dt;
+            py += dy / d * SEPARATION_SPEED * dt;
+        }else if entity.id() < body.entity.id() {
+            px += SEPARATION_SPEED * dt;
+        }
+    }
+    if !open((pos.0 + px, pos.1 + py)) {
+        px = 0.0;
+        py = 0.0;
+    }
+
+    //straight on first, then turning right, so two people meeting head on both step the same way round
+    let angles = [0.0f32, -45.0, 45.0, -90.0, 90.0];
+    for (i, angle) in angles.iter().enumerate() {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        let (tx, ty) = (mx * cos - my * sin, mx * sin + my * cos);
+        let next = (pos.0 + tx + px, pos.1 + ty + py);
+        //turning aside mustn't walk into a wall; the path itself is known to be clear
+        if walks_into(entity, pos, next, half, bodies) || (i > 0 && !open(next)) {
+            continue;
+        }
+        if i == 0 {
+            mover.reset_blocked_time();
+        }
+        return (tx + px, ty + py);
+    }
+
+    mover.add_blocked_time(dt);
+    if mover.blocked_time() >= AVOID_WAIT_TIME {
+        mover.reset_blocked_time();
+        detour(mover, pos, bodies, entity, map);
+    }
+    (px, py)
+}
+
+//finds a way from where the mover stands to a step a little further along its path that keeps off everybody's
+//tiles; with no such way the path is dropped and the rudder plans again
+fn detour(mover: &mut Mover, pos: (f32, f32), bodies: &[Body], entity: Entity, map: &Map) {
+    let path = mover.path();
+    let start = Physical::into_tile_position(pos);
+    let occupied: Vec<usize> = bodies.iter()
+        .filter(|b| b.entity != entity)
+        .map(|b| Physical::into_tile_position(b.pos))
+        .filter(|t| map.nav.has_tile(*t))
+        .map(|t| map.nav.node(t))
+        .collect();
+
+    //the furthest step within reach that's a tile of this area nobody stands on
+    let rejoin = (0..path.len().min(DETOUR_LOOKAHEAD + 1)).rev().find(|i| {
+        let (x, y) = path[*i].local();
+        path[*i].area() == map.location && map.nav.has_tile((x, y)) && !occupied.contains(&map.nav.node((x, y)))
+    });
+    let rejoin = match rejoin {
+        Some(i) => i,
+        None => {
+            mover.clear_step_vec();
+            return;
+        }
+    };
+
+    match map.nav.find_path_avoiding(map.nav.node(start), map.nav.node(path[rejoin].local()), |n| occupied.contains(&n)) {
+        Some((nodes, cost)) => {
+            //what the replaced steps would have cost, so the rest of the path keeps its cost
+            let mut replaced = 0;
+            let mut prev = start;
+            for step in path[..=rejoin].iter() {
+                replaced += map.step_cost(prev, step.local());
+                prev = step.local();
+            }
+            let steps = nodes.into_iter().skip(1).map(|n| map.nav.anchor(n, map.location)).collect();
+            let rest = mover.path_cost().saturating_sub(replaced);
+            mover.replace_steps(rejoin + 1, steps, rest + cost);
+        }
+        None => mover.clear_step_vec(),
+    }
+}
+
 pub struct RudderSystem;
 
 impl<'s> System<'s> for RudderSystem{
@@ -85,15 +247,23 @@ impl<'s> System<'s> for RudderSystem{
         WriteStorage<'s, Physical>,
         WriteStorage<'s, Mover>,
         ReadStorage<'s, Id>,
-        WriteStorage<'s, Hunger>,
+        WriteStorage<'s, Needs>,
         WriteStorage<'s, Plant>,
         Read<'s, Config>,
         Read<'s, Map>,
+        Entities<'s>,
+        Write<'s, PathQueue>,
+        Read<'s, Time>,
+        Read<'s, NeedSet>,
     );
 
-    fn run(&mut self, (mut physicals, mut movers, ids, mut hungs, mut plants, config, map): Self::SystemData) {
-        for (mover, phys, id) in (&mut movers, &mut physicals, &ids).join(){
-            if mover.is_step_vec_empty() && map.location == phys.get_location() {
+    fn run(&mut self, (mut physicals, mut movers, ids, mut needs, mut plants, config, map, entities, mut queue, time, need_set): Self::SystemData) {
+        for (entity, mover, phys, _id) in (&entities, &mut movers, &mut physicals, &ids).join(){
+            //goals only age where the rudder works on them; offscreen trips are settled whole by PhysicalSystem
+            if map.location == phys.get_location() {
+                mover.tick_goal(time.delta_seconds());
+            }
+            if mover.is_step_vec_empty() && !mover.is_path_pending() && !mover.is_goal_waiting() && map.location == phys.get_location() {
                 match mover.get_goal() {
                     None => {}
                     Some(goal) => {    
@@ -102,57 +272,21 @@ impl<'s> System<'s> for RudderSystem{
                         
                         //println!("phys {:?} goal {:?}", (phys.get_tile_position(), phys.get_location()), (goal.local(), goal.area()));
                         if (x, y) != goal.local() && phys.get_location() == goal.area() {
-
-                            let origin = map.anchor_points[x + y * map.width + 4].clone();
-                            //println!("anchor points are currently: {:?}", map.anchor_points);
-                            let path = astar(&origin, |p| {let successors: Vec<(Anchor, usize)> = (*p).succ.to_vec().into_iter().map(|val| (map.anchor_points[val.0].clone(), val.1)).collect(); successors},
-                            |p| (((*p).pos.0 as i32 - goal.pos.0 as i32).abs() + ((*p).pos.1 as i32 - goal.pos.1 as i32).abs()) as usize / 3,
-                            |p| *p == goal);
-                            //println!("path calculated!");
-                            match path {
-                                Some((v, c)) => {
-                                    //println!("path cost is {}", c);
-                                    //println!("same area: path is {:?}", v);
-                                    mover.set_step_vec(v, c);
-                                }
-                                None => {
-                                    if map.location == phys.get_location() {
-                                        println!("same area no path found! from {:?} to {:?}", (x, y, phys.get_location().0, phys.get_location().1), goal);
-                                        mover.pop_goal();
-                                    }
-                                }
-                            }
+                            queue.submit(entity, mover.get_goal_priority(), map.location, (x, y), goal);
+                            mover.set_path_pending(true);
                         }else if phys.get_tile_position() == goal.local() && phys.get_location() == goal.area() {
                             //println!("reached goal successfully; goaltype {:?}", mover.get_goal_type());
                             match mover.get_goal_type() {
-                                GoalType::SimpleIdle => {
-
-                                }
-                                GoalType::Size => {
-
-                                }
-                                GoalType::MealSearch => {
-
-                                }
                                 GoalType::MealGoal => {
-                                    
                                     let mut meal = Id::nil();
-                                    
-                                    
-                                    for (sid, hung) in (&ids, &mut hungs).join() {
-                                        if sid.get_uuid() == id.get_uuid() {
-                                            //getting meal id
-                                            meal = hung.get_meal_id();
-
-
-                                            hung.set_meal_id(Id::nil());
-                                            //become full
-                                            println!("becoming full");
-                                            hung.set_hunger(hung.get_capacity());
-                                            break;
-                                        }
+                                    if let Some(n) = needs.get_mut(entity) {
+                                        meal = n.get_target();
+                                        n.set_target(Id::nil());
+                                        //become full
+                                        println!("becoming full");
+                                        n.satisfy(&need_set, Action::Eat);
                                     }
-                                    
+
                                     if meal.get_type() == EntityType::Plant { 
                                         for (pid, plant) in (&ids, &mut plants).join() {
                                             //println!("pid {} meal_id {}", pid.get_uuid(), meal_id);
@@ -164,66 +298,38 @@ impl<'s> System<'s> for RudderSystem{
                                             }       
                                         }
                                     }
-                                    
                                 }
+                                GoalType::RestGoal => {
+                                    if let Some(n) = needs.get_mut(entity) {
+                                        n.satisfy(&need_set, Action::Rest);
+                                    }
+                                }
+                                GoalType::SocialGoal => {
+                                    let mut partner = Id::nil();
+                                    if let Some(n) = needs.get_mut(entity) {
+                                        partner = n.get_target();
+                                        n.set_target(Id::nil());
+                                        n.satisfy(&need_set, Action::Socialize);
+                                    }
+                                    //whoever they came to see gets the company too
+                                    if partner.get_type() == EntityType::Person {
+                                        for (pid, n) in (&ids, &mut needs).join() {
+                                            if partner.get_uuid() == pid.get_uuid() {
+                                                n.satisfy(&need_set, Action::Socialize);
+                                                break;
+                                            }
+                                        }
+                                    }
+                                }
+                                _ => {}
                             }
                             mover.pop_goal();
                         }else if goal.area() != phys.get_location() {
                             //println!("not same area, sending to other area");
                             //println!("not same area goal is {:?}", goal);
-
-                            let (cax, cay) = phys.get_location();
-                            let (gax, gay) = goal.area();
-
-                            let mut goal = Anchor::new(0, 0, 0, 0);
-                            
-                            /*
-                            area.anchor_points.push(west);
-                            area.anchor_points.push(east);
-                            area.anchor_points.push(north);
-                            area.anchor_points.push(south);
-                            */
-                            /*if map.location == phys.get_location() {
-                                println!("curr locale: {:?} need to get to: {:?}", (cax, cay), (gax, gay));
-                            }*/
-                            if cax < gax {
-                                //east
-                                //println!("go east");
-                                goal = map.anchor_points[1].clone();
-                            }else if cax > gax {
-                                //west
-                               //println!("go west");
-                                goal = map.anchor_points[0].clone();
-                            }else if cay < gay {
-                                //north
-                                //println!("go north");
-                                goal = map.anchor_points[2].clone();
-                            }else if cay > gay {
-                                //south
-                                //println!("go south");
-                                goal = map.anchor_points[3].clone();
-                            }
-
-                            //println!("goal anchor is {:?}", goal);
-
-                            let origin = map.anchor_points[x + y * map.width + 4].clone();
-                            let path = astar(&origin, |p| {let successors: Vec<(Anchor, usize)> = (*p).succ.to_vec().into_iter().map(|val| (map.anchor_points[val.0].clone(), val.1)).collect(); successors},
-                            |p| (((*p).pos.0 as i32 - goal.pos.0 as i32).abs() + ((*p).pos.1 as i32 - goal.pos.1 as i32).abs()) as usize / 3,
-                            |p| *p == goal);
-                            //println!("path calculated!");
-                            match path {
-                                Some((v, c)) => {
-                                    //println!("path cost is {}", c);
-                                    //println!("change area: path is {:?}", v);
-                                    mover.set_step_vec(v, c);
-                                }
-                                None => {
-                                    if phys.get_location() == map.location {
-                                        //println!("change area no path found! from {:?} to {:?}", (x, y, phys.get_location().0, phys.get_location().1), goal);
-                                        mover.pop_goal();
-                                    }
-                                }
-                            }  
+                            //people never take the stairs, so their goals are all on the layer they stand on
+                            queue.submit(entity, mover.get_goal_priority(), map.location, (x, y), goal);
+                            mover.set_path_pending(true);
                         }
                     }
                 }
@@ -232,49 +338,388 @@ impl<'s> System<'s> for RudderSystem{
     }
 }
 
-pub struct SimpleIdle;
+//works through the path queue until the frame's budget is spent, handing each path to the mover that asked
+pub struct PathfindingSystem;
 
-impl<'s> System<'s> for SimpleIdle{
+impl<'s> System<'s> for PathfindingSystem{
     type SystemData = (
         ReadStorage<'s, Physical>,
         WriteStorage<'s, Mover>,
-        Read<'s, Config>,
+        Write<'s, PathQueue>,
         Read<'s, Map>,
     );
 
-    fn run(&mut self, (physes, mut movers, config, map): Self::SystemData) {
-        for (mover, phys) in (&mut movers, &physes).join(){
-            
-            match mover.get_goal() {
-                None => {
-                    let mut rng = rand::thread_rng();
-                    
-                    let mut ax = rng.gen_range(-1, 2);
-                    let mut ay = rng.gen_range(-1, 2);
-
-                    if rng.gen::<f32>() > 0.2 {
-                        ax = 0;
-                        ay = 0;
-                    }
+    fn run(&mut self, (physicals, mut movers, mut queue, map): Self::SystemData) {
+        serve_paths(&physicals, &mut movers, &mut queue, &map, PATH_BUDGET);
+    }
+}
 
-                    let gx = rng.gen_range(0, map.width);
-                    let gy = rng.gen_range(0, map.height);
+//works through the queue until the budget in seconds is spent; the first request is always served
+fn serve_paths(physicals: &ReadStorage<Physical>, movers: &mut WriteStorage<Mover>, queue: &mut PathQueue, map: &Map, budget: f32) {
+    let started = Instant::now();
+    while let Some(request) = queue.pop() {
+        let (mover, phys) = match (movers.get_mut(request.entity), physicals.get(request.entity)) {
+            (Some(m), Some(p)) => (m, p),
+            _ => continue,
+        };
+        mover.set_path_pending(false);
 
-                    let index = gx + gy * map.width;
-                    //if (ax, ay) == map.location {
-                    if index < map.width * map.height && index < map.anchor_points.len() {
-                        //println!("adding goal {:?}", map.anchor_points[gx + gy * map.width].pos);
-                        let mut dest_anchor = map.anchor_points[gx + gy * map.width + 4].clone();
-                        dest_anchor.set_area((ax, ay));
+        //the player changed area, or the mover moved or changed its mind while it waited; it asks again
+        let stale = request.location != map.location || phys.get_location() != request.location
+            || phys.get_tile_position() != request.start || mover.get_goal() != Some(request.goal);
+        if !stale {
+            match find_steps(map, request.start, request.goal) {
+                Some((steps, cost)) => mover.set_step_vec(steps, cost),
+                None => mover.fail_goal(),
+            }
+        }
 
-                        //println!("destination anchor {:?}", dest_anchor);
-                        
-                        
-                        mover.add_goal( Goal::new(GoalPriority::SimpleIdle as usize, dest_anchor, GoalType::SimpleIdle) );
+        if started.elapsed().as_secs_f32() >= budget {
+            break;
+        }
+    }
+}
+
+//steps from start to the goal, or to the edge where the route to a goal in another area leaves this one
+fn find_steps(map: &Map, (x, y): (usize, usize), goal: Anchor) -> Option<(Vec<Anchor>, usize)> {
+    if goal.area() == map.location {
+        let path = map.nav.find_path(map.nav.node((x, y)), map.nav.node(goal.local()));
+        //println!("path calculated!");
+        match path {
+            Some((v, c)) => {
+                //println!("path cost is {}", c);
+                //println!("same area: path is {:?}", v);
+                Some((smooth_path(map, v.into_iter().map(|n| map.nav.anchor(n, map.location)).collect()), c))
+            }
+            None => {
+                println!("same area no path found! from {:?} to {:?}", (x, y, map.location), goal);
+                None
+            }
+        }
+    }else{
+        //the route over the areas in between decides where to leave this one
+        let crossing = match plan_route(&map.world_map, (map.width, map.height), map.location, (x, y), goal) {
+            Some(c) => c,
+            None => {
+                println!("no route from {:?} to {:?}", (x, y, map.location), goal);
+                return None;
+            }
+        };
+        //println!("leaving by {:?}", crossing);
+
+        let path = map.nav.find_path(map.nav.node((x, y)), map.nav.node(crossing.tile));
+        //println!("path calculated!");
+        match path {
+            Some((v, c)) => {
+                //println!("path cost is {}", c);
+                //println!("change area: path is {:?}", v);
+                let mut steps = smooth_path(map, v.into_iter().map(|n| map.nav.anchor(n, map.location)).collect());
+                steps.push(crossing.exit(map.location, (map.width, map.height)));
+                Some((steps, c + 10))
+            }
+            None => {
+                //println!("change area no path found! from {:?} to {:?}", (x, y, map.location), crossing);
+                None
+            }
+        }
+    }
+}
+
+//string pulling: drops every waypoint the mover can walk past in a straight line from the one before. a
+//shortcut has to stay clear of walls and can't cross ground rougher than the steps it replaces, so paths
+//still keep to roads
+fn smooth_path(map: &Map, steps: Vec<Anchor>) -> Vec<Anchor> {
+    if steps.len() < 3 {
+        return steps;
+    }
+    let mut out = vec![steps[0]];
+    let mut from = 0;
+    for i in 1..steps.len() - 1 {
+        let next = steps[i + 1];
+        let (nx, ny) = next.local();
+        let inside = nx < map.width && ny < map.height;
+        let roughest = steps[from..=i + 1].iter().map(|s| map.move_cost(s.local())).max().unwrap_or(10);
+        if !inside || !clear_line(map, steps[from].real_local(), next.real_local(), roughest) {
+            out.push(steps[i]);
+            from = i;
+        }
+    }
+    out.push(steps[steps.len() - 1]);
+    out
+}
+
+//true if a body walking from one point to another keeps to walkable tiles no rougher than max_cost; a quarter
+//tile to either side of the line gets checked too, so it doesn't clip the corner of a wall, and passing that close
+//to a tile corner has to be a diagonal step the nav graph allows
+fn clear_line(map: &Map, (x0, y0): (f32, f32), (x1, y1): (f32, f32), max_cost: usize) -> bool {
+    let clearance = TILE_SIZE as f32 / 4.0;
+    let samples = (distance((x0, y0), (x1, y1)) / 2.0).ceil() as usize;
+    let steps_to = |from: (usize, usize), to: (usize, usize)| {
+        map.nav.successors(map.nav.node(from)).iter().any(|(n, _)| *n == map.nav.node(to))
+    };
+    for i in 0..=samples {
+        let t = if samples == 0 { 0.0 } else { i as f32 / samples as f32 };
+        let (x, y) = (x0 + (x1 - x0) * t, y0 + (y1 - y0) * t);
+        if x - clearance < 0.0 || y - clearance < 0.0 {
+            return false;
+        }
+        let (low, high) = (Physical::into_tile_position((x - clearance, y - clearance)), Physical::into_tile_position((x + clearance, y + clearance)));
+        for tile in [low, (high.0, low.1), (low.0, high.1), high].iter() {
+            if !map.is_passable(*tile) || map.move_cost(*tile) > max_cost {
+                return false;
+            }
+        }
+        if low.0 != high.0 && low.1 != high.1 && (!steps_to(low, high) || !steps_to((high.0, low.1), (low.0, high.1))) {
+            return false;
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amethyst::ecs::prelude::{Builder, World, WorldExt};
+    use crate::game_state::{GoalPriority, load_map};
+    use crate::components::Goal;
+    use crate::worldgen::{DiagonalPolicy, area_from_rows};
+
+    //the middle of a tile, in pixels
+    fn centre((x, y): (usize, usize)) -> (f32, f32) {
+        let half = TILE_SIZE as f32 / 2.0;
+        ((x * TILE_SIZE) as f32 + half, (y * TILE_SIZE) as f32 + half)
+    }
+
+    fn map(rows: &[&str], diagonals: DiagonalPolicy) -> Map {
+        let mut map = Map::new(rows[0].len(), rows.len());
+        map.world_map.insert((0, 0, 0), area_from_rows(rows, diagonals));
+        load_map(&mut map, (0, 0, 0));
+        map
+    }
+
+    fn open_map() -> Map {
+        map(&["......", "......", "......", "......"], DiagonalPolicy::default())
+    }
+
+    fn world() -> World {
+        let mut world = World::new();
+        world.register::<Physical>();
+        world.register::<Mover>();
+        world
+    }
+
+    //somebody standing on a tile who has asked for a path to the goal
+    fn asking(world: &mut World, queue: &mut PathQueue, map: &Map, tile: (usize, usize), goal: (usize, usize)) -> Entity {
+        let goal = Anchor::new(goal.0, goal.1, map.location);
+        let mut mover = Mover::new(1.0);
+        mover.add_goal(Goal::new(GoalPriority::SimpleIdle as usize, goal, GoalType::SimpleIdle));
+        mover.set_path_pending(true);
+        let entity = world.create_entity().with(Physical::new(centre(tile), map.location)).with(mover).build();
+        queue.submit(entity, GoalPriority::SimpleIdle as usize, map.location, tile, goal);
+        entity
+    }
+
+    fn serve(world: &mut World, queue: &mut PathQueue, map: &Map, budget: f32) {
+        let (physicals, mut movers) = world.system_data::<(ReadStorage<Physical>, WriteStorage<Mover>)>();
+        serve_paths(&physicals, &mut movers, queue, map, budget);
+    }
+
+    #[test]
+    fn stale_requests_are_dropped_without_steps() {
+        let (mut world, mut queue, map) = (world(), PathQueue::default(), open_map());
+        let moved = asking(&mut world, &mut queue, &map, (0, 0), (5, 3));
+        let changed_mind = asking(&mut world, &mut queue, &map, (1, 0), (5, 3));
+        world.write_storage::<Physical>().get_mut(moved).unwrap().mut_x(TILE_SIZE as f32);
+        world.write_storage::<Mover>().get_mut(changed_mind).unwrap()
+            .add_goal(Goal::new(GoalPriority::MealGoal as usize, Anchor::new(0, 3, map.location), GoalType::MealGoal));
+
+        serve(&mut world, &mut queue, &map, 1.0);
+        let movers = world.read_storage::<Mover>();
+        for entity in [moved, changed_mind].iter() {
+            let mover = movers.get(*entity).unwrap();
+            assert!(!mover.is_path_pending());
+            assert!(mover.is_step_vec_empty());
+        }
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn a_spent_budget_still_serves_one_request() {
+        let (mut world, mut queue, map) = (world(), PathQueue::default(), open_map());
+        let first = asking(&mut world, &mut queue, &map, (0, 0), (5, 3));
+        let second = asking(&mut world, &mut queue, &map, (0, 3), (5, 0));
+
+        serve(&mut world, &mut queue, &map, 0.0);
+        {
+            let movers = world.read_storage::<Mover>();
+            let mover = movers.get(first).unwrap();
+            assert!(!mover.is_path_pending());
+            assert_eq!(mover.path().last().map(|a| a.local()), Some((5, 3)));
+            assert!(movers.get(second).unwrap().is_path_pending());
+        }
+        assert_eq!(queue.len(), 1);
+
+        serve(&mut world, &mut queue, &map, 0.0);
+        assert!(!world.read_storage::<Mover>().get(second).unwrap().is_step_vec_empty());
+        assert!(queue.is_empty());
+    }
+
+    fn body(entity: Entity, tile: (usize, usize)) -> Body {
+        let half = TILE_SIZE as f32 / 2.0;
+        Body { entity, pos: centre(tile), half: (half, half) }
+    }
+
+    fn path_to(mover: &mut Mover, map: &Map, from: (usize, usize), tiles: &[(usize, usize)]) {
+        let mut cost = 0;
+        let mut prev = from;
+        for tile in tiles.iter() {
+            cost += map.step_cost(prev, *tile);
+            prev = *tile;
+        }
+        mover.set_step_vec(tiles.iter().map(|t| Anchor::new(t.0, t.1, map.location)).collect(), cost);
+    }
+
+    #[test]
+    fn walking_into_somebody_only_counts_when_getting_closer() {
+        let mut world = World::new();
+        let (me, other) = (world.create_entity().build(), world.create_entity().build());
+        let bodies = [body(other, (2, 1)), body(me, (1, 1))];
+        let half = bodies[0].half;
+
+        assert!(walks_into(me, (16.0, 24.0), (26.0, 24.0), half, &bodies));
+        //already overlapping but stepping back out, or not touching at all
+        assert!(!walks_into(me, (26.0, 24.0), (24.0, 24.0), half, &bodies));
+        assert!(!walks_into(me, (8.0, 24.0), (12.0, 24.0), half, &bodies));
+        //nobody walks into themselves
+        assert!(!walks_into(other, (26.0, 24.0), (30.0, 24.0), half, &bodies));
+    }
+
+    #[test]
+    fn movers_slide_past_somebody_standing_in_the_way() {
+        let map = open_map();
+        let mut world = World::new();
+        let (me, other) = (world.create_entity().build(), world.create_entity().build());
+        let bodies = [body(other, (1, 1))];
+        let mut mover = Mover::new(1.0);
+        let mut pos = centre((0, 1));
+
+        for _ in 0..40 {
+            let (mx, my) = avoid(me, &mut mover, pos, (2.0, 0.0), &bodies, &map, 0.1);
+            assert!((mx * mx + my * my).sqrt() > 1.0);
+            pos = (pos.0 + mx, pos.1 + my);
+            assert!(!overlaps(pos, bodies[0].half, &bodies[0]));
+            assert!(map.is_passable(Physical::into_tile_position(pos)));
+        }
+        assert!(pos.0 > bodies[0].pos.0 + TILE_SIZE as f32);
+        assert_eq!(mover.blocked_time(), 0.0);
+    }
+
+    #[test]
+    fn boxed_in_movers_wait_then_walk_around() {
+        let map = open_map();
+        let mut world = World::new();
+        let me = world.create_entity().build();
+        let bodies: Vec<Body> = [(1, 1), (0, 2), (0, 0)].iter().map(|t| body(world.create_entity().build(), *t)).collect();
+        let mut mover = Mover::new(1.0);
+        path_to(&mut mover, &map, (0, 1), &[(1, 1), (2, 1), (3, 1), (4, 1), (5, 1)]);
+        let pos = centre((0, 1));
+
+        let mut waited = 0.0;
+        while mover.path().contains(&Anchor::new(1, 1, map.location)) {
+            assert_eq!(avoid(me, &mut mover, pos, (1.0, 0.0), &bodies, &map, 0.1), (0.0, 0.0));
+            waited += 0.1;
+            assert!(waited < AVOID_WAIT_TIME + 0.15);
+        }
+        assert!(waited >= AVOID_WAIT_TIME - 0.05);
+        assert_eq!(mover.blocked_time(), 0.0);
+    }
+
+    #[test]
+    fn detours_keep_off_occupied_tiles_and_rejoin_the_path() {
+        let map = open_map();
+        let mut world = World::new();
+        let me = world.create_entity().build();
+        let bodies = [body(world.create_entity().build(), (2, 1)), body(me, (0, 1))];
+        let mut mover = Mover::new(1.0);
+        path_to(&mut mover, &map, (0, 1), &[(1, 1), (2, 1), (3, 1), (4, 1), (5, 1), (5, 2)]);
+
+        detour(&mut mover, centre((0, 1)), &bodies, me, &map);
+        let path: Vec<(usize, usize)> = mover.path().iter().map(|a| a.local()).collect();
+        assert!(!path.contains(&(2, 1)));
+        assert_eq!(path.last(), Some(&(5, 2)));
+        //the rest of the path is unchanged, and the cost still adds up
+        assert_eq!(&path[path.len() - 2..], &[(5, 1), (5, 2)]);
+        let mut cost = 0;
+        let mut prev = (0, 1);
+        for tile in path.iter() {
+            cost += map.step_cost(prev, *tile);
+            prev = *tile;
+        }
+        assert_eq!(mover.path_cost(), cost);
+    }
+
+    #[test]
+    fn detours_give_up_the_path_when_nobody_can_get_by() {
+        let map = map(&["######", "......", "######"], DiagonalPolicy::default());
+        let mut world = World::new();
+        let me = world.create_entity().build();
+        let bodies = [body(world.create_entity().build(), (2, 1))];
+        let mut mover = Mover::new(1.0);
+        path_to(&mut mover, &map, (0, 1), &[(1, 1), (2, 1), (3, 1), (4, 1)]);
+
+        detour(&mut mover, centre((0, 1)), &bodies, me, &map);
+        assert!(mover.is_step_vec_empty());
+    }
+
+    //every tile the middle of a body crosses between two points, in order
+    fn tiles_crossed(from: (f32, f32), to: (f32, f32)) -> Vec<(usize, usize)> {
+        let samples = (distance(from, to) * 4.0).ceil() as usize;
+        let mut tiles: Vec<(usize, usize)> = Vec::new();
+        for i in 0..=samples {
+            let t = if samples == 0 { 0.0 } else { i as f32 / samples as f32 };
+            let tile = Physical::into_tile_position((from.0 + (to.0 - from.0) * t, from.1 + (to.1 - from.1) * t));
+            if tiles.last() != Some(&tile) {
+                tiles.push(tile);
+            }
+        }
+        tiles
+    }
+
+    #[test]
+    fn smoothing_keeps_the_turns_walls_and_diagonals_need() {
+        let layouts: [&[&str]; 3] = [
+            &["......", ".###..", "...#..", "...#.."],
+            &["..#...", "..#...", "......", "...#.."],
+            &[".#....", "#..#..", "...#..", "......"],
+        ];
+        let policies = [DiagonalPolicy::Never, DiagonalPolicy::BothSidesFree, DiagonalPolicy::OneSideFree];
+        for rows in layouts.iter() {
+            for policy in policies.iter() {
+                let map = map(rows, *policy);
+                for start in [(0, 0), (0, 3), (5, 0)].iter() {
+                    for goal in [(5, 3), (4, 0), (2, 2), (0, 3)].iter() {
+                        if !map.is_passable(*start) || !map.is_passable(*goal) || start == goal {
+                            continue;
+                        }
+                        let steps = match find_steps(&map, *start, Anchor::new(goal.0, goal.1, map.location)) {
+                            Some((steps, _)) => steps,
+                            None => continue,
+                        };
+                        let mut prev = centre(*start);
+                        for step in steps.iter() {
+                            let tiles = tiles_crossed(prev, step.real_local());
+                            for pair in tiles.windows(2) {
+                                assert!(map.is_passable(pair[1]), "{:?} {:?} {:?} -> {:?} crosses a wall at {:?}", rows, policy, start, goal, pair[1]);
+                                let (from, to) = (map.nav.node(pair[0]), map.nav.node(pair[1]));
+                                assert!(map.nav.successors(from).iter().any(|(n, _)| *n == to),
+                                    "{:?} {:?} {:?} -> {:?} steps from {:?} to {:?}", rows, policy, start, goal, pair[0], pair[1]);
+                            }
+                            prev = step.real_local();
+                        }
                     }
-                },
-                _ => {},
+                }
             }
         }
     }
-}
\ No newline at end of file
+}
+
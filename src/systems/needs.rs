@@ -0,0 +1,288 @@
+use amethyst::{
+    core::timing::Time,
+    ecs::prelude::{Entities, Entity, Join, Read, ReadStorage, System, WriteStorage},
+};
+use crate::game_state::{Map, Anchor, Action, GoalPriority, GoalType, Tile};
+use crate::components::{Id, Mover, Needs, NeedSet, Offscreen, Physical, Goal, Plant, Resident, Behaviour};
+
+use pathfinding::prelude::absdiff;
+use rand::Rng;
+
+//runs everybody's needs down, and now and then meets those of people offscreen
+pub struct DecaySystem;
+
+impl<'s> System<'s> for DecaySystem{
+    type SystemData = (
+        WriteStorage<'s, Needs>,
+        ReadStorage<'s, Offscreen>,
+        Read<'s, NeedSet>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (mut needs, offs, need_set, time): Self::SystemData) {
+        let dt = time.delta_seconds();
+        //what people do from moment to moment isn't part of the world a seed reproduces, so it stays unseeded
+        let mut rng = rand::thread_rng();
+        for (needs, off) in (&mut needs, offs.maybe()).join() {
+            needs.decay(dt);
+            //nobody sees what people get up to offscreen, so their needs get met by chance
+            if off.map(|o| o.time_passed() > 0.0).unwrap_or(false) {
+                for def in need_set.needs.iter() {
+                    if rng.gen::<f32>() < def.offscreen_relief * dt {
+                        needs.fill(&def.name);
+                    }
+                }
+            }
+        }
+    }
+}
+
+//scores the needs of everybody without a behaviour tree against the actions in the need set and carries out
+//the best one
+pub struct GoalSystem;
+
+impl<'s> System<'s> for GoalSystem{
+    type SystemData = (
+        Entities<'s>,
+        WriteStorage<'s, Needs>,
+        WriteStorage<'s, Mover>,
+        ReadStorage<'s, Physical>,
+        ReadStorage<'s, Id>,
+        ReadStorage<'s, Plant>,
+        ReadStorage<'s, Resident>,
+        ReadStorage<'s, Behaviour>,
+        Read<'s, Map>,
+        Read<'s, NeedSet>,
+    );
+
+    fn run(&mut self, (entities, mut needs, mut movers, physicals, ids, plants, residents, behaviours, map, need_set): Self::SystemData) {
+        let around = Surroundings::new(&map, &entities, &physicals, &ids, &needs, &plants);
+
+        for (entity, needs, mover, phys, resident, ()) in (&entities, &mut needs, &mut movers, &physicals, residents.maybe(), !&behaviours).join() {
+            //there's no telling what's around offscreen, so people there just wander
+            let onscreen = phys.get_location() == map.location;
+            let best = match need_set.choose(needs, |a| onscreen || a == Action::Wander) {
+                Some(a) => a,
+                None => continue,
+            };
+            //nothing to be done about it right now, so they wander
+            if carry_out(best, entity, needs, mover, phys, resident, &around).is_none() && best != Action::Wander {
+                carry_out(Action::Wander, entity, needs, mover, phys, resident, &around);
+            }
+        }
+    }
+}
+
+//what somebody deciding what to do can see of the area on screen, as it was at the start of the frame
+pub(crate) struct Surroundings<'a> {
+    map: &'a Map,
+    people: Vec<(Entity, Id, (usize, usize))>, //everyone there is to talk to
+    ripe: Vec<(Id, (usize, usize))>, //plants with fruit on them and where they stand
+}
+
+impl<'a> Surroundings<'a> {
+    pub(crate) fn new(map: &'a Map, entities: &Entities, physicals: &ReadStorage<Physical>, ids: &ReadStorage<Id>,
+        needs: &WriteStorage<Needs>, plants: &ReadStorage<Plant>) -> Surroundings<'a> {
+        let people = (entities, physicals, ids, needs).join()
+            .filter(|(_, phys, _, _)| phys.get_location() == map.location)
+            .map(|(e, phys, id, _)| (e, *id, phys.get_tile_position()))
+            .collect();
+        let ripe = (ids, physicals, plants).join()
+            .filter(|(_, phys, plant)| phys.get_location() == map.location && plant.get_fruit_progress() >= 1.0)
+            .map(|(id, phys, _)| (*id, phys.get_tile_position()))
+            .collect();
+        Surroundings {
+            map,
+            people,
+            ripe,
+        }
+    }
+    pub(crate) fn map(&self) -> &Map {
+        self.map
+    }
+    //the ripe plant closest to a tile, counting diagonal steps as one
+    fn nearest_ripe(&self, (x, y): (usize, usize)) -> Option<(Id, (usize, usize))> {
+        self.ripe.iter()
+            .min_by_key(|(_, (px, py))| absdiff(*px, x).max(absdiff(*py, y)))
+            .cloned()
+    }
+}
+
+#[cfg(test)]
+impl<'a> Surroundings<'a> {
+    //an area with nobody else in it and nothing to eat
+    pub(crate) fn empty(map: &'a Map) -> Surroundings<'a> {
+        Surroundings {
+            map,
+            people: Vec::new(),
+            ripe: Vec::new(),
+        }
+    }
+}
+
+//sets the goals that carry out an action: Some(true) if it set new ones, Some(false) if those it set before are still
+//under way, None if it can't be done right now or something more urgent is under way
+pub(crate) fn carry_out(action: Action, entity: Entity, needs: &mut Needs, mover: &mut Mover, phys: &Physical, resident: Option<&Resident>, around: &Surroundings) -> Option<bool> {
+    let current = mover.get_goal_type().action();
+    if current != Action::Size && current != action && action.priority() >= mover.get_goal_priority() {
+        return None;
+    }
+    //everything but wandering needs to see the area
+    if phys.get_location() != around.map.location && action != Action::Wander {
+        return None;
+    }
+
+    let planned = match action {
+        Action::Eat => eat(needs, mover, phys, around),
+        Action::Rest => rest(mover, phys, around.map, resident.is_some()),
+        Action::Socialize => socialize(entity, needs, mover, phys, around.map, &around.people),
+        Action::Wander => wander(mover, phys, resident, around.map),
+        Action::Size => false,
+    };
+    if !planned {
+        None
+    }else{
+        Some(current != action)
+    }
+}
+
+//heads for the nearest ripe plant in the area, or off to another area to look for food there
+fn eat(needs: &mut Needs, mover: &mut Mover, phys: &Physical, around: &Surroundings) -> bool {
+    if mover.has_goal_type(GoalType::MealGoal) {
+        return true;
+    }
+    let map = around.map;
+
+    if let Some((id, (mx, my))) = around.nearest_ripe(phys.get_tile_position()) {
+        if map.nav.has_tile((mx, my)) {
+            needs.set_target(id);
+            mover.add_goal(Goal::new(GoalPriority::MealGoal as usize, Anchor::new(mx, my, map.location), GoalType::MealGoal));
+            return true;
+        }
+    }
+
+    if mover.has_goal_type(GoalType::MealSearch) {
+        return true;
+    }
+
+    //if no meal found in local area, search for meal in a neighbouring one
+    let mut rng = rand::thread_rng();
+
+    let (lx, ly, layer) = phys.get_location();
+    let (mut ax, mut ay) = (0, 0);
+    while (ax, ay) == (0, 0) {
+        ax = rng.gen_range(-1, 2);
+        ay = rng.gen_range(-1, 2);
+    }
+
+    let gx = rng.gen_range(0, map.width);
+    let gy = rng.gen_range(0, map.height);
+
+    if map.nav.has_tile((gx, gy)) {
+        let dest_anchor = Anchor::new(gx, gy, (lx + ax, ly + ay, layer));
+        mover.add_goal(Goal::new(GoalPriority::MealSearch as usize, dest_anchor, GoalType::MealSearch));
+        return true;
+    }
+    false
+}
+
+//residents go indoors to the nearest floor to rest, anyone else sits down where they are
+fn rest(mover: &mut Mover, phys: &Physical, map: &Map, resident: bool) -> bool {
+    if mover.has_goal_type(GoalType::RestGoal) {
+        return true;
+    }
+    let (x, y) = phys.get_tile_position();
+    let mut spot = (x, y);
+    if resident {
+        let indoors = (0..map.width * map.height)
+            .filter(|i| map.tiles[*i].tile == Tile::WoodFloor && map.tiles[*i].passable)
+            .min_by_key(|i| absdiff(*i % map.width, x).max(absdiff(*i / map.width, y)));
+        if let Some(i) = indoors {
+            spot = (i % map.width, i / map.width);
+        }
+    }
+    if !map.nav.has_tile(spot) {
+        return false;
+    }
+    mover.add_goal(Goal::new(GoalPriority::RestGoal as usize, Anchor::new(spot.0, spot.1, map.location), GoalType::RestGoal));
+    true
+}
+
+//walks up to the nearest other person in the area, stopping on a free tile beside them
+fn socialize(entity: Entity, needs: &mut Needs, mover: &mut Mover, phys: &Physical, map: &Map, people: &[(Entity, Id, (usize, usize))]) -> bool {
+    if mover.has_goal_type(GoalType::SocialGoal) {
+        return true;
+    }
+    let (x, y) = phys.get_tile_position();
+    let dist = |(ox, oy): (usize, usize)| absdiff(ox, x).max(absdiff(oy, y));
+    let (id, (ox, oy)) = match people.iter().filter(|(e, _, _)| *e != entity).min_by_key(|(_, _, t)| dist(*t)) {
+        Some((_, id, t)) => (*id, *t),
+        None => return false,
+    };
+
+    let mut spot = None;
+    for dy in -1i64..=1 {
+        for dx in -1i64..=1 {
+            let (sx, sy) = (ox as i64 + dx, oy as i64 + dy);
+            if (dx, dy) == (0, 0) || sx < 0 || sy < 0 {
+                continue;
+            }
+            let tile = (sx as usize, sy as usize);
+            if !map.nav.has_tile(tile) || !map.is_passable(tile) {
+                continue;
+            }
+            match spot {
+                Some(s) if dist(s) <= dist(tile) => (),
+                _ => spot = Some(tile),
+            }
+        }
+    }
+    let (sx, sy) = match spot {
+        Some(s) => s,
+        None => return false,
+    };
+    needs.set_target(id);
+    mover.add_goal(Goal::new(GoalPriority::SocialGoal as usize, Anchor::new(sx, sy, map.location), GoalType::SocialGoal));
+    true
+}
+
+//a walk somewhere nearby, now and then into a neighbouring area; residents stay home or visit another part of
+//their settlement
+fn wander(mover: &mut Mover, phys: &Physical, resident: Option<&Resident>, map: &Map) -> bool {
+    if mover.get_goal().is_some() {
+        return mover.has_goal_type(GoalType::SimpleIdle);
+    }
+    let mut rng = rand::thread_rng();
+
+    let (lx, ly, layer) = phys.get_location();
+    let mut ax = lx + rng.gen_range(-1, 2);
+    let mut ay = ly + rng.gen_range(-1, 2);
+
+    if rng.gen::<f32>() > 0.2 {
+        ax = lx;
+        ay = ly;
+    }
+
+    if let Some(resident) = resident {
+        ax = lx;
+        ay = ly;
+        let areas = resident.get_areas();
+        if rng.gen::<f32>() <= 0.2 && !areas.is_empty() {
+            let (sx, sy) = areas[rng.gen_range(0, areas.len())];
+            ax = sx;
+            ay = sy;
+        }
+    }
+
+    let gx = rng.gen_range(0, map.width);
+    let gy = rng.gen_range(0, map.height);
+
+    let index = gx + gy * map.width;
+    //walls can't be walked to, so don't pick them
+    if index < map.width * map.height && map.nav.has_tile((gx, gy)) && map.is_passable((gx, gy)) {
+        let dest_anchor = Anchor::new(gx, gy, (ax, ay, layer));
+        mover.add_goal(Goal::new(GoalPriority::SimpleIdle as usize, dest_anchor, GoalType::SimpleIdle));
+        return true;
+    }
+    false
+}
@@ -5,6 +5,7 @@ use amethyst::{
 };
 use crate::game_state::{Config, Map, TILE_SIZE, OFFSCREEN_UNKNOWN_PATH_WAIT_TIME};
 use crate::components::{Physical, Id, Mover, Offscreen};
+use crate::worldgen::plan_route;
 
 pub struct PhysicalSystem;
 
@@ -83,6 +84,7 @@ impl<'s> System<'s> for PhysicalSystem{
                 
                 if p_c > 0 {
                     //println!("have path");
+                    //path costs are tenths of a tile at walking speed, move costs included, the same rate MoveSystem walks at
                     let real_p_c = (p_c * TILE_SIZE) as f32 / 10.0;
                     let dist_traveled = mover.speed() * offs_time;
                     path_completed = dist_traveled / real_p_c;
@@ -94,30 +96,53 @@ impl<'s> System<'s> for PhysicalSystem{
                     match mover.get_goal() {
                         Some(anchor) => {
                             if anchor.area() != phys.get_location() {
-                                let (ax, ay) = anchor.area();
-                                let (px, py) = phys.get_location();
+                                //goals are only ever set on the mover's own layer, so only the area changes
+                                let (ax, ay, _) = anchor.area();
+                                let (px, py, _) = phys.get_location();
                                 //println!("other area");
-                                if px < ax {
+                                //known areas are crossed where the route says, unexplored ones straight towards the goal
+                                let crossing = plan_route(&map.world_map, (map.width, map.height), phys.get_location(), phys.get_tile_position(), anchor);
+                                let edge = match crossing {
+                                    Some(c) => Some(c.edge),
+                                    None if map.world_map.contains(phys.get_location()) => None,
+                                    None if px < ax => Some(1),
+                                    None if px > ax => Some(0),
+                                    None if py < ay => Some(2),
+                                    None => Some(3),
+                                };
+                                if edge.is_none() {
+                                    //no way there for now, it gets a few more tries
+                                    mover.fail_goal();
+                                }else if edge == Some(1) {
                                     //east
                                     //println!("east");
                                     phys.mut_area_x(1);
                                     phys.set_x(TILE_SIZE as f32 / 2.0);  
-                                }else if px > ax {
+                                }else if edge == Some(0) {
                                     //west
                                     //println!("west");
                                     phys.mut_area_x(-1);
                                     phys.set_x(config.stage_width - TILE_SIZE as f32 / 2.0);
-                                }else if py < ay {
+                                }else if edge == Some(2) {
                                     //north
                                     //println!("north");
                                     phys.mut_area_y(1);
                                     phys.set_y(TILE_SIZE as f32 / 2.0);
-                                }else if py > ay {
+                                }else{
                                     //south
                                     //println!("south");
                                     phys.mut_area_y(-1);
                                     phys.set_y(config.stage_height - TILE_SIZE as f32 / 2.0);
                                 }
+                                //arriving level with where the route crossed
+                                if let (Some(c), Some(_)) = (crossing, edge) {
+                                    let (cx, cy) = c.arrival;
+                                    if c.edge < 2 {
+                                        phys.set_y((TILE_SIZE / 2 + cy * TILE_SIZE) as f32);
+                                    }else{
+                                        phys.set_x((TILE_SIZE / 2 + cx * TILE_SIZE) as f32);
+                                    }
+                                }
                             } else {
                                 let (gx, gy) = anchor.real_local();
                                 //within area
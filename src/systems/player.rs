@@ -1,13 +1,25 @@
 use amethyst::{
     core::transform::Transform,
     core::timing::Time,
-    ecs::prelude::{Join, Read, Write, ReadStorage, System, SystemData, WriteStorage},
+    ecs::prelude::{Entities, Join, Read, Write, ReadStorage, System, SystemData, WriteStorage},
     input::{InputHandler, StringBindings, VirtualKeyCode},
 };
-use crate::components::{Player, Physical};
-use crate::game_state::{TILE_SIZE, Config, UiHolder, UiState, Ui, KeyCheck, Map, Area, load_map, regenerate_map, update_world_seed, PLAYER_SPEED};
+use crate::components::{Player, Physical, Mover};
+use super::mover::{Body, walks_into};
+use crate::worldgen::PassGenerator;
+use crate::game_state::{TILE_SIZE, Config, UiHolder, UiState, Ui, KeyCheck, Map, Tile, load_map, regenerate_map, update_location, update_layer, WorldRng, PLAYER_SPEED};
 
-pub struct MapSystem;
+pub struct MapSystem{
+    pub on_stairs: bool,
+}
+
+impl MapSystem {
+    pub fn new() -> MapSystem {
+        MapSystem {
+            on_stairs: false,
+        }
+    }
+}
 
 impl<'s> System<'s> for MapSystem{
     type SystemData = (
@@ -15,19 +27,15 @@ impl<'s> System<'s> for MapSystem{
         WriteStorage<'s, Physical>,
         Read<'s, Config>,
         Write<'s, Map>,
+        Read<'s, WorldRng>,
+        Read<'s, PassGenerator>,
     );
 
-    fn run(&mut self, (players, mut physicals, config, mut map): Self::SystemData) {
+    fn run(&mut self, (players, mut physicals, config, mut map, world_rng, generator): Self::SystemData) {
         let mut change_map = false;
-        //let mut area_pointer = &mut Area::new();
-        let mut area_index = map.area_index;
-        
-        /*{
-            let curr_area_index = map.area_index;
-            area_pointer = &mut (map.world_map[curr_area_index])
-        }*/
 
         let mut dir = ' ';
+        let mut layer_change = 0;
 
         for (player, phys) in (&players, &mut physicals).join(){
             let (x, y) = phys.get_real_position();
@@ -58,21 +66,34 @@ impl<'s> System<'s> for MapSystem{
             if change_map {
                 break;
             }    
+
+            //stepping onto stairs takes the player down or up a layer; arriving on the stairs at the other end
+            //doesn't count, so they have to step off and back on to return
+            let (tx, ty) = phys.get_tile_position();
+            let tile = if tx < map.width && ty < map.height { map.tiles[tx + ty * map.width].tile } else { Tile::Size };
+            let on_stairs = tile == Tile::StairsDown || tile == Tile::StairsUp;
+            if on_stairs && !self.on_stairs {
+                layer_change = if tile == Tile::StairsDown { -1 } else { 1 };
+                phys.set_layer(phys.get_layer() + layer_change);
+            }
+            self.on_stairs = on_stairs;
         }
         if change_map {
             //println!("change map! {}", dir);
 
-            {
-                update_world_seed(&mut map, dir);
-            }
+            update_location(&mut map, dir);
 
-            let mut load_tuple = (None, 0);
+            regenerate_map(&mut map, &world_rng, &generator);
 
-            {
-                load_tuple = regenerate_map(&mut map, area_index, dir);
-            }
+            let location = map.location;
+            load_map(&mut map, location);
+        }else if layer_change != 0 {
+            update_layer(&mut map, layer_change);
 
-            load_map(&mut map, load_tuple);
+            regenerate_map(&mut map, &world_rng, &generator);
+
+            let location = map.location;
+            load_map(&mut map, location);
         }
     }
 }
@@ -133,7 +154,9 @@ pub struct MoveSystem;
 
 impl<'s> System<'s> for MoveSystem{
     type SystemData = (
+        Entities<'s>,
         ReadStorage<'s, Player>,
+        ReadStorage<'s, Mover>,
         WriteStorage<'s, Physical>,
         Read<'s, Config>,
         Read<'s, InputHandler<StringBindings>>,
@@ -142,14 +165,28 @@ impl<'s> System<'s> for MoveSystem{
     );
 
 
-    fn run(&mut self, (players, mut physicals, config, input, time, map): Self::SystemData) {
-        
+    fn run(&mut self, (entities, players, movers, mut physicals, config, input, time, map): Self::SystemData) {
+        //people on screen, who the player has to walk around like they have to walk around each other
+        let bodies: Vec<Body> = (&entities, &physicals, movers.maybe()).join()
+            .filter(|(_, phys, _)| phys.get_location() == map.location)
+            .filter_map(|(entity, phys, mover)| Body::of(entity, phys, None, mover))
+            .collect();
+
+        for (entity, player, phys) in (&entities, &players, &mut physicals).join(){
+            let half = ((player.width * TILE_SIZE) as f32 / 2.0, (player.height * TILE_SIZE) as f32 / 2.0);
+            //water slows the player down, roads speed them up
+            let speed = PLAYER_SPEED * 10.0 / map.move_cost(phys.get_tile_position()) as f32;
+
+            //facing whichever way the keys point
+            let (h, v) = (input.axis_value("horizontal_mv").unwrap_or(0.0), input.axis_value("vertical_mv").unwrap_or(0.0));
+            if h != 0.0 || v != 0.0 {
+                phys.set_heading((v as f32).atan2(h as f32));
+            }
 
-        for (player, phys) in (&players, &mut physicals).join(){
             let movement = input.axis_value("horizontal_mv");
             //println!("running, since {}", time.delta_seconds());
             if let Some(mv_amount) = movement {
-                let scaled_amount = PLAYER_SPEED * time.delta_seconds() * mv_amount as f32;
+                let scaled_amount = speed * time.delta_seconds() * mv_amount as f32;
                 let (x, y) = phys.get_real_position();
                 //println!("updating x by {}", scaled_amount);
                 
@@ -158,7 +195,7 @@ impl<'s> System<'s> for MoveSystem{
                     nx += TILE_SIZE as f32 - 1.0;
                 }
 
-                if map.is_passable(Physical::into_tile_position((nx, y))) {
+                if map.is_passable(Physical::into_tile_position((nx, y))) && !walks_into(entity, (x, y), (x + scaled_amount, y), half, &bodies) {
                     phys.set_x(
                         (x + scaled_amount)
                             //.min(config.stage_width as f32 - player.width as f32 * 0.5)
@@ -170,14 +207,14 @@ impl<'s> System<'s> for MoveSystem{
 
             let movement = input.axis_value("vertical_mv");
             if let Some(mv_amount) = movement {
-                let scaled_amount = PLAYER_SPEED * time.delta_seconds() * mv_amount as f32;
+                let scaled_amount = speed * time.delta_seconds() * mv_amount as f32;
                 let (x, y) = phys.get_real_position();
                 //println!("updating x by {}", scaled_amount);
                 
                 let ny = y + scaled_amount;
                 
 
-                if map.is_passable(Physical::into_tile_position((x, ny))) {
+                if map.is_passable(Physical::into_tile_position((x, ny))) && !walks_into(entity, (x, y), (x, ny), half, &bodies) {
                     phys.set_y(
                         (y + scaled_amount)
                             //.min(config.stage_height as f32 - player.height as f32 * 0.5)
@@ -0,0 +1,96 @@
+use amethyst::{
+    core::transform::Transform,
+    core::timing::Time,
+    ecs::prelude::{Join, Read, ReadStorage, System, Write, WriteStorage},
+    input::{InputHandler, StringBindings},
+    renderer::SpriteRender,
+};
+use amethyst::ecs::prelude::Entities;
+use crate::components::{Player, Physical, Mover, Needs, Behaviour, BehaviourSet, Plant, Offscreen, Resident, Id};
+use crate::game_state::{Map, WorldRng, SpriteSheetHandles, SAVE_PATH, restore_entity};
+use crate::save::{WorldSave, EntitySave, write_save, read_save};
+
+pub struct SaveSystem{
+    pub input_lockout: f32,
+}
+
+impl SaveSystem {
+    pub fn new() -> SaveSystem {
+        SaveSystem {
+            input_lockout: 0.0,
+        }
+    }
+}
+
+impl<'s> System<'s> for SaveSystem{
+    type SystemData = (
+        ReadStorage<'s, Player>,
+        WriteStorage<'s, Physical>,
+        WriteStorage<'s, Mover>,
+        WriteStorage<'s, Needs>,
+        WriteStorage<'s, Behaviour>,
+        WriteStorage<'s, Plant>,
+        WriteStorage<'s, Offscreen>,
+        WriteStorage<'s, Resident>,
+        WriteStorage<'s, Id>,
+        WriteStorage<'s, Transform>,
+        WriteStorage<'s, SpriteRender>,
+        Entities<'s>,
+        Read<'s, SpriteSheetHandles>,
+        Read<'s, BehaviourSet>,
+        Write<'s, Map>,
+        Write<'s, WorldRng>,
+        Read<'s, InputHandler<StringBindings>>,
+        Read<'s, Time>,
+    );
+
+    fn run(&mut self, (players, mut physicals, mut movers, mut needs, mut behaviours, mut plants, mut offs, mut residents, mut ids, mut trans, mut srs, mut ents, handles, behaviour_set, mut map, mut world_rng, input, time): Self::SystemData) {
+        self.input_lockout -= time.delta_seconds();
+        if self.input_lockout > 0.0 {
+            return;
+        }
+        self.input_lockout = 0.0;
+
+        let save = input.action_is_down("save").unwrap_or(false);
+        let load = input.action_is_down("load").unwrap_or(false);
+
+        if !save && !load {
+            return;
+        }
+        self.input_lockout = 0.5;
+
+        if save {
+            let mut entities = Vec::new();
+            for (id, phys, mover, need, behaviour, plant, off, resident, ()) in (&ids, &physicals, movers.maybe(), needs.maybe(), behaviours.maybe(), plants.maybe(), offs.maybe(), residents.maybe(), !&players).join() {
+                entities.push(EntitySave::capture(id, phys, mover, need, behaviour, plant, off, resident));
+            }
+            if let Some((_, phys)) = (&players, &physicals).join().next() {
+                match write_save(SAVE_PATH, &WorldSave::capture(&map, &world_rng, phys, entities)) {
+                    Ok(()) => println!("world saved to {}", SAVE_PATH),
+                    Err(e) => println!("failed to save world: {:?}", e),
+                }
+            }
+        }else if load {
+            let mut restored = None;
+            if let Some((_, phys)) = (&players, &mut physicals).join().next() {
+                restored = Some(read_save(SAVE_PATH).and_then(|s| s.restore(&mut map, &mut world_rng, phys)));
+            }
+            match restored {
+                Some(Ok(entities)) => {
+                    //the saved people and plants replace the current ones
+                    for (ent, _id, ()) in (&*ents, &ids, !&players).join() {
+                        if let Err(e) = ents.delete(ent) {
+                            println!("failed to delete entity: {:?}", e);
+                        }
+                    }
+                    for entity in entities {
+                        restore_entity(entity, &handles, &mut ents, &mut physicals, &mut movers, &mut plants, &mut ids, &mut offs, &mut trans, &mut srs, &mut needs, &mut behaviours, &behaviour_set, &mut residents);
+                    }
+                    println!("world loaded from {}", SAVE_PATH);
+                }
+                Some(Err(e)) => println!("failed to load world: {:?}", e),
+                None => {}
+            }
+        }
+    }
+}
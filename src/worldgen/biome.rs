@@ -0,0 +1,203 @@
+use noise::{NoiseFn, Perlin};
+use serde::Deserialize;
+
+use crate::game_state::{Area, Tile, TileBlock, BIOME_RESOLUTION_FACTOR};
+use crate::worldgen::{GenContext, GenPass, GenStage};
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct PaletteTile {
+    pub tile: Tile,
+    pub passable: bool,
+}
+
+//one biome from biomes.ron; the palette runs from low to high elevation
+#[derive(Clone, Debug, Deserialize)]
+pub struct Biome {
+    pub name: String,
+    pub temperature: (f64, f64),
+    pub moisture: (f64, f64),
+    pub palette: Vec<PaletteTile>,
+    pub plant_weight: f32,
+    pub person_weight: f32,
+}
+
+impl Biome {
+    pub fn contains(&self, temperature: f64, moisture: f64) -> bool {
+        temperature >= self.temperature.0 && temperature < self.temperature.1 && moisture >= self.moisture.0 && moisture < self.moisture.1
+    }
+    fn centre_dist(&self, temperature: f64, moisture: f64) -> f64 {
+        let ct = (self.temperature.0 + self.temperature.1) / 2.0;
+        let cm = (self.moisture.0 + self.moisture.1) / 2.0;
+        (ct - temperature).abs() + (cm - moisture).abs()
+    }
+    //palettes are never empty once the set has been validated
+    pub fn tile(&self, elevation: f64) -> PaletteTile {
+        let index = ((elevation * self.palette.len() as f64) as usize).min(self.palette.len() - 1);
+        self.palette[index]
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BiomeSet {
+    pub biomes: Vec<Biome>,
+}
+
+impl BiomeSet {
+    //what lookup and Biome::tile count on: at least one biome, each with at least one palette tile
+    pub fn validate(&self) -> Result<(), String> {
+        if self.biomes.is_empty() {
+            return Err("no biomes defined".to_string());
+        }
+        for biome in self.biomes.iter() {
+            if biome.palette.is_empty() {
+                return Err(format!("biome {} has an empty palette", biome.name));
+            }
+        }
+        Ok(())
+    }
+    //first biome whose ranges hold the sample, otherwise the one centred closest to it
+    pub fn lookup(&self, temperature: f64, moisture: f64) -> usize {
+        for (i, biome) in self.biomes.iter().enumerate() {
+            if biome.contains(temperature, moisture) {
+                return i;
+            }
+        }
+        let mut closest = 0;
+        for (i, biome) in self.biomes.iter().enumerate() {
+            if biome.centre_dist(temperature, moisture) < self.biomes[closest].centre_dist(temperature, moisture) {
+                closest = i;
+            }
+        }
+        closest
+    }
+    pub fn get(&self, index: usize) -> Option<&Biome> {
+        self.biomes.get(index)
+    }
+    pub fn plant_weight(&self, index: usize) -> f32 {
+        self.get(index).map(|b| b.plant_weight).unwrap_or(1.0)
+    }
+    pub fn person_weight(&self, index: usize) -> f32 {
+        self.get(index).map(|b| b.person_weight).unwrap_or(1.0)
+    }
+    //average plant weight over a set of tile biomes
+    pub fn mean_plant_weight(&self, tile_biomes: &[usize]) -> f32 {
+        if tile_biomes.is_empty() {
+            return 1.0;
+        }
+        let total: f32 = tile_biomes.iter().map(|b| self.plant_weight(*b)).sum();
+        total / tile_biomes.len() as f32
+    }
+}
+
+//only used when no biomes.ron was loaded
+impl Default for BiomeSet {
+    fn default() -> Self {
+        let palette = [Tile::Plain, Tile::Grassy, Tile::RockyLight, Tile::GrassyHeavy, Tile::Rocky];
+        BiomeSet {
+            biomes: vec![Biome {
+                name: "plains".to_string(),
+                temperature: (0.0, 1.0),
+                moisture: (0.0, 1.0),
+                palette: palette.iter().map(|t| PaletteTile { tile: *t, passable: true }).collect(),
+                plant_weight: 1.0,
+                person_weight: 1.0,
+            }],
+        }
+    }
+}
+
+//picks a biome for every tile from temperature and moisture, then turns the elevation into that biome's tile
+pub struct BiomePass {
+    biomes: BiomeSet,
+}
+
+impl BiomePass {
+    pub fn new(biomes: BiomeSet) -> BiomePass {
+        BiomePass {
+            biomes,
+        }
+    }
+}
+
+impl GenPass for BiomePass {
+    fn stage(&self) -> GenStage {
+        GenStage::Biome
+    }
+
+    fn apply(&self, area: &mut Area, ctx: &mut GenContext) {
+        let w = ctx.width;
+        let h = ctx.height;
+
+        let perlin = Perlin::new();
+
+        let bxseed = ctx.world_seed.4;
+        let byseed = ctx.world_seed.5;
+
+        area.tiles.clear();
+        area.biomes.clear();
+        for y in 0..h {
+            for x in 0..w {
+                let (ux, uy) = ctx.global_unit(x, y);
+                let bx = ux * BIOME_RESOLUTION_FACTOR as f64;
+                let by = uy * BIOME_RESOLUTION_FACTOR as f64;
+                //noise runs from -1 to 1, the biome ranges from 0 to 1
+                let temperature = (perlin.get([bxseed + bx, byseed + by]) + 1.0) / 2.0;
+                let moisture = (perlin.get([byseed + by, bxseed + bx]) + 1.0) / 2.0;
+
+                let biome_index = self.biomes.lookup(temperature, moisture);
+                ctx.biomes[x + y * w] = biome_index;
+
+                let palette_tile = self.biomes.biomes[biome_index].tile(ctx.elevation[x + y * w]);
+                area.tiles.push(TileBlock::new(palette_tile.tile, palette_tile.passable));
+                area.biomes.push(biome_index);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn biome(name: &str, temperature: (f64, f64), moisture: (f64, f64)) -> Biome {
+        Biome {
+            name: name.to_string(),
+            temperature,
+            moisture,
+            palette: vec![PaletteTile { tile: Tile::Plain, passable: true }],
+            plant_weight: 1.0,
+            person_weight: 1.0,
+        }
+    }
+
+    fn set() -> BiomeSet {
+        BiomeSet {
+            biomes: vec![biome("cold", (0.0, 0.5), (0.0, 1.0)), biome("warm", (0.5, 0.8), (0.2, 0.6))],
+        }
+    }
+
+    #[test]
+    fn lookup_picks_the_biome_holding_the_sample() {
+        let biomes = set();
+        assert_eq!(biomes.lookup(0.2, 0.9), 0);
+        assert_eq!(biomes.lookup(0.6, 0.3), 1);
+        //ranges include their start and leave out their end
+        assert_eq!(biomes.lookup(0.5, 0.2), 1);
+    }
+
+    #[test]
+    fn lookup_falls_back_to_the_closest_centre() {
+        let biomes = set();
+        assert_eq!(biomes.lookup(0.95, 0.4), 1);
+        assert_eq!(biomes.lookup(0.6, 0.9), 1);
+    }
+
+    #[test]
+    fn validate_rejects_empty_sets_and_palettes() {
+        assert!(set().validate().is_ok());
+        assert!(BiomeSet { biomes: Vec::new() }.validate().is_err());
+        let mut biomes = set();
+        biomes.biomes[1].palette.clear();
+        assert!(biomes.validate().is_err());
+    }
+}
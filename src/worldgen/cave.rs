@@ -0,0 +1,254 @@
+use noise::{NoiseFn, Perlin};
+use rand::Rng;
+
+use crate::game_state::{Area, Tile, TileBlock, WorldRng, RngStream,
+    CAVE_DEPTH, CAVE_ENTRANCE_CHANCE, CAVE_ENTRANCE_TRIES, CAVE_FILL, CAVE_SMOOTHING_STEPS};
+use crate::worldgen::{GenContext, GenPass, GenStage};
+
+const CAVE_NOISE_FACTOR: f64 = 0.71; //per tile; off the lattice so neighbouring samples don't correlate
+const CAVERN_NOISE_FACTOR: f64 = 0.07; //per tile, for the larger halls and walls
+const STAIRS_CLEARING: i64 = 2; //open floor kept around stairs
+
+//carves caves out of solid rock: noise decides where rock starts out, then a few rounds of cellular automata
+//smooth it into caverns. the automata run over the area plus a margin as wide as the number of rounds,
+//which makes every tile come out as it would in one world sized grid, so caves line up across area edges
+pub struct CavePass;
+
+impl GenPass for CavePass {
+    fn stage(&self) -> GenStage {
+        GenStage::Terrain
+    }
+
+    fn applies(&self, ctx: &GenContext) -> bool {
+        ctx.underground()
+    }
+
+    fn apply(&self, area: &mut Area, ctx: &mut GenContext) {
+        let w = ctx.width;
+        let h = ctx.height;
+        let margin = CAVE_SMOOTHING_STEPS;
+        let (pw, ph) = (w + margin * 2, h + margin * 2);
+
+        let perlin = Perlin::new();
+        let layer = ctx.location.2 as f64;
+        let xseed = ctx.world_seed.6 + layer * 97.0;
+        let yseed = ctx.world_seed.7 - layer * 89.0;
+
+        let (ox, oy) = ctx.global_index(0, 0);
+        let mut rock = vec![false; pw * ph];
+        for y in 0..ph {
+            for x in 0..pw {
+                let gx = (ox + x as i64 - margin as i64) as f64;
+                let gy = (oy + y as i64 - margin as i64) as f64;
+                let grain = perlin.get([xseed + gx * CAVE_NOISE_FACTOR, yseed + gy * CAVE_NOISE_FACTOR]);
+                let cavern = perlin.get([yseed + gy * CAVERN_NOISE_FACTOR, xseed + gx * CAVERN_NOISE_FACTOR]);
+                rock[x + y * pw] = grain + cavern * 0.5 > CAVE_FILL;
+            }
+        }
+
+        //rock with most of its neighbours rock stays, open tiles with most of theirs rock fill in
+        for _i in 0..CAVE_SMOOTHING_STEPS {
+            let mut next = rock.clone();
+            for y in 1..ph - 1 {
+                for x in 1..pw - 1 {
+                    let mut count = 0;
+                    for dy in 0..3 {
+                        for dx in 0..3 {
+                            if (dx, dy) != (1, 1) && rock[x + dx - 1 + (y + dy - 1) * pw] {
+                                count += 1;
+                            }
+                        }
+                    }
+                    if count >= 5 {
+                        next[x + y * pw] = true;
+                    }else if count <= 3 {
+                        next[x + y * pw] = false;
+                    }
+                }
+            }
+            rock = next;
+        }
+
+        area.tiles.clear();
+        area.biomes.clear();
+        for y in 0..h {
+            for x in 0..w {
+                let tile = if rock[x + margin + (y + margin) * pw] { Tile::CaveWall } else { Tile::CaveFloor };
+                area.tiles.push(TileBlock::new(tile, tile.default_passable()));
+                area.biomes.push(0);
+            }
+        }
+    }
+}
+
+//spots on the layer at location that could lead down to the one below it, best first. underground the first
+//one is always used; the surface takes the first one it has room for
+pub fn cave_entrances(world_rng: &WorldRng, location: (i32, i32, i32), (w, h): (usize, usize)) -> Vec<(usize, usize)> {
+    if location.2 <= -CAVE_DEPTH {
+        return Vec::new();
+    }
+    let mut rng = world_rng.layer_rng(location, RngStream::Caves);
+    if rng.gen::<f32>() >= CAVE_ENTRANCE_CHANCE {
+        return Vec::new();
+    }
+    //keep clear of the edges; areas too small for that get no entrances at all
+    let margin = STAIRS_CLEARING as usize + 2;
+    if w <= margin * 2 || h <= margin * 2 {
+        return Vec::new();
+    }
+    (0..CAVE_ENTRANCE_TRIES).map(|_| (rng.gen_range(margin, w - margin), rng.gen_range(margin, h - margin))).collect()
+}
+
+//where a cave layer's stairs down go: its first entrance that the stairs up from the layer above don't already
+//take
+pub fn cave_stairs_down(world_rng: &WorldRng, location: (i32, i32, i32), dim: (usize, usize), stairs_up: &[(usize, usize)]) -> Option<(usize, usize)> {
+    cave_entrances(world_rng, location, dim).into_iter().find(|pos| !stairs_up.contains(pos))
+}
+
+//every stairs down tile of a generated area
+pub fn stairs_down(area: &Area, w: usize) -> Vec<(usize, usize)> {
+    (0..area.tiles.len())
+        .filter(|i| area.tiles[*i].tile == Tile::StairsDown)
+        .map(|i| (i % w, i / w))
+        .collect()
+}
+
+//where on the surface a cave entrance can go: open ground, out of the water and clear of buildings
+fn entrance_fits(area: &Area, (x, y): (usize, usize), w: usize) -> bool {
+    let block = area.tiles[x + y * w];
+    block.passable && !block.tile.is_water() && !area.structures.iter().any(|r| x >= r.x && x < r.x + r.w && y >= r.y && y < r.y + r.h)
+}
+
+//puts stairs down wherever this layer has an entrance and stairs up under the stairs down of the layer above,
+//which the generator works out before building a cave layer
+pub struct StairsPass;
+
+impl GenPass for StairsPass {
+    fn stage(&self) -> GenStage {
+        GenStage::Decoration
+    }
+
+    fn applies(&self, _ctx: &GenContext) -> bool {
+        true
+    }
+
+    fn apply(&self, area: &mut Area, ctx: &mut GenContext) {
+        let w = ctx.width;
+        let up = ctx.entrances_above.clone().unwrap_or_default();
+
+        let mut stairs = Vec::new();
+        let entrances = cave_entrances(&ctx.world_rng, ctx.location, (w, ctx.height));
+        let down = if ctx.underground() {
+            cave_stairs_down(&ctx.world_rng, ctx.location, (w, ctx.height), &up)
+        }else if ctx.handcrafted {
+            //hand authored areas only get the stairs they were drawn with
+            None
+        }else{
+            entrances.into_iter().find(|pos| entrance_fits(area, *pos, w))
+        };
+        if let Some(pos) = down {
+            stairs.push((pos, Tile::StairsDown));
+        }
+        for pos in up {
+            stairs.push((pos, Tile::StairsUp));
+        }
+
+        for ((x, y), tile) in stairs {
+            //underground, stairs get some room to step off them
+            if ctx.underground() {
+                for dy in -STAIRS_CLEARING..=STAIRS_CLEARING {
+                    for dx in -STAIRS_CLEARING..=STAIRS_CLEARING {
+                        let (cx, cy) = (x as i64 + dx, y as i64 + dy);
+                        //stairs drawn into a landmark may lie closer to the edge than ours, which is left to match
+                        //the neighbouring areas
+                        if cx <= 0 || cy <= 0 || cx >= w as i64 - 1 || cy >= ctx.height as i64 - 1 {
+                            continue;
+                        }
+                        let index = cx as usize + cy as usize * w;
+                        if area.tiles[index].tile == Tile::CaveWall {
+                            area.tiles[index] = TileBlock::new(Tile::CaveFloor, true);
+                        }
+                    }
+                }
+            }
+            area.tiles[x + y * w] = TileBlock::new(tile, true);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worldgen::WorldGenerator;
+    use crate::worldgen::generator::tests::{generator, context};
+
+    #[test]
+    fn surface_entrances_lead_onto_stairs_up() {
+        let generator = generator();
+        let mut found = 0;
+        for x in 0..12 {
+            let mut ctx = context(7, (x, 0, 0));
+            let surface = generator.generate(&mut ctx);
+            let below = generator.generate(&mut context(7, (x, 0, -1)));
+            for i in (0..ctx.width * ctx.height).filter(|i| surface.tiles[*i].tile == Tile::StairsDown) {
+                let (tx, ty) = (i % ctx.width, i / ctx.width);
+                assert!(!surface.structures.iter().any(|r| tx >= r.x && tx < r.x + r.w && ty >= r.y && ty < r.y + r.h));
+                assert_eq!(below.tiles[i].tile, Tile::StairsUp);
+                found += 1;
+            }
+        }
+        assert!(found > 0);
+    }
+
+    #[test]
+    fn cave_stairs_lead_onto_stairs_up_below() {
+        let generator = generator();
+        let mut found = 0;
+        for x in 0..12 {
+            let ctx = context(7, (x, 0, -1));
+            let first = generator.generate(&mut context(7, (x, 0, -1)));
+            let second = generator.generate(&mut context(7, (x, 0, -2)));
+            for i in (0..ctx.width * ctx.height).filter(|i| second.tiles[*i].tile == Tile::StairsUp) {
+                assert_eq!(first.tiles[i].tile, Tile::StairsDown);
+                found += 1;
+            }
+        }
+        assert!(found > 0);
+    }
+
+    #[test]
+    fn known_stairs_above_are_used_as_given() {
+        let generator = generator();
+        let mut ctx = context(7, (0, 0, -1));
+        ctx.entrances_above = Some(vec![(10, 10)]);
+        let area = generator.generate(&mut ctx);
+        assert_eq!(area.tiles[10 + 10 * ctx.width].tile, Tile::StairsUp);
+        assert!(stairs_down(&area, ctx.width).iter().all(|pos| *pos != (10, 10)));
+    }
+
+    #[test]
+    fn tiny_areas_have_no_entrances() {
+        let world_rng = WorldRng::new(7);
+        let margin = STAIRS_CLEARING as usize + 2;
+        for x in 0..32 {
+            for layer in -1..1 {
+                assert!(cave_entrances(&world_rng, (x, 0, layer), (margin * 2, 40)).is_empty());
+                assert!(cave_entrances(&world_rng, (x, 0, layer), (40, 3)).is_empty());
+            }
+        }
+    }
+
+    #[test]
+    fn entrances_skip_water_and_buildings() {
+        let ctx = context(7, (0, 0, 0));
+        let mut area = Area::new();
+        area.tiles = vec![TileBlock::new(Tile::Plain, true); ctx.width * ctx.height];
+        area.tiles[10 + 10 * ctx.width] = TileBlock::new(Tile::ShallowWater, true);
+        area.structures.push(crate::game_state::Rect::new((20, 10), (4, 4)));
+
+        assert!(entrance_fits(&area, (9, 10), ctx.width));
+        assert!(!entrance_fits(&area, (10, 10), ctx.width));
+        assert!(!entrance_fits(&area, (23, 13), ctx.width));
+        assert!(entrance_fits(&area, (24, 13), ctx.width));
+    }
+}
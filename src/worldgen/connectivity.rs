@@ -0,0 +1,264 @@
+use std::collections::VecDeque;
+
+use crate::game_state::{Area, Tile};
+use crate::worldgen::{GenContext, GenPass, GenStage, DiagonalPolicy, build_nav};
+
+//carving rounds before an area is left as it is
+const MAX_REPAIRS: usize = 64;
+
+//joins walled off pockets to the rest of the area and makes sure every edge anchor can be reached,
+//opening doors in structure walls or clearing other blocking tiles
+pub struct ConnectivityPass {
+    diagonals: DiagonalPolicy,
+}
+
+impl ConnectivityPass {
+    pub fn new(diagonals: DiagonalPolicy) -> ConnectivityPass {
+        ConnectivityPass {
+            diagonals,
+        }
+    }
+}
+
+impl GenPass for ConnectivityPass {
+    fn stage(&self) -> GenStage {
+        GenStage::Connectivity
+    }
+
+    fn applies(&self, _ctx: &GenContext) -> bool {
+        true
+    }
+
+    fn apply(&self, area: &mut Area, ctx: &mut GenContext) {
+        //hand authored areas are left the way they were drawn
+        if ctx.handcrafted {
+            return;
+        }
+        repair_connectivity(area, ctx.location, (ctx.width, ctx.height), self.diagonals);
+    }
+}
+
+//labels each walkable tile with the anchor graph region it belongs to, walls get None
+pub fn connected_regions(area: &Area, (w, h): (usize, usize)) -> (Vec<Option<usize>>, usize) {
+    let mut labels = vec![None; w * h];
+    let mut count = 0;
+
+    for start in 0..w * h {
+        if labels[start].is_some() || !area.tiles[start].passable {
+            continue;
+        }
+        let mut queue = VecDeque::new();
+        labels[start] = Some(count);
+        queue.push_back(start);
+
+        while let Some(index) = queue.pop_front() {
+            for (succ, _) in area.nav.successors(index + 4).iter() {
+                //out of bounds anchors aren't tiles
+                if *succ < 4 {
+                    continue;
+                }
+                let next = *succ - 4;
+                if labels[next].is_none() {
+                    labels[next] = Some(count);
+                    queue.push_back(next);
+                }
+            }
+        }
+        count += 1;
+    }
+    (labels, count)
+}
+
+//opens tiles until the area is a single region touching every edge it can; returns how many were opened.
+//edge tiles are shared with the neighbouring area, so they are never opened, only joined up to
+pub fn repair_connectivity(area: &mut Area, location: (i32, i32, i32), (w, h): (usize, usize), diagonals: DiagonalPolicy) -> usize {
+    let mut opened = 0;
+    //first tiles of pockets that can't be reached without opening an edge tile
+    let mut stuck: Vec<usize> = Vec::new();
+
+    for _i in 0..MAX_REPAIRS {
+        let (labels, count) = connected_regions(area, (w, h));
+        if count == 0 {
+            break;
+        }
+
+        let mut edges = vec![[false; 4]; count];
+        let mut sizes = vec![0; count];
+        for index in 0..w * h {
+            if let Some(region) = labels[index] {
+                sizes[region] += 1;
+                for (succ, _) in area.nav.successors(index + 4).iter() {
+                    if *succ < 4 {
+                        edges[region][*succ] = true;
+                    }
+                }
+            }
+        }
+
+        //the region reaching the most edges is the one everything else gets joined to
+        let main = (0..count).max_by_key(|r| (edges[*r].iter().filter(|e| **e).count(), sizes[*r])).unwrap();
+
+        let pocket = (0..count).find(|r| *r != main && !stuck.iter().any(|i| labels[*i] == Some(*r)));
+        let path = if let Some(pocket) = pocket {
+            let sources: Vec<usize> = (0..w * h).filter(|i| labels[*i] == Some(pocket)).collect();
+            match carve_path(area, (w, h), &sources, |i| labels[i] == Some(main)) {
+                Some(tiles) => tiles,
+                None => {
+                    stuck.push(sources[0]);
+                    continue;
+                }
+            }
+        }else if let Some(edge) = (0..4).find(|e| !edges[main][*e] && (0..w * h).any(|i| on_edge(i, *e, (w, h)) && area.tiles[i].passable)) {
+            let sources: Vec<usize> = (0..w * h).filter(|i| labels[*i] == Some(main)).collect();
+            match carve_path(area, (w, h), &sources, |i| on_edge(i, edge, (w, h)) && area.tiles[i].passable) {
+                Some(tiles) => tiles,
+                None => {
+                    println!("could not connect area {:?}", location);
+                    break;
+                }
+            }
+        }else{
+            break;
+        };
+
+        for index in path.iter() {
+            open_tile(area, *index);
+        }
+        opened += path.len();
+        build_nav(area, (w, h), diagonals);
+    }
+    opened
+}
+
+//w, e, n, s as in the anchor list
+fn on_edge(index: usize, edge: usize, (w, h): (usize, usize)) -> bool {
+    let (x, y) = (index % w, index / w);
+    match edge {
+        0 => x == 0,
+        1 => x == w - 1,
+        2 => y == h - 1,
+        _ => y == 0,
+    }
+}
+
+//a wall turns into a door, deep water into a ford and rock into a tunnel, anything else just becomes walkable
+fn open_tile(area: &mut Area, index: usize) {
+    let block = &mut area.tiles[index];
+    match block.tile {
+        Tile::WoodWall => block.tile = Tile::WoodFloor,
+        Tile::DeepWater => block.tile = Tile::ShallowWater,
+        Tile::CaveWall => block.tile = Tile::CaveFloor,
+        _ => (),
+    }
+    block.passable = true;
+}
+
+fn on_border(index: usize, (w, h): (usize, usize)) -> bool {
+    (0..4).any(|e| on_edge(index, e, (w, h)))
+}
+
+//cheapest orthogonal route from any source to a goal tile, counting only blocked tiles and never going through
+//a blocked edge tile; returns the blocked tiles along it
+fn carve_path<F: Fn(usize) -> bool>(area: &Area, (w, h): (usize, usize), sources: &[usize], goal: F) -> Option<Vec<usize>> {
+    let mut cost = vec![usize::max_value(); w * h];
+    let mut prev = vec![None; w * h];
+    let mut queue = VecDeque::new();
+
+    for source in sources.iter() {
+        cost[*source] = 0;
+        queue.push_back(*source);
+    }
+
+    while let Some(index) = queue.pop_front() {
+        if goal(index) {
+            let mut blocked = Vec::new();
+            let mut curr = Some(index);
+            while let Some(i) = curr {
+                if !area.tiles[i].passable {
+                    blocked.push(i);
+                }
+                curr = prev[i];
+            }
+            return Some(blocked);
+        }
+
+        let (x, y) = (index % w, index / w);
+        let mut next = Vec::new();
+        if x > 0 {
+            next.push(index - 1);
+        }
+        if x + 1 < w {
+            next.push(index + 1);
+        }
+        if y > 0 {
+            next.push(index - w);
+        }
+        if y + 1 < h {
+            next.push(index + w);
+        }
+
+        for n in next {
+            if !area.tiles[n].passable && on_border(n, (w, h)) {
+                continue;
+            }
+            let step = if area.tiles[n].passable { 0 } else { 1 };
+            if cost[index] + step < cost[n] {
+                cost[n] = cost[index] + step;
+                prev[n] = Some(index);
+                if step == 0 {
+                    queue.push_front(n);
+                }else{
+                    queue.push_back(n);
+                }
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worldgen::area_from_rows;
+
+    fn area(rows: &[&str]) -> (Area, (usize, usize)) {
+        (area_from_rows(rows, DiagonalPolicy::default()), (rows[0].len(), rows.len()))
+    }
+
+    fn border(area: &Area, dim: (usize, usize)) -> Vec<bool> {
+        (0..dim.0 * dim.1).filter(|i| on_border(*i, dim)).map(|i| area.tiles[i].passable).collect()
+    }
+
+    #[test]
+    fn walled_off_halves_are_joined_inside_the_area() {
+        let (mut area, dim) = area(&[
+            "....#....",
+            "....#....",
+            "....#....",
+            "....#....",
+            "....#....",
+        ]);
+        let before = border(&area, dim);
+        assert_eq!(repair_connectivity(&mut area, (0, 0, 0), dim, DiagonalPolicy::default()), 1);
+        assert_eq!(connected_regions(&area, dim).1, 1);
+        assert_eq!(border(&area, dim), before);
+    }
+
+    #[test]
+    fn edge_tiles_are_never_opened() {
+        //the corner pocket could only be reached through the edge, and the blocked east edge stays blocked
+        let (mut area, dim) = area(&[
+            "#######",
+            "#..#..#",
+            "#..#..#",
+            "#.....#",
+            ".#.####",
+        ]);
+        let before = border(&area, dim);
+        repair_connectivity(&mut area, (0, 0, 0), dim, DiagonalPolicy::default());
+        assert_eq!(border(&area, dim), before);
+        let (labels, _) = connected_regions(&area, dim);
+        let inside: Vec<_> = (0..dim.0 * dim.1).filter(|i| !on_border(*i, dim)).filter_map(|i| labels[i]).collect();
+        assert!(inside.iter().all(|r| *r == inside[0]));
+    }
+}
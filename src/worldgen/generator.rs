@@ -0,0 +1,276 @@
+use rand_chacha::ChaCha8Rng;
+
+use crate::game_state::{Area, WorldRng, RngStream};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::worldgen::{TerrainPass, BiomePass, BiomeSet, WaterPass, TiledPass, SettlementPass, StructurePass, RoadPass, SettlementPlanner, CavePass, StairsPass, NavigationPass, ConnectivityPass, PortalPass, DiagonalPolicy, stairs_down, cave_stairs_down};
+
+//passes run in this order; a generator keeps its passes sorted by stage
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum GenStage {
+    Terrain = 0,
+    Biome,
+    Water,
+    Structures,
+    Roads,
+    Decoration,
+    Navigation,
+    Connectivity,
+    Portals,
+    Size,
+}
+
+//everything a pass needs to know about the area being built, plus scratch data shared between passes
+pub struct GenContext {
+    pub location: (i32, i32, i32),
+    pub width: usize,
+    pub height: usize,
+    pub world_seed: (f64, f64, f64, f64, f64, f64, f64, f64),
+    pub rng: ChaCha8Rng,
+    pub world_rng: WorldRng,
+    pub elevation: Vec<f64>,
+    pub biomes: Vec<usize>,
+    pub handcrafted: bool,
+    pub settlement: Option<(i32, i32)>,
+    pub entrances_above: Option<Vec<(usize, usize)>>, //stairs down on the layer above a cave layer, if known
+}
+
+impl GenContext {
+    pub fn new(location: (i32, i32, i32), (width, height): (usize, usize), world_seed: (f64, f64, f64, f64, f64, f64, f64, f64), world_rng: &WorldRng) -> GenContext {
+        GenContext {
+            location,
+            width,
+            height,
+            world_seed,
+            rng: world_rng.layer_rng(location, RngStream::Structures),
+            world_rng: world_rng.clone(),
+            elevation: vec![0.0; width * height],
+            biomes: vec![0; width * height],
+            handcrafted: false,
+            settlement: None,
+            entrances_above: None,
+        }
+    }
+    //tile position in the whole world; an area's last row and column are its neighbour's first,
+    //which is where movers wrap when they walk off the stage
+    pub fn global_index(&self, x: usize, y: usize) -> (i64, i64) {
+        let gx = self.location.0 as i64 * (self.width as i64 - 1) + x as i64;
+        let gy = self.location.1 as i64 * (self.height as i64 - 1) + y as i64;
+        (gx, gy)
+    }
+    pub fn global_tile(&self, x: usize, y: usize) -> (f64, f64) {
+        let (gx, gy) = self.global_index(x, y);
+        (gx as f64, gy as f64)
+    }
+    //global tile position scaled so that one area spans one unit
+    pub fn global_unit(&self, x: usize, y: usize) -> (f64, f64) {
+        let (gx, gy) = self.global_tile(x, y);
+        (gx / (self.width - 1) as f64, gy / (self.height - 1) as f64)
+    }
+    //the area's coordinate without its layer
+    pub fn surface_location(&self) -> (i32, i32) {
+        (self.location.0, self.location.1)
+    }
+    pub fn underground(&self) -> bool {
+        self.location.2 < 0
+    }
+}
+
+pub trait GenPass: Send + Sync {
+    fn stage(&self) -> GenStage;
+    fn apply(&self, area: &mut Area, ctx: &mut GenContext);
+    //passes build the surface unless they say otherwise
+    fn applies(&self, ctx: &GenContext) -> bool {
+        !ctx.underground()
+    }
+}
+
+pub trait WorldGenerator {
+    fn passes(&self) -> &[Box<dyn GenPass>];
+
+    fn generate(&self, ctx: &mut GenContext) -> Area {
+        //callers that have the layer above already pass in where its stairs are
+        if ctx.underground() && ctx.entrances_above.is_none() {
+            ctx.entrances_above = Some(self.stairs_above(ctx));
+        }
+        let mut area = Area::new();
+        for pass in self.passes() {
+            if pass.applies(ctx) {
+                pass.apply(&mut area, ctx);
+            }
+        }
+        area
+    }
+
+    //where the layer above a cave layer has its stairs down; the surface picks them from what it has room for,
+    //so it gets built to see where they went
+    fn stairs_above(&self, ctx: &GenContext) -> Vec<(usize, usize)> {
+        let (lx, ly, layer) = ctx.location;
+        let dim = (ctx.width, ctx.height);
+        let mut above = GenContext::new((lx, ly, layer + 1), dim, ctx.world_seed, &ctx.world_rng);
+        if !above.underground() {
+            return stairs_down(&self.generate(&mut above), ctx.width);
+        }
+        let up = self.stairs_above(&above);
+        cave_stairs_down(&ctx.world_rng, above.location, dim, &up).into_iter().collect()
+    }
+}
+
+pub struct PassGenerator {
+    passes: Vec<Box<dyn GenPass>>,
+    settlements: Arc<SettlementPlanner>, //shared by the passes that need to know where settlements are
+}
+
+impl PassGenerator {
+    pub fn new() -> PassGenerator {
+        PassGenerator {
+            passes: Vec::new(),
+            settlements: Arc::new(SettlementPlanner::new()),
+        }
+    }
+    pub fn settlements(&self) -> &SettlementPlanner {
+        &self.settlements
+    }
+    pub fn with_pass(mut self, pass: Box<dyn GenPass>) -> PassGenerator {
+        self.add_pass(pass);
+        self
+    }
+    //inserts after every pass of the same or an earlier stage
+    pub fn add_pass(&mut self, pass: Box<dyn GenPass>) {
+        let stage = pass.stage();
+        let index = self.passes.iter().position(|p| p.stage() > stage).unwrap_or(self.passes.len());
+        self.passes.insert(index, pass);
+    }
+    //the default pipeline with the given biome definitions and landmark areas, and caves below
+    pub fn standard(biomes: BiomeSet, landmarks: TiledPass, diagonals: DiagonalPolicy) -> PassGenerator {
+        let generator = PassGenerator::new();
+        let settlements = generator.settlements.clone();
+        generator
+            .with_pass(Box::new(TerrainPass))
+            .with_pass(Box::new(BiomePass::new(biomes)))
+            .with_pass(Box::new(WaterPass))
+            .with_pass(Box::new(landmarks))
+            .with_pass(Box::new(SettlementPass::new(settlements.clone())))
+            .with_pass(Box::new(StructurePass))
+            .with_pass(Box::new(RoadPass::new(settlements)))
+            .with_pass(Box::new(CavePass))
+            .with_pass(Box::new(StairsPass))
+            .with_pass(Box::new(NavigationPass::new(diagonals)))
+            .with_pass(Box::new(ConnectivityPass::new(diagonals)))
+            .with_pass(Box::new(PortalPass))
+    }
+    //drops every pass of the given stage and puts the new one in their place
+    pub fn replace_stage(&mut self, pass: Box<dyn GenPass>) {
+        let stage = pass.stage();
+        self.passes.retain(|p| p.stage() != stage);
+        self.add_pass(pass);
+    }
+}
+
+impl Default for PassGenerator {
+    fn default() -> Self {
+        PassGenerator::standard(BiomeSet::default(), TiledPass::new(HashMap::new()), DiagonalPolicy::default())
+    }
+}
+
+impl WorldGenerator for PassGenerator {
+    fn passes(&self) -> &[Box<dyn GenPass>] {
+        &self.passes
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::game_state::{Tile, TileBlock, roll_world_seed};
+    use crate::worldgen::build_nav;
+
+    //the standard pipeline with the biomes the game ships with and no landmarks
+    pub(crate) fn generator() -> PassGenerator {
+        let biomes: BiomeSet = ron::de::from_str(include_str!("../../config/biomes.ron")).unwrap();
+        PassGenerator::standard(biomes, TiledPass::new(HashMap::new()), DiagonalPolicy::default())
+    }
+
+    pub(crate) fn context(seed: u64, location: (i32, i32, i32)) -> GenContext {
+        let mut world_rng = WorldRng::new(seed);
+        let world_seed = roll_world_seed(&mut world_rng);
+        GenContext::new(location, (61, 38), world_seed, &world_rng)
+    }
+
+    //an area of the given size with its navigation graph built, each tile picked by position and walkable
+    //unless the tile never is
+    pub(crate) fn area_from_fn<F: Fn(usize, usize) -> Tile>((w, h): (usize, usize), diagonals: DiagonalPolicy, tile: F) -> Area {
+        let mut area = Area::new();
+        for y in 0..h {
+            for x in 0..w {
+                let t = tile(x, y);
+                area.tiles.push(TileBlock::new(t, t.default_passable()));
+            }
+        }
+        build_nav(&mut area, (w, h), diagonals);
+        area
+    }
+
+    //an area drawn as rows listed north first: '#' wall, '=' road, ',' heavy grass, '~' shallow water and
+    //anything else plain ground
+    pub(crate) fn area_from_rows(rows: &[&str], diagonals: DiagonalPolicy) -> Area {
+        let h = rows.len();
+        let cells: Vec<Vec<char>> = rows.iter().map(|r| r.chars().collect()).collect();
+        area_from_fn((cells[0].len(), h), diagonals, |x, y| match cells[h - 1 - y][x] {
+            '#' => Tile::WoodWall,
+            '=' => Tile::Road,
+            ',' => Tile::GrassyHeavy,
+            '~' => Tile::ShallowWater,
+            _ => Tile::Plain,
+        })
+    }
+
+    //tiles and biomes of the row or column two neighbours share; east compares columns, north rows
+    fn edge_mismatches(a: &Area, b: &Area, (w, h): (usize, usize), east: bool) -> Vec<usize> {
+        let mut mismatches = Vec::new();
+        let len = if east { h } else { w };
+        for i in 0..len {
+            let (ai, bi) = if east {
+                ((w - 1) + i * w, i * w)
+            }else{
+                (i + (h - 1) * w, i)
+            };
+            if a.tiles[ai].tile != b.tiles[bi].tile || a.tiles[ai].passable != b.tiles[bi].passable || a.biomes[ai] != b.biomes[bi] {
+                mismatches.push(i);
+            }
+        }
+        mismatches
+    }
+
+    //every area in the range against its east and north neighbours, the whole pipeline included
+    fn check_edges(seed: u64, (x0, y0): (i32, i32), (x1, y1): (i32, i32), layer: i32) {
+        let generator = generator();
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let mut ctx = context(seed, (x, y, layer));
+                let dim = (ctx.width, ctx.height);
+                let area = generator.generate(&mut ctx);
+                for (offset, east) in [((1, 0), true), ((0, 1), false)].iter() {
+                    let location = (x + offset.0, y + offset.1, layer);
+                    let neighbour = generator.generate(&mut context(seed, location));
+                    let mismatches = edge_mismatches(&area, &neighbour, dim, *east);
+                    assert!(mismatches.is_empty(), "seed {}: edge {:?} -> {:?} differs at {:?}", seed, (x, y, layer), location, mismatches);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn surface_edges_line_up() {
+        check_edges(7, (0, 0), (2, 2), 0);
+        check_edges(1234, (-3, 5), (-1, 6), 0);
+        check_edges(99, (-1, -1), (0, 0), 0);
+    }
+
+    #[test]
+    fn cave_edges_line_up() {
+        check_edges(7, (0, 0), (1, 1), -1);
+        check_edges(1234, (-2, 3), (-1, 4), -2);
+    }
+}
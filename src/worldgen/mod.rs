@@ -0,0 +1,26 @@
+pub use self::generator::{GenContext, GenPass, GenStage, WorldGenerator, PassGenerator};
+pub use self::terrain::TerrainPass;
+pub use self::biome::{BiomePass, Biome, BiomeSet, PaletteTile};
+pub use self::water::WaterPass;
+pub use self::settlement::{SettlementPass, SettlementPlanner, Settlement, Building, GlobalRect, settlement_region, owning_area};
+pub use self::structures::{StructurePass, generate_structures, find_doors};
+pub use self::roads::{RoadPass, edge_crossings, lay_roads};
+pub use self::cave::{CavePass, StairsPass, cave_entrances, cave_stairs_down, stairs_down};
+pub use self::navigation::{NavigationPass, NavGraph, DiagonalPolicy, build_nav};
+pub use self::connectivity::{ConnectivityPass, connected_regions, repair_connectivity};
+pub use self::portals::{PortalPass, Portal, Crossing, build_portals, costs_from, plan_route};
+pub use self::tmx::{TiledPass, TiledArea, LandmarkSet, LandmarkDef, import_tmx, export_tmx, area_tmx, tileset_tsx};
+#[cfg(test)]
+pub(crate) use self::generator::tests::{area_from_fn, area_from_rows};
+mod generator;
+mod terrain;
+mod biome;
+mod water;
+mod settlement;
+mod structures;
+mod roads;
+mod cave;
+mod navigation;
+mod connectivity;
+mod portals;
+mod tmx;
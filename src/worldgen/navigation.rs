@@ -0,0 +1,324 @@
+use serde::{Serialize, Deserialize};
+use pathfinding::prelude::astar;
+
+use crate::game_state::{Area, Anchor};
+use crate::worldgen::{GenContext, GenPass, GenStage};
+
+//the cheapest single step there is, a straight one along a road; keeps the estimate below the real cost
+pub(crate) const MIN_STEP_COST: usize = 6;
+
+//builds the navigation graph: four out of bounds nodes (w, e, n, s) followed by one node per tile
+pub struct NavigationPass {
+    diagonals: DiagonalPolicy,
+}
+
+impl NavigationPass {
+    pub fn new(diagonals: DiagonalPolicy) -> NavigationPass {
+        NavigationPass {
+            diagonals,
+        }
+    }
+}
+
+impl GenPass for NavigationPass {
+    fn stage(&self) -> GenStage {
+        GenStage::Navigation
+    }
+
+    fn applies(&self, _ctx: &GenContext) -> bool {
+        true
+    }
+
+    fn apply(&self, area: &mut Area, ctx: &mut GenContext) {
+        build_nav(area, (ctx.width, ctx.height), self.diagonals);
+    }
+}
+
+//when a diagonal step may pass the corner between its two orthogonal neighbours; set in globals.ron
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+pub enum DiagonalPolicy {
+    Never,
+    BothSidesFree, //no brushing past wall corners at all
+    OneSideFree, //may brush past one corner, but never squeeze between two
+}
+
+//people are a tile wide, so by default they can't slip past wall corners
+impl Default for DiagonalPolicy {
+    fn default() -> Self {
+        DiagonalPolicy::BothSidesFree
+    }
+}
+
+impl DiagonalPolicy {
+    pub fn allows(self, side_a: bool, side_b: bool) -> bool {
+        match self {
+            DiagonalPolicy::Never => false,
+            DiagonalPolicy::BothSidesFree => side_a && side_b,
+            DiagonalPolicy::OneSideFree => side_a || side_b,
+        }
+    }
+}
+
+//walkable connections between the tiles of one area, nodes numbered like the tiles with the four
+//out of bounds nodes in front; successors are kept in one flat list, each node owning a slice of it
+#[derive(Default, Clone, Debug, Serialize, Deserialize)]
+pub struct NavGraph {
+    width: usize,
+    height: usize,
+    offsets: Vec<usize>,
+    edges: Vec<(usize, usize)>,
+}
+
+impl NavGraph {
+    pub fn node(&self, (x, y): (usize, usize)) -> usize {
+        x + y * self.width + 4
+    }
+    pub fn len(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    //false until the graph has been built for an area this size
+    pub fn has_tile(&self, (x, y): (usize, usize)) -> bool {
+        x < self.width && y < self.height && self.node((x, y)) < self.len()
+    }
+    //neighbouring nodes and what it costs to step onto them
+    pub fn successors(&self, node: usize) -> &[(usize, usize)] {
+        &self.edges[self.offsets[node]..self.offsets[node + 1]]
+    }
+    //tile position of a node; out of bounds nodes sit just past their edge like their anchors
+    pub fn local(&self, node: usize) -> (usize, usize) {
+        match node {
+            0 => (usize::max_value(), 0),
+            1 => (self.width, 0),
+            2 => (0, self.height),
+            3 => (0, usize::max_value()),
+            _ => ((node - 4) % self.width, (node - 4) / self.width),
+        }
+    }
+    pub fn anchor(&self, node: usize, location: (i32, i32, i32)) -> Anchor {
+        let (x, y) = self.local(node);
+        Anchor::new(x, y, location)
+    }
+    //lower bound on the cost from a tile node to goal, counting steps to the edge for out of bounds goals
+    fn estimate(&self, node: usize, goal: usize) -> usize {
+        if node < 4 {
+            return 0;
+        }
+        let (x, y) = self.local(node);
+        let steps = match goal {
+            0 => x + 1,
+            1 => self.width - x,
+            2 => self.height - y,
+            3 => y + 1,
+            _ => {
+                let (gx, gy) = self.local(goal);
+                let dx = if x > gx { x - gx } else { gx - x };
+                let dy = if y > gy { y - gy } else { gy - y };
+                dx.max(dy)
+            }
+        };
+        steps * MIN_STEP_COST
+    }
+    //cheapest route from start to goal as a list of nodes, start included, and its cost
+    pub fn find_path(&self, start: usize, goal: usize) -> Option<(Vec<usize>, usize)> {
+        if start >= self.len() || goal >= self.len() {
+            return None;
+        }
+        astar(&start, |n| self.successors(*n).iter().cloned(), |n| self.estimate(*n, goal), |n| *n == goal)
+    }
+    //the same, never stepping on a node avoid picks out, e.g. one somebody is standing on
+    pub fn find_path_avoiding<F: Fn(usize) -> bool>(&self, start: usize, goal: usize, avoid: F) -> Option<(Vec<usize>, usize)> {
+        if start >= self.len() || goal >= self.len() {
+            return None;
+        }
+        let avoid = &avoid;
+        astar(&start, |n| self.successors(*n).iter().cloned().filter(move |(next, _)| !avoid(*next)),
+            |n| self.estimate(*n, goal), |n| *n == goal)
+    }
+}
+
+//rebuilds area.nav from the current tiles, letting diagonal steps past wall corners through as the policy says
+pub fn build_nav(area: &mut Area, (w, h): (usize, usize), diagonals: DiagonalPolicy) {
+    let mut offsets = Vec::with_capacity(w * h + 5);
+    let mut edges = Vec::with_capacity(w * h * 8);
+
+    //out of bounds nodes lead nowhere, leaving the area is handled by whoever walks there
+    for _i in 0..5 {
+        offsets.push(0);
+    }
+
+    for ty in 0..h {
+        for tx in 0..w {
+            if tx == 0 {
+                //add west to succ
+                edges.push((0, 10));
+            }else if tx == w - 1 {
+                //add east to succ
+                edges.push((1, 10));
+            }
+            if ty == 0 {
+                //add south to succ
+                edges.push((3, 10));
+            } else if ty == h - 1 {
+                //add north to succ
+                edges.push((2, 10));
+            }
+            if area.tiles[tx + ty * w].passable {
+                for y in -1..2 {
+                    let py = ty as i32 + y;
+                    if py < 0 {
+                        continue;
+                    }
+                    let ny = py as usize;
+                    if ny >= h {
+                        break;
+                    }
+                    for x in -1..2 {
+                        if x == 0 && y == 0 {
+                            continue;
+                        }
+                        let px = tx as i32 + x;
+                        if px < 0 {
+                            continue;
+                        }
+                        let nx = px as usize;
+                        if nx >= w {
+                            break;
+                        }
+                        let index = nx + ny * w;
+                        if x != 0 && y != 0 {
+                            let side_a = area.tiles[nx + ty * w].passable;
+                            let side_b = area.tiles[tx + ny * w].passable;
+                            if !diagonals.allows(side_a, side_b) {
+                                continue;
+                            }
+                        }
+                        if area.tiles[index].passable {
+                            //roads and paths are quicker to walk and rough ground slower, so routes bend accordingly
+                            edges.push((index + 4, area.tiles[index].tile.step_cost(x != 0 && y != 0)));
+                        }
+                    }
+                }
+            }
+            offsets.push(edges.len());
+        }
+    }
+
+    area.nav = NavGraph {
+        width: w,
+        height: h,
+        offsets,
+        edges,
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_state::Tile;
+    use crate::worldgen::area_from_rows;
+    use pathfinding::prelude::dijkstra;
+
+    //tells whether the graph of a 2x2 area joins the bottom left tile to the top right one
+    fn corner_step(rows: [&str; 2], diagonals: DiagonalPolicy) -> bool {
+        let area = area_from_rows(&rows, diagonals);
+        let (from, to) = (area.nav.node((0, 0)), area.nav.node((1, 1)));
+        area.nav.successors(from).iter().any(|(next, _)| *next == to)
+    }
+
+    #[test]
+    fn never_allows_no_diagonals() {
+        for rows in [["#.", ".#"], ["#.", ".."], ["..", ".#"], ["..", ".."]].iter() {
+            assert!(!corner_step(*rows, DiagonalPolicy::Never));
+        }
+    }
+
+    #[test]
+    fn both_sides_free_keeps_off_every_corner() {
+        assert!(!corner_step(["#.", ".#"], DiagonalPolicy::BothSidesFree));
+        assert!(!corner_step(["#.", ".."], DiagonalPolicy::BothSidesFree));
+        assert!(!corner_step(["..", ".#"], DiagonalPolicy::BothSidesFree));
+        assert!(corner_step(["..", ".."], DiagonalPolicy::BothSidesFree));
+    }
+
+    #[test]
+    fn one_side_free_brushes_past_one_corner_only() {
+        assert!(!corner_step(["#.", ".#"], DiagonalPolicy::OneSideFree));
+        assert!(corner_step(["#.", ".."], DiagonalPolicy::OneSideFree));
+        assert!(corner_step(["..", ".#"], DiagonalPolicy::OneSideFree));
+        assert!(corner_step(["..", ".."], DiagonalPolicy::OneSideFree));
+    }
+
+    fn area(rows: &[&str]) -> Area {
+        area_from_rows(rows, DiagonalPolicy::default())
+    }
+
+    //what a path costs step by step, panicking on a step the graph doesn't have
+    fn walked_cost(nav: &NavGraph, path: &[usize]) -> usize {
+        path.windows(2).map(|step| {
+            nav.successors(step[0]).iter().find(|(n, _)| *n == step[1]).expect("no such step").1
+        }).sum()
+    }
+
+    #[test]
+    fn find_path_goes_round_walls_and_off_the_edge() {
+        let area = area(&[
+            ".....",
+            ".#.#.",
+            "...#.",
+        ]);
+        let nav = &area.nav;
+        let (start, goal) = (nav.node((0, 0)), nav.node((4, 0)));
+        let (path, cost) = nav.find_path(start, goal).unwrap();
+        assert_eq!((path[0], path[path.len() - 1]), (start, goal));
+        assert_eq!(walked_cost(nav, &path), cost);
+        assert!(path.iter().all(|n| *n < 4 || area.tiles[*n - 4].passable));
+
+        //out the east edge, over the wall
+        let (path, cost) = nav.find_path(start, 1).unwrap();
+        assert_eq!(path[path.len() - 1], 1);
+        assert_eq!(walked_cost(nav, &path), cost);
+
+        //with the gap over the wall taken, there's no way round
+        let gap = nav.node((3, 2));
+        assert!(nav.find_path_avoiding(start, nav.node((2, 2)), |n| n == gap).is_some());
+        assert!(nav.find_path_avoiding(start, goal, |n| n == gap).is_none());
+        assert!(nav.find_path(start, nav.node((1, 1))).is_none());
+    }
+
+    #[test]
+    fn routes_bend_towards_cheaper_ground() {
+        let area = area(&[
+            "=======",
+            ",,,,,,,",
+            ".......",
+        ]);
+        let nav = &area.nav;
+        let (path, cost) = nav.find_path(nav.node((0, 1)), nav.node((6, 1))).unwrap();
+        assert!(path.iter().any(|n| *n >= 4 && area.tiles[*n - 4].tile == Tile::Road));
+        assert!(cost < 6 * Tile::GrassyHeavy.step_cost(false));
+    }
+
+    #[test]
+    fn estimates_never_overshoot_the_cheapest_route() {
+        let area = area(&[
+            "=,~.#=",
+            ".#=,~.",
+            "~.=#,=",
+            "=,.=.~",
+            ".~=,#.",
+        ]);
+        let nav = &area.nav;
+        for start in 4..nav.len() {
+            for goal in 0..nav.len() {
+                let cheapest = dijkstra(&start, |n| nav.successors(*n).to_vec(), |n| *n == goal);
+                if let Some((_, cost)) = cheapest {
+                    assert!(nav.estimate(start, goal) <= cost, "estimate over the cost from {} to {}", start, goal);
+                    assert_eq!(nav.find_path(start, goal).map(|(_, c)| c), Some(cost));
+                }
+            }
+        }
+    }
+}
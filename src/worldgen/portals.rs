@@ -0,0 +1,372 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use serde::{Serialize, Deserialize};
+use pathfinding::prelude::astar;
+
+use crate::game_state::{Area, Anchor, WorldMap};
+use crate::worldgen::{GenContext, GenPass, GenStage, NavGraph};
+use crate::worldgen::navigation::MIN_STEP_COST;
+
+const MAX_PORTAL_SPAN: usize = 10; //longer openings are split, so routes don't all funnel through their middle
+const ROUTE_MARGIN: i32 = 1; //areas around the start and goal a route may swing out through
+const CROSSING_COST: usize = 10; //the step out of one area into the next
+
+//finds where each edge of the area can be crossed and what it costs to walk between those places
+pub struct PortalPass;
+
+impl GenPass for PortalPass {
+    fn stage(&self) -> GenStage {
+        GenStage::Portals
+    }
+
+    fn applies(&self, _ctx: &GenContext) -> bool {
+        true
+    }
+
+    fn apply(&self, area: &mut Area, ctx: &mut GenContext) {
+        build_portals(area, (ctx.width, ctx.height));
+    }
+}
+
+//a run of walkable tiles along one edge; neighbours share their edge tiles, so the area on the other side
+//has a matching portal wherever both sides agree
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Portal {
+    pub edge: usize, //w, e, n, s as the out of bounds nodes
+    pub span: (usize, usize), //first and last tile along the edge
+    pub tile: (usize, usize),
+    pub links: Vec<(usize, usize)>, //other portals of the area reachable from this one, with the cost
+}
+
+impl Portal {
+    fn contains(&self, along: usize) -> bool {
+        along >= self.span.0 && along <= self.span.1
+    }
+}
+
+//rebuilds area.portals from area.nav
+pub fn build_portals(area: &mut Area, (w, h): (usize, usize)) {
+    let mut portals = Vec::new();
+    for edge in 0..4 {
+        let len = if edge < 2 { h } else { w };
+        let mut run: Option<usize> = None;
+        for along in 0..=len {
+            let open = along < len && area.tiles[edge_index(edge, along, (w, h))].passable;
+            match (run, open) {
+                (None, true) => run = Some(along),
+                (Some(first), _) if !open || along - first == MAX_PORTAL_SPAN => {
+                    let last = along - 1;
+                    let mid = (first + last) / 2;
+                    let index = edge_index(edge, mid, (w, h));
+                    portals.push(Portal {
+                        edge,
+                        span: (first, last),
+                        tile: (index % w, index / w),
+                        links: Vec::new(),
+                    });
+                    run = if open { Some(along) } else { None };
+                }
+                _ => (),
+            }
+        }
+    }
+
+    for i in 0..portals.len() {
+        let costs = costs_from(&area.nav, area.nav.node(portals[i].tile));
+        let mut links = Vec::new();
+        for (j, other) in portals.iter().enumerate() {
+            let cost = costs[area.nav.node(other.tile)];
+            if j != i && cost != usize::max_value() {
+                links.push((j, cost));
+            }
+        }
+        portals[i].links = links;
+    }
+    area.portals = portals;
+}
+
+fn edge_index(edge: usize, along: usize, (w, h): (usize, usize)) -> usize {
+    match edge {
+        0 => along * w,
+        1 => w - 1 + along * w,
+        2 => along + (h - 1) * w,
+        _ => along,
+    }
+}
+
+//cost of the cheapest walk from start to every node of the graph, usize::max_value() where it can't be reached
+pub fn costs_from(nav: &NavGraph, start: usize) -> Vec<usize> {
+    let mut cost = vec![usize::max_value(); nav.len()];
+    let mut queue = BinaryHeap::new();
+    if start >= nav.len() {
+        return cost;
+    }
+    cost[start] = 0;
+    queue.push(Reverse((0, start)));
+
+    while let Some(Reverse((c, node))) = queue.pop() {
+        if c > cost[node] {
+            continue;
+        }
+        for (next, step) in nav.successors(node).iter() {
+            //leaving the area isn't walking inside it
+            if *next < 4 {
+                continue;
+            }
+            if c + step < cost[*next] {
+                cost[*next] = c + step;
+                queue.push(Reverse((c + step, *next)));
+            }
+        }
+    }
+    cost
+}
+
+//where to leave the current area on the way to a goal further off
+#[derive(Clone, Copy, Debug)]
+pub struct Crossing {
+    pub edge: usize,
+    pub tile: (usize, usize), //last tile in this area
+    pub arrival: (usize, usize), //first tile in the next one
+    pub cost: usize, //estimated for the whole route
+}
+
+impl Crossing {
+    //the point just past the edge, level with the crossing tile, that walks an entity over into the next area
+    pub fn exit(&self, location: (i32, i32, i32), (w, h): (usize, usize)) -> Anchor {
+        let (x, y) = self.tile;
+        match self.edge {
+            0 => Anchor::new(usize::max_value(), y, location),
+            1 => Anchor::new(w, y, location),
+            2 => Anchor::new(x, h, location),
+            _ => Anchor::new(x, usize::max_value(), location),
+        }
+    }
+    pub fn offset(&self) -> (i32, i32) {
+        edge_offset(self.edge)
+    }
+}
+
+fn edge_offset(edge: usize) -> (i32, i32) {
+    match edge {
+        0 => (-1, 0),
+        1 => (1, 0),
+        2 => (0, 1),
+        _ => (0, -1),
+    }
+}
+
+//the edge of the neighbour that touches this one
+fn facing(edge: usize) -> usize {
+    match edge {
+        0 => 1,
+        1 => 0,
+        2 => 3,
+        _ => 2,
+    }
+}
+
+//the same tile seen from the area across the edge
+fn across(edge: usize, (x, y): (usize, usize), (w, h): (usize, usize)) -> (usize, usize) {
+    match edge {
+        0 => (w - 1, y),
+        1 => (0, y),
+        2 => (x, 0),
+        _ => (x, h - 1),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+enum RouteNode {
+    Start,
+    Portal((i32, i32, i32), usize),
+    //an area nobody has been to yet, assumed open all the way across
+    Unexplored((i32, i32, i32)),
+    Goal,
+}
+
+//plans a route over portals from a tile in one area to a goal in another on the same layer, walking
+//through areas that haven't been generated as if they were open ground; returns the first crossing
+pub fn plan_route(world_map: &WorldMap, (w, h): (usize, usize), location: (i32, i32, i32), start: (usize, usize), goal: Anchor) -> Option<Crossing> {
+    let goal_location = goal.area();
+    let area = world_map.get(location)?;
+    if goal_location.2 != location.2 || goal_location == location {
+        return None;
+    }
+
+    let (sw, sh) = ((w - 1) as i64, (h - 1) as i64);
+    let global = |(lx, ly, _): (i32, i32, i32), (x, y): (usize, usize)| (lx as i64 * sw + x as i64, ly as i64 * sh + y as i64);
+    let goal_tile = global(goal_location, goal.local());
+    let bounds = (
+        location.0.min(goal_location.0) - ROUTE_MARGIN,
+        location.1.min(goal_location.1) - ROUTE_MARGIN,
+        location.0.max(goal_location.0) + ROUTE_MARGIN,
+        location.1.max(goal_location.1) + ROUTE_MARGIN,
+    );
+    let in_bounds = |(lx, ly, _): (i32, i32, i32)| lx >= bounds.0 && ly >= bounds.1 && lx <= bounds.2 && ly <= bounds.3;
+    //crossing an area nobody has seen, about half the way across and half the way up
+    let unexplored_cost = (w + h) / 2 * 10;
+
+    let start_costs = costs_from(&area.nav, area.nav.node(start));
+    //walking is near enough the same cost either way, so costs out from the goal stand in for costs into it
+    let goal_costs = world_map.get(goal_location).map(|a| costs_from(&a.nav, a.nav.node(goal.local())));
+
+    //everything in an area entered at the portal on the given edge
+    let enter = |next: (i32, i32, i32), edge: usize, arrival: Option<(usize, usize)>, cost: usize, out: &mut Vec<(RouteNode, usize)>| {
+        if !in_bounds(next) {
+            return;
+        }
+        match world_map.get(next) {
+            Some(a) => {
+                for (j, portal) in a.portals.iter().enumerate() {
+                    let along = match arrival {
+                        Some((x, y)) => if edge < 2 { y } else { x },
+                        None => if edge < 2 { portal.tile.1 } else { portal.tile.0 },
+                    };
+                    if portal.edge == edge && portal.contains(along) {
+                        out.push((RouteNode::Portal(next, j), cost));
+                    }
+                }
+            }
+            None => out.push((RouteNode::Unexplored(next), cost)),
+        }
+    };
+
+    let successors = |node: &RouteNode| -> Vec<(RouteNode, usize)> {
+        let mut out = Vec::new();
+        match *node {
+            RouteNode::Start => {
+                for (i, portal) in area.portals.iter().enumerate() {
+                    let cost = start_costs[area.nav.node(portal.tile)];
+                    if cost != usize::max_value() {
+                        out.push((RouteNode::Portal(location, i), cost));
+                    }
+                }
+            }
+            RouteNode::Portal(at, i) => {
+                let here = world_map.get(at).unwrap();
+                let portal = &here.portals[i];
+                for (j, cost) in portal.links.iter() {
+                    out.push((RouteNode::Portal(at, *j), *cost));
+                }
+                if at == goal_location {
+                    if let Some(costs) = goal_costs.as_ref() {
+                        let cost = costs[here.nav.node(portal.tile)];
+                        if cost != usize::max_value() {
+                            out.push((RouteNode::Goal, cost));
+                        }
+                    }
+                }
+                let (dx, dy) = edge_offset(portal.edge);
+                let next = (at.0 + dx, at.1 + dy, at.2);
+                let arrival = across(portal.edge, portal.tile, (w, h));
+                enter(next, facing(portal.edge), Some(arrival), CROSSING_COST, &mut out);
+            }
+            RouteNode::Unexplored(at) => {
+                if at == goal_location {
+                    out.push((RouteNode::Goal, unexplored_cost / 2));
+                }
+                for edge in 0..4 {
+                    let (dx, dy) = edge_offset(edge);
+                    let next = (at.0 + dx, at.1 + dy, at.2);
+                    enter(next, facing(edge), None, unexplored_cost, &mut out);
+                }
+            }
+            RouteNode::Goal => (),
+        }
+        out
+    };
+
+    let estimate = |node: &RouteNode| -> usize {
+        let (gx, gy) = goal_tile;
+        let (dx, dy) = match *node {
+            RouteNode::Start => {
+                let (x, y) = global(location, start);
+                (x - gx, y - gy)
+            }
+            RouteNode::Portal(at, i) => {
+                let (x, y) = global(at, world_map.get(at).unwrap().portals[i].tile);
+                (x - gx, y - gy)
+            }
+            //from the nearest point of the area
+            RouteNode::Unexplored(at) => {
+                let (x0, y0) = global(at, (0, 0));
+                (gx.max(x0).min(x0 + sw) - gx, gy.max(y0).min(y0 + sh) - gy)
+            }
+            RouteNode::Goal => (0, 0),
+        };
+        dx.abs().max(dy.abs()) as usize * MIN_STEP_COST
+    };
+
+    let (route, cost) = astar(&RouteNode::Start, successors, estimate, |n| *n == RouteNode::Goal)?;
+    //the route may visit a few portals of this area before it leaves by the last of them
+    let exit = route.iter().skip(1).take_while(|n| match n {
+        RouteNode::Portal(at, _) => *at == location,
+        _ => false,
+    }).last();
+    match exit {
+        Some(RouteNode::Portal(_, i)) => {
+            let portal = &area.portals[*i];
+            Some(Crossing {
+                edge: portal.edge,
+                tile: portal.tile,
+                arrival: across(portal.edge, portal.tile, (w, h)),
+                cost,
+            })
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_state::Tile;
+    use crate::worldgen::{DiagonalPolicy, area_from_fn};
+
+    const DIM: (usize, usize) = (7, 5);
+
+    //every area around the origin, walls placed by global tile so neighbours agree on their shared edges
+    fn world<F: Fn(i64, i64) -> bool>(wall: F) -> WorldMap {
+        let (w, h) = DIM;
+        let mut world_map = WorldMap::new();
+        for ly in -1..=1 {
+            for lx in -1..=2 {
+                let mut area = area_from_fn(DIM, DiagonalPolicy::default(), |x, y| {
+                    let (gx, gy) = (lx as i64 * (w - 1) as i64 + x as i64, ly as i64 * (h - 1) as i64 + y as i64);
+                    if wall(gx, gy) { Tile::WoodWall } else { Tile::Plain }
+                });
+                build_portals(&mut area, DIM);
+                world_map.insert((lx, ly, 0), area);
+            }
+        }
+        world_map
+    }
+
+    fn route(world_map: &WorldMap) -> Option<Crossing> {
+        plan_route(world_map, DIM, (0, 0, 0), (1, 2), Anchor::new(4, 2, (1, 0, 0)))
+    }
+
+    #[test]
+    fn open_edges_are_crossed_straight_over() {
+        let crossing = route(&world(|_, _| false)).unwrap();
+        assert_eq!(crossing.edge, 1);
+        assert_eq!(crossing.offset(), (1, 0));
+        assert_eq!(crossing.arrival, (0, crossing.tile.1));
+    }
+
+    #[test]
+    fn walled_edges_are_gone_round() {
+        //the shared edge is walled along the whole of the two areas
+        let crossing = route(&world(|gx, gy| gx == 6 && (0..=4).contains(&gy))).unwrap();
+        assert!(crossing.edge == 2 || crossing.edge == 3, "left by edge {}", crossing.edge);
+        let (w, h) = DIM;
+        assert_eq!(crossing.arrival, if crossing.edge == 2 { (crossing.tile.0, 0) } else { (crossing.tile.0, h - 1) });
+        assert!(crossing.tile.0 < w - 1);
+
+        //and with the start walled in there's no way out at all
+        let boxed = world(|gx, gy| (gx == 6 && (0..=4).contains(&gy)) || ((gy == 0 || gy == 4) && (0..=6).contains(&gx)) || (gx == 0 && (0..=4).contains(&gy)));
+        assert!(route(&boxed).is_none());
+    }
+}
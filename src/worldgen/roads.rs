@@ -0,0 +1,328 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::sync::Arc;
+
+use rand::Rng;
+
+use crate::game_state::{Area, Tile, TileBlock, WorldRng, RngStream, ROAD_EDGE_CHANCE};
+use crate::worldgen::{GenContext, GenPass, GenStage, SettlementPlanner, Settlement, settlement_region, find_doors};
+
+//route cost of one bridge tile against one tile of road
+const BRIDGE_COST: usize = 4;
+
+//links every door to the area's roads and runs roads out to the points where they cross into the neighbours;
+//a crossing depends only on the edge it sits on, so roads carry on where the next area picks them up
+pub struct RoadPass {
+    planner: Arc<SettlementPlanner>,
+}
+
+impl RoadPass {
+    pub fn new(planner: Arc<SettlementPlanner>) -> RoadPass {
+        RoadPass {
+            planner,
+        }
+    }
+}
+
+impl GenPass for RoadPass {
+    fn stage(&self) -> GenStage {
+        GenStage::Roads
+    }
+
+    fn apply(&self, area: &mut Area, ctx: &mut GenContext) {
+        let (w, h) = (ctx.width, ctx.height);
+        let (lx, ly) = ctx.surface_location();
+
+        //settlements nearby, so crossings never land on a wall or building
+        let mut regions = Vec::new();
+        for location in [(lx, ly), (lx - 1, ly), (lx + 1, ly), (lx, ly - 1), (lx, ly + 1)].iter() {
+            let region = settlement_region(*location);
+            if !regions.contains(&region) {
+                regions.push(region);
+            }
+        }
+        let settlements: Vec<Arc<Settlement>> = regions.iter().filter_map(|r| self.planner.plan(&ctx.world_rng, *r, (w, h))).collect();
+
+        let mut crossings = Vec::new();
+        for (x, y) in edge_crossings(&ctx.world_rng, ctx.surface_location(), (w, h)) {
+            let pos = ctx.global_index(x, y);
+            let settled = settlements.iter().any(|s| match s.tile_at(pos) {
+                Some(block) => block.tile != Tile::Road,
+                None => false,
+            });
+            if !settled {
+                crossings.push(x + y * w);
+            }
+        }
+
+        lay_roads(area, (w, h), &crossings);
+    }
+}
+
+//tiles on the area edge where roads leave it, in w, e, n, s order
+pub fn edge_crossings(world_rng: &WorldRng, (lx, ly): (i32, i32), (w, h): (usize, usize)) -> Vec<(usize, usize)> {
+    let mut crossings = Vec::new();
+    if let Some(y) = edge_crossing(world_rng, (lx - 1, ly), RngStream::RoadsEast, h) {
+        crossings.push((0, y));
+    }
+    if let Some(y) = edge_crossing(world_rng, (lx, ly), RngStream::RoadsEast, h) {
+        crossings.push((w - 1, y));
+    }
+    if let Some(x) = edge_crossing(world_rng, (lx, ly), RngStream::RoadsNorth, w) {
+        crossings.push((x, h - 1));
+    }
+    if let Some(x) = edge_crossing(world_rng, (lx, ly - 1), RngStream::RoadsNorth, w) {
+        crossings.push((x, 0));
+    }
+    crossings
+}
+
+//the east or north edge of the area at location, seen from either side
+fn edge_crossing(world_rng: &WorldRng, location: (i32, i32), stream: RngStream, len: usize) -> Option<usize> {
+    let mut rng = world_rng.area_rng(location, stream);
+    if rng.gen::<f32>() >= ROAD_EDGE_CHANCE {
+        return None;
+    }
+    Some(rng.gen_range(2, len - 2))
+}
+
+//joins the crossings with roads and every door with a path; existing road tiles count as already joined
+pub fn lay_roads(area: &mut Area, (w, h): (usize, usize), crossings: &[usize]) {
+    let mut blocked = vec![false; w * h];
+    for index in 0..w * h {
+        let (x, y) = (index % w, index / w);
+        let edge = x == 0 || y == 0 || x == w - 1 || y == h - 1;
+        //roads only touch the edge where they cross it, so neighbours always agree on their shared tiles;
+        //water gets bridged rather than walked around
+        let wall = !area.tiles[index].passable && !area.tiles[index].tile.is_water();
+        blocked[index] = wall || (edge && !crossings.contains(&index));
+    }
+    for rect in area.structures.iter() {
+        for y in rect.y..(rect.y + rect.h) {
+            for x in rect.x..(rect.x + rect.w) {
+                blocked[x + y * w] = true;
+            }
+        }
+    }
+
+    let mut targets: Vec<(usize, Tile)> = crossings.iter().filter(|i| !blocked[**i]).map(|i| (*i, Tile::Road)).collect();
+    for (x, y) in door_steps(area, (w, h), &blocked) {
+        targets.push((x + y * w, Tile::Path));
+    }
+
+    let mut network: Vec<bool> = area.tiles.iter().map(|t| match t.tile {
+        Tile::Road | Tile::Path | Tile::Bridge => true,
+        _ => false,
+    }).collect();
+    if !network.iter().any(|n| *n) {
+        match targets.len() {
+            0 => return,
+            //a lone road runs out in the middle of the area
+            1 => {
+                let centre = w / 2 + h / 2 * w;
+                if !blocked[centre] {
+                    targets.insert(0, (centre, Tile::Road));
+                }
+            }
+            _ => (),
+        }
+        let (first, tile) = targets.remove(0);
+        paint(area, first, tile);
+        network[first] = true;
+    }
+
+    for (start, tile) in targets {
+        if let Some(route) = route_to_network(area, &blocked, &network, (w, h), start) {
+            for index in route {
+                paint(area, index, tile);
+                network[index] = true;
+            }
+        }
+    }
+}
+
+//the walkable tile just outside each door
+fn door_steps(area: &Area, (w, h): (usize, usize), blocked: &[bool]) -> Vec<(usize, usize)> {
+    let mut steps = Vec::new();
+    for (x, y) in find_doors(area, w) {
+        let mut next = Vec::new();
+        if x > 0 {
+            next.push((x - 1, y));
+        }
+        if x + 1 < w {
+            next.push((x + 1, y));
+        }
+        if y > 0 {
+            next.push((x, y - 1));
+        }
+        if y + 1 < h {
+            next.push((x, y + 1));
+        }
+        if let Some(step) = next.into_iter().find(|(nx, ny)| !blocked[nx + ny * w]) {
+            steps.push(step);
+        }
+    }
+    steps
+}
+
+//floors stay as they are, a path never downgrades a road and either one crosses water on a bridge
+fn paint(area: &mut Area, index: usize, tile: Tile) {
+    let block = &mut area.tiles[index];
+    match block.tile {
+        Tile::WoodFloor | Tile::WoodWall | Tile::Road | Tile::Bridge => (),
+        Tile::DeepWater | Tile::ShallowWater => *block = TileBlock::new(Tile::Bridge, true),
+        _ => block.tile = tile,
+    }
+}
+
+//cheapest orthogonal route from start to the nearest network tile, without the network tile itself;
+//bridges cost more to build than roads, so they're kept short
+fn route_to_network(area: &Area, blocked: &[bool], network: &[bool], (w, h): (usize, usize), start: usize) -> Option<Vec<usize>> {
+    let mut cost = vec![usize::max_value(); w * h];
+    let mut prev = vec![None; w * h];
+    let mut queue = BinaryHeap::new();
+    cost[start] = 0;
+    queue.push(Reverse((0, start)));
+
+    while let Some(Reverse((c, index))) = queue.pop() {
+        if c > cost[index] {
+            continue;
+        }
+        if network[index] {
+            let mut route = Vec::new();
+            let mut curr = prev[index];
+            while let Some(i) = curr {
+                route.push(i);
+                curr = prev[i];
+            }
+            return Some(route);
+        }
+
+        let (x, y) = (index % w, index / w);
+        let mut next = Vec::new();
+        if x > 0 {
+            next.push(index - 1);
+        }
+        if x + 1 < w {
+            next.push(index + 1);
+        }
+        if y > 0 {
+            next.push(index - w);
+        }
+        if y + 1 < h {
+            next.push(index + w);
+        }
+
+        for n in next {
+            if blocked[n] && !network[n] {
+                continue;
+            }
+            let step = if area.tiles[n].tile.is_water() { BRIDGE_COST } else { 1 };
+            if c + step < cost[n] {
+                cost[n] = c + step;
+                prev[n] = Some(index);
+                queue.push(Reverse((c + step, n)));
+            }
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const W: usize = 9;
+    const H: usize = 7;
+
+    fn plain() -> Area {
+        let mut area = Area::new();
+        area.tiles = vec![TileBlock::new(Tile::Plain, true); W * H];
+        area
+    }
+
+    fn on_road(area: &Area, index: usize) -> bool {
+        match area.tiles[index].tile {
+            Tile::Road | Tile::Bridge => true,
+            _ => false,
+        }
+    }
+
+    //the road tiles reachable from start, orthogonally
+    fn reached(area: &Area, start: usize) -> Vec<bool> {
+        let mut seen = vec![false; W * H];
+        let mut open = vec![start];
+        while let Some(i) = open.pop() {
+            if seen[i] || !on_road(area, i) {
+                continue;
+            }
+            seen[i] = true;
+            let (x, y) = (i % W, i / W);
+            if x > 0 {
+                open.push(i - 1);
+            }
+            if x + 1 < W {
+                open.push(i + 1);
+            }
+            if y > 0 {
+                open.push(i - W);
+            }
+            if y + 1 < H {
+                open.push(i + W);
+            }
+        }
+        seen
+    }
+
+    #[test]
+    fn roads_join_crossings_and_only_touch_the_edge_there() {
+        let mut area = plain();
+        let crossings = [2 * W, 5 + (H - 1) * W];
+        lay_roads(&mut area, (W, H), &crossings);
+
+        assert!(crossings.iter().all(|c| area.tiles[*c].tile == Tile::Road));
+        assert!(reached(&area, crossings[0])[crossings[1]]);
+        for index in 0..W * H {
+            let (x, y) = (index % W, index / W);
+            if x == 0 || y == 0 || x == W - 1 || y == H - 1 {
+                assert_eq!(on_road(&area, index), crossings.contains(&index), "road on the edge at {:?}", (x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn lone_crossings_run_to_the_middle_and_water_is_bridged() {
+        let mut area = plain();
+        for y in 0..H {
+            area.tiles[2 + y * W] = TileBlock::new(Tile::DeepWater, false);
+        }
+        let crossing = 3 * W;
+        lay_roads(&mut area, (W, H), &[crossing]);
+
+        let centre = W / 2 + H / 2 * W;
+        assert!(reached(&area, crossing)[centre]);
+        assert_eq!(area.tiles[2 + 3 * W].tile, Tile::Bridge);
+        assert!(area.tiles[2 + 3 * W].passable);
+    }
+
+    #[test]
+    fn neighbours_agree_on_their_shared_crossings() {
+        let world_rng = WorldRng::new(7);
+        let (w, h) = (61, 38);
+        for lx in -3..3 {
+            for ly in -3..3 {
+                let here = edge_crossings(&world_rng, (lx, ly), (w, h));
+                let east = edge_crossings(&world_rng, (lx + 1, ly), (w, h));
+                let north = edge_crossings(&world_rng, (lx, ly + 1), (w, h));
+
+                let mine: Vec<_> = here.iter().filter(|(x, _)| *x == w - 1).map(|(_, y)| *y).collect();
+                let theirs: Vec<_> = east.iter().filter(|(x, _)| *x == 0).map(|(_, y)| *y).collect();
+                assert_eq!(mine, theirs);
+
+                let mine: Vec<_> = here.iter().filter(|(_, y)| *y == h - 1).map(|(x, _)| *x).collect();
+                let theirs: Vec<_> = north.iter().filter(|(_, y)| *y == 0).map(|(x, _)| *x).collect();
+                assert_eq!(mine, theirs);
+            }
+        }
+    }
+}
@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use rand::Rng;
+
+use crate::game_state::{Area, Rect, Tile, TileBlock, WorldRng, RngStream,
+    SETTLEMENT_REGION_AREAS, SETTLEMENT_CHANCE, SETTLEMENT_RADIUS_LOWER, SETTLEMENT_RADIUS_UPPER};
+use crate::worldgen::{GenContext, GenPass, GenStage};
+
+const PLAZA_WIDTH: i64 = 10;
+const PLAZA_HEIGHT: i64 = 6;
+const BUILDING_GAP: i64 = 2; //free tiles kept around every building
+const BUILDING_WIDTH: (i64, i64) = (6, 11); //lower bound inclusive, upper exclusive
+const BUILDING_HEIGHT: (i64, i64) = (5, 8);
+
+//a rectangle in global tile coordinates, see GenContext::global_tile
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct GlobalRect {
+    pub x: i64,
+    pub y: i64,
+    pub w: i64,
+    pub h: i64,
+}
+
+impl GlobalRect {
+    pub fn new((x, y): (i64, i64), (w, h): (i64, i64)) -> GlobalRect {
+        GlobalRect {
+            x,
+            y,
+            w,
+            h,
+        }
+    }
+    pub fn contains(&self, (x, y): (i64, i64)) -> bool {
+        x >= self.x && x < self.x + self.w && y >= self.y && y < self.y + self.h
+    }
+    //true if the rects come closer than gap tiles
+    pub fn overlaps(&self, o: &GlobalRect, gap: i64) -> bool {
+        self.x - gap < o.x + o.w && o.x - gap < self.x + self.w && self.y - gap < o.y + o.h && o.y - gap < self.y + self.h
+    }
+    pub fn on_border(&self, (x, y): (i64, i64)) -> bool {
+        self.contains((x, y)) && (x == self.x || x == self.x + self.w - 1 || y == self.y || y == self.y + self.h - 1)
+    }
+    pub fn center(&self) -> (i64, i64) {
+        (self.x + self.w / 2, self.y + self.h / 2)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Building {
+    pub rect: GlobalRect,
+    pub door: (i64, i64),
+}
+
+//a village laid out across several areas around a central plaza, fenced by a palisade with four gates
+#[derive(Clone, Debug)]
+pub struct Settlement {
+    pub region: (i32, i32),
+    pub centre: (i64, i64),
+    pub radius: i64,
+    pub plaza: GlobalRect,
+    pub buildings: Vec<Building>,
+}
+
+impl Settlement {
+    //everything the settlement touches, palisade included
+    pub fn bounds(&self) -> GlobalRect {
+        let (cx, cy) = self.centre;
+        let r = self.radius + 1;
+        GlobalRect::new((cx - r, cy - r), (r * 2 + 1, r * 2 + 1))
+    }
+    //the two streets crossing at the plaza, each running out through a pair of gates
+    pub fn streets(&self) -> [GlobalRect; 2] {
+        streets(self.centre, self.radius)
+    }
+    fn in_palisade(&self, (x, y): (i64, i64)) -> bool {
+        let (dx, dy) = (x - self.centre.0, y - self.centre.1);
+        let d2 = dx * dx + dy * dy;
+        let gate = dx.abs() <= 1 || dy.abs() <= 1;
+        !gate && d2 >= self.radius * self.radius && d2 < (self.radius + 1) * (self.radius + 1)
+    }
+    //what the settlement builds at a global tile, if anything
+    pub fn tile_at(&self, pos: (i64, i64)) -> Option<TileBlock> {
+        for building in self.buildings.iter() {
+            if building.rect.contains(pos) {
+                if pos != building.door && building.rect.on_border(pos) {
+                    return Some(TileBlock::new(Tile::WoodWall, false));
+                }
+                return Some(TileBlock::new(Tile::WoodFloor, true));
+            }
+        }
+        if self.plaza.contains(pos) {
+            return Some(TileBlock::new(Tile::WoodFloor, true));
+        }
+        if self.streets().iter().any(|street| street.contains(pos)) {
+            return Some(TileBlock::new(Tile::Road, true));
+        }
+        if self.in_palisade(pos) {
+            return Some(TileBlock::new(Tile::WoodWall, false));
+        }
+        None
+    }
+    //area coordinates the settlement reaches into, for areas (w, h) tiles across
+    pub fn areas(&self, (w, h): (usize, usize)) -> Vec<(i32, i32)> {
+        let (sw, sh) = ((w - 1) as i64, (h - 1) as i64);
+        let bounds = self.bounds();
+        let mut areas = Vec::new();
+        for ay in div_floor(bounds.y, sh)..=div_floor(bounds.y + bounds.h - 1, sh) {
+            for ax in div_floor(bounds.x, sw)..=div_floor(bounds.x + bounds.w - 1, sw) {
+                areas.push((ax as i32, ay as i32));
+            }
+        }
+        areas
+    }
+}
+
+fn div_floor(a: i64, b: i64) -> i64 {
+    let d = a / b;
+    if a % b != 0 && (a < 0) != (b < 0) {
+        d - 1
+    }else{
+        d
+    }
+}
+
+fn streets((cx, cy): (i64, i64), radius: i64) -> [GlobalRect; 2] {
+    let r = radius + 1;
+    [GlobalRect::new((cx - r, cy), (r * 2 + 1, 1)), GlobalRect::new((cx, cy - r), (1, r * 2 + 1))]
+}
+
+//the area holding a global tile and the tile's place in it; tiles on a shared edge belong to the area east or south
+//of it, so every tile has exactly one owner
+pub fn owning_area((x, y): (i64, i64), (w, h): (usize, usize)) -> ((i32, i32), (usize, usize)) {
+    let (sw, sh) = ((w - 1) as i64, (h - 1) as i64);
+    let (ax, ay) = (div_floor(x, sw), div_floor(y, sh));
+    ((ax as i32, ay as i32), ((x - ax * sw) as usize, (y - ay * sh) as usize))
+}
+
+//the region a settlement is planned for; every area belongs to exactly one
+pub fn settlement_region((x, y): (i32, i32)) -> (i32, i32) {
+    (div_floor(x as i64, SETTLEMENT_REGION_AREAS as i64) as i32, div_floor(y as i64, SETTLEMENT_REGION_AREAS as i64) as i32)
+}
+
+//lays out at most one settlement per region, the same way every time for a given world seed; plans are kept,
+//so the areas of a region, their neighbours and the residents all share one layout instead of rolling it again
+#[derive(Default)]
+pub struct SettlementPlanner {
+    plans: Mutex<HashMap<(u64, (i32, i32), (usize, usize)), Option<Arc<Settlement>>>>,
+}
+
+impl SettlementPlanner {
+    pub fn new() -> SettlementPlanner {
+        SettlementPlanner::default()
+    }
+
+    pub fn plan(&self, world_rng: &WorldRng, region: (i32, i32), dim: (usize, usize)) -> Option<Arc<Settlement>> {
+        let mut plans = self.plans.lock().expect("settlement plans poisoned");
+        plans.entry((world_rng.seed(), region, dim))
+            .or_insert_with(|| SettlementPlanner::roll(world_rng, region, dim).map(Arc::new))
+            .clone()
+    }
+
+    fn roll(world_rng: &WorldRng, region: (i32, i32), (w, h): (usize, usize)) -> Option<Settlement> {
+        let mut rng = world_rng.area_rng(region, RngStream::Settlements);
+        if rng.gen::<f32>() >= SETTLEMENT_CHANCE {
+            return None;
+        }
+
+        //settlements stay inside their region so neighbouring ones never overlap
+        let n = SETTLEMENT_REGION_AREAS as i64;
+        let (span_x, span_y) = (n * (w - 1) as i64, n * (h - 1) as i64);
+        let radius = rng.gen_range(SETTLEMENT_RADIUS_LOWER, SETTLEMENT_RADIUS_UPPER).min((span_x.min(span_y) - 6) / 2);
+        //regions of small areas have no room for even the widest building
+        if radius * 2 < BUILDING_WIDTH.1 {
+            return None;
+        }
+        let cx = region.0 as i64 * span_x + rng.gen_range(radius + 2, span_x - radius - 2);
+        let cy = region.1 as i64 * span_y + rng.gen_range(radius + 2, span_y - radius - 2);
+
+        let plaza = GlobalRect::new((cx - PLAZA_WIDTH / 2, cy - PLAZA_HEIGHT / 2), (PLAZA_WIDTH, PLAZA_HEIGHT));
+        let streets = streets((cx, cy), radius);
+
+        let mut buildings: Vec<Building> = Vec::new();
+        for _i in 0..radius {
+            let bw = rng.gen_range(BUILDING_WIDTH.0, BUILDING_WIDTH.1);
+            let bh = rng.gen_range(BUILDING_HEIGHT.0, BUILDING_HEIGHT.1);
+            let bx = cx + rng.gen_range(-radius, radius - bw);
+            let by = cy + rng.gen_range(-radius, radius - bh);
+            let rect = GlobalRect::new((bx, by), (bw, bh));
+
+            let inner = (radius - BUILDING_GAP) * (radius - BUILDING_GAP);
+            let corners = [(bx, by), (bx + bw - 1, by), (bx, by + bh - 1), (bx + bw - 1, by + bh - 1)];
+            if corners.iter().any(|(x, y)| (x - cx) * (x - cx) + (y - cy) * (y - cy) >= inner) {
+                continue;
+            }
+            if rect.overlaps(&plaza, BUILDING_GAP) || streets.iter().any(|s| rect.overlaps(s, BUILDING_GAP)) {
+                continue;
+            }
+            if buildings.iter().any(|b| rect.overlaps(&b.rect, BUILDING_GAP)) {
+                continue;
+            }
+
+            //doors face the plaza
+            let (mx, my) = rect.center();
+            let (dx, dy) = (cx - mx, cy - my);
+            let door = if dx.abs() > dy.abs() {
+                if dx > 0 { (bx + bw - 1, my) } else { (bx, my) }
+            }else{
+                if dy > 0 { (mx, by + bh - 1) } else { (mx, by) }
+            };
+            buildings.push(Building {
+                rect,
+                door,
+            });
+        }
+
+        Some(Settlement {
+            region,
+            centre: (cx, cy),
+            radius,
+            plaza,
+            buildings,
+        })
+    }
+}
+
+//carves this area's share of its region's settlement; buildings crossing an edge are finished by the neighbour
+pub struct SettlementPass {
+    planner: Arc<SettlementPlanner>,
+}
+
+impl SettlementPass {
+    pub fn new(planner: Arc<SettlementPlanner>) -> SettlementPass {
+        SettlementPass {
+            planner,
+        }
+    }
+}
+
+impl GenPass for SettlementPass {
+    fn stage(&self) -> GenStage {
+        GenStage::Structures
+    }
+
+    fn apply(&self, area: &mut Area, ctx: &mut GenContext) {
+        if ctx.handcrafted {
+            return;
+        }
+        let w = ctx.width;
+        let h = ctx.height;
+
+        let region = settlement_region(ctx.surface_location());
+        let settlement = match self.planner.plan(&ctx.world_rng, region, (w, h)) {
+            Some(s) => s,
+            None => return,
+        };
+
+        let origin = ctx.global_index(0, 0);
+        let extent = GlobalRect::new(origin, (w as i64, h as i64));
+        if !settlement.bounds().overlaps(&extent, 0) {
+            return;
+        }
+        ctx.settlement = Some(region);
+        area.settlement = Some(region);
+
+        for y in 0..h {
+            for x in 0..w {
+                if let Some(block) = settlement.tile_at(ctx.global_index(x, y)) {
+                    //streets cross rivers on bridges
+                    if block.tile == Tile::Road && area.tiles[x + y * w].tile.is_water() {
+                        area.tiles[x + y * w] = TileBlock::new(Tile::Bridge, true);
+                    }else{
+                        area.tiles[x + y * w] = block;
+                    }
+                }
+            }
+        }
+
+        //each building's part inside this area, in local coordinates
+        for building in settlement.buildings.iter() {
+            let r = building.rect;
+            if !r.overlaps(&extent, 0) {
+                continue;
+            }
+            let x0 = r.x.max(extent.x) - origin.0;
+            let y0 = r.y.max(extent.y) - origin.1;
+            let x1 = (r.x + r.w).min(extent.x + extent.w) - origin.0;
+            let y1 = (r.y + r.h).min(extent.y + extent.h) - origin.1;
+            area.structures.push(Rect::new((x0 as usize, y0 as usize), ((x1 - x0) as usize, (y1 - y0) as usize)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worldgen::generator::tests::context;
+
+    const DIM: (usize, usize) = (61, 38);
+
+    //the first regions along a row that get a settlement for the seed
+    fn settled(world_rng: &WorldRng, count: usize) -> Vec<Arc<Settlement>> {
+        let planner = SettlementPlanner::new();
+        (0..).filter_map(|x| planner.plan(world_rng, (x, 0), DIM)).take(count).collect()
+    }
+
+    #[test]
+    fn plans_are_the_same_for_a_region_every_time() {
+        let world_rng = WorldRng::new(7);
+        let planner = SettlementPlanner::new();
+        for x in -4..4 {
+            let first = planner.plan(&world_rng, (x, 1), DIM);
+            let again = SettlementPlanner::new().plan(&world_rng, (x, 1), DIM);
+            assert_eq!(format!("{:?}", first), format!("{:?}", again));
+            //the planner hands back the plan it made the first time
+            if let (Some(a), Some(b)) = (first, planner.plan(&world_rng, (x, 1), DIM)) {
+                assert!(Arc::ptr_eq(&a, &b));
+            }
+        }
+    }
+
+    #[test]
+    fn tiny_regions_have_no_settlements() {
+        let world_rng = WorldRng::new(7);
+        let planner = SettlementPlanner::new();
+        for x in 0..32 {
+            assert!(planner.plan(&world_rng, (x, 0), (61, 6)).is_none());
+            assert!(planner.plan(&world_rng, (x, 0), (3, 38)).is_none());
+        }
+    }
+
+    #[test]
+    fn buildings_keep_their_distance_inside_the_palisade() {
+        for seed in [7, 1234, 99].iter() {
+            for settlement in settled(&WorldRng::new(*seed), 4) {
+                let (cx, cy) = settlement.centre;
+                let inner = settlement.radius - BUILDING_GAP;
+                for (i, building) in settlement.buildings.iter().enumerate() {
+                    let r = building.rect;
+                    for (x, y) in [(r.x, r.y), (r.x + r.w - 1, r.y), (r.x, r.y + r.h - 1), (r.x + r.w - 1, r.y + r.h - 1)].iter() {
+                        assert!((x - cx) * (x - cx) + (y - cy) * (y - cy) < inner * inner);
+                    }
+                    assert!(!r.overlaps(&settlement.plaza, BUILDING_GAP));
+                    assert!(settlement.streets().iter().all(|s| !r.overlaps(s, BUILDING_GAP)));
+                    assert!(settlement.buildings[i + 1..].iter().all(|b| !r.overlaps(&b.rect, BUILDING_GAP)));
+                    assert!(r.on_border(building.door));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn areas_are_the_ones_the_pass_settles() {
+        let seed = 7;
+        let pass = SettlementPass::new(Arc::new(SettlementPlanner::new()));
+        for settlement in settled(&WorldRng::new(seed), 2) {
+            let n = SETTLEMENT_REGION_AREAS;
+            let (rx, ry) = settlement.region;
+            let mut touched = Vec::new();
+            for ly in ry * n - 1..=ry * n + n {
+                for lx in rx * n - 1..=rx * n + n {
+                    let mut ctx = context(seed, (lx, ly, 0));
+                    let mut area = Area::new();
+                    area.tiles = vec![TileBlock::new(Tile::Plain, true); DIM.0 * DIM.1];
+                    pass.apply(&mut area, &mut ctx);
+                    if area.settlement == Some(settlement.region) {
+                        touched.push((lx, ly));
+                    }
+                }
+            }
+            assert_eq!(touched, settlement.areas(DIM));
+        }
+    }
+
+    #[test]
+    fn every_building_centre_has_one_owning_area() {
+        for settlement in settled(&WorldRng::new(7), 2) {
+            for building in settlement.buildings.iter() {
+                let centre = building.rect.center();
+                let (area, (x, y)) = owning_area(centre, DIM);
+                assert!(x < DIM.0 - 1 && y < DIM.1 - 1);
+                assert_eq!(context(7, (area.0, area.1, 0)).global_index(x, y), centre);
+            }
+        }
+    }
+
+    #[test]
+    fn streets_cross_water_on_bridges() {
+        let seed = 7;
+        let pass = SettlementPass::new(Arc::new(SettlementPlanner::new()));
+        let settlement = settled(&WorldRng::new(seed), 1).remove(0);
+        //the area the streets cross in
+        let (cx, cy) = settlement.centre;
+        let location = (div_floor(cx, DIM.0 as i64 - 1) as i32, div_floor(cy, DIM.1 as i64 - 1) as i32, 0);
+        let mut ctx = context(seed, location);
+        let mut area = Area::new();
+        area.tiles = vec![TileBlock::new(Tile::ShallowWater, true); DIM.0 * DIM.1];
+        pass.apply(&mut area, &mut ctx);
+
+        let mut bridged = 0;
+        for y in 0..DIM.1 {
+            for x in 0..DIM.0 {
+                let tile = area.tiles[x + y * DIM.0];
+                if settlement.tile_at(ctx.global_index(x, y)).map(|b| b.tile) == Some(Tile::Road) {
+                    assert_eq!((tile.tile, tile.passable), (Tile::Bridge, true));
+                    bridged += 1;
+                }
+            }
+        }
+        assert!(bridged > 0);
+    }
+}
@@ -0,0 +1,164 @@
+use noise::{NoiseFn, Billow};
+use rand::Rng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::game_state::{Area, Rect, Tile, TileBlock, STRUCTURE_RESOLUTION_FACTOR};
+use crate::worldgen::{GenContext, GenPass, GenStage};
+
+//splits the area into walled buildings, each with a single door
+pub struct StructurePass;
+
+impl GenPass for StructurePass {
+    fn stage(&self) -> GenStage {
+        GenStage::Structures
+    }
+
+    fn apply(&self, area: &mut Area, ctx: &mut GenContext) {
+        //landmarks and settlements bring their own buildings
+        if ctx.handcrafted || ctx.settlement.is_some() {
+            return;
+        }
+        generate_structures(area, ctx.surface_location(), (ctx.world_seed.6, ctx.world_seed.7), (ctx.width, ctx.height), &mut ctx.rng);
+    }
+}
+
+pub fn generate_structures(area: &mut Area, location: (i32, i32), seed: (f64, f64), dim: (usize, usize), rng: &mut ChaCha8Rng) {
+
+    //println!("calling generate_structures");
+
+    let (map_x, map_y) = location;
+    let (seed_x, seed_y) = seed;
+    let (map_width, map_height) = dim;
+    let billow = Billow::new();
+    let noise_check = billow.get([map_x as f64 * STRUCTURE_RESOLUTION_FACTOR as f64 + seed_x, map_y as f64 * STRUCTURE_RESOLUTION_FACTOR as f64 + seed_y]);
+
+    let mut rect_squad = Vec::<Rect>::new();
+    
+    rect_squad.push(Rect::new((1, 1), (map_width-1, map_height-1)));
+
+    let mut structure_num = ((noise_check.abs() - 0.4).max(0.0) / 0.1) as usize;
+
+    //println!("setup complete; structure_num {}", structure_num);
+
+    while rect_squad.len() < structure_num {
+        let mut temp = Vec::new();
+
+        let randi = rng.gen_range(0, rect_squad.len());
+
+        //println!("randi is {}", randi);
+
+        let chosen_rect = rect_squad[randi];
+
+        //println!("selecting rectangle {:?}", chosen_rect);
+
+        if chosen_rect.w < 5 || chosen_rect.h < 5 {
+            continue;
+        }else{
+            let dir = rng.gen_range(0, 2);
+            let mut split_x = 0;
+            let mut split_y = 0;
+
+            if dir == 0 {
+                //horizontal split
+                let mut lb = chosen_rect.x + chosen_rect.w / 3;
+                let mut rb = chosen_rect.x + chosen_rect.w * 2 / 3;
+                if chosen_rect.w / 3 < 5 && chosen_rect.w / 2 >= 5 {
+                    lb = chosen_rect.x + chosen_rect.w / 2;
+                    rb = chosen_rect.x + chosen_rect.w / 2 + 1;
+                }else if chosen_rect.w / 2 < 5{
+                    structure_num -= 1;
+                    continue;
+                }
+                //println!("left bound {} right bound {}", lb, rb);
+
+                split_x = rng.gen_range(lb, rb);
+            }else{
+                //vertical split
+                let mut lb = chosen_rect.y + chosen_rect.h / 3;
+                let mut ub = chosen_rect.y + chosen_rect.h * 2 / 3;
+                if chosen_rect.h / 3 < 5 && chosen_rect.h / 2 >= 5 {
+                    lb = chosen_rect.y + chosen_rect.h / 2;
+                    ub = chosen_rect.y + chosen_rect.h / 2 + 1;
+                }else if chosen_rect.h / 2 < 5{
+                    structure_num -= 1;
+                    continue;
+                }
+
+                //println!("lower bound {} upper bound {}", lb, ub);
+
+                split_y = rng.gen_range(lb, ub);
+            }
+            temp.append(&mut chosen_rect.split(split_x, split_y));
+        }
+
+        rect_squad.remove(randi);
+        rect_squad.append(&mut temp);
+    }
+    
+    //println!("rectangles split up!");
+
+    for mut rect in rect_squad {
+        //println!("shrinking rectangles");
+
+        let shrinkage = rng.gen_range(2 + (10 - structure_num), 5 + 1 * (10 - structure_num));
+
+        //println!("shrinkage {}", shrinkage);
+
+        rect.shrink((shrinkage as f32 * 1.5) as usize, shrinkage);
+
+        area.structures.push(rect.clone());
+        //println!("transferring rect tiles..");
+
+        for y in rect.y..(rect.y + rect.h) {
+            for x in rect.x..(rect.x + rect.w) {
+                //println!("replacing tile at {:?}", (x, y));
+                if (x == rect.x || x == rect.x + rect.w - 1) || (y == rect.y || y == rect.y + rect.h - 1) {
+                    area.tiles[x + y * map_width] = TileBlock::new(Tile::WoodWall, false);
+                }else{
+                    area.tiles[x + y * map_width] = TileBlock::new(Tile::WoodFloor, true);
+                }
+                
+            }
+        }
+
+        //println!("tiles transferred!");
+
+        let perimeter = rect.perimeter();
+        let randi = rng.gen_range(0, perimeter.len());
+        
+        //println!("door tile index selected! {}", randi);
+
+        let (doorx, doory) = perimeter[randi];
+
+        //println!("door tile is {:?}", (doorx, doory));
+
+        area.tiles[doorx + doory * map_width] = TileBlock::new(Tile::WoodFloor, true);
+
+        //println!("door placed!");
+    }
+
+    //println!("structures generated!");
+}
+
+//walkable tiles set into the walls of each structure; a gap has wall on both sides along the wall line,
+//which rules out the open side of a building cut off by the area edge
+pub fn find_doors(area: &Area, map_width: usize) -> Vec<(usize, usize)> {
+    let mut doors = Vec::new();
+    for rect in area.structures.iter() {
+        for (x, y) in rect.perimeter() {
+            if !area.tiles[x + y * map_width].passable {
+                continue;
+            }
+            let vertical_wall = x == rect.x || x == rect.x + rect.w - 1;
+            let (a, b) = if vertical_wall {
+                ((x, y - 1), (x, y + 1))
+            }else{
+                ((x - 1, y), (x + 1, y))
+            };
+            if !area.tiles[a.0 + a.1 * map_width].passable && !area.tiles[b.0 + b.1 * map_width].passable {
+                doors.push((x, y));
+            }
+        }
+    }
+    doors
+}
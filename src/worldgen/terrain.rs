@@ -0,0 +1,50 @@
+use noise::{NoiseFn, Perlin, Billow};
+
+use crate::game_state::{Area, ZOOM_FACTOR, ADJUSTMENT_ZOOM_FACTOR, NOISE_DISPLACEMENT};
+use crate::worldgen::{GenContext, GenPass, GenStage};
+
+//fills the elevation of every tile in [0, 1)
+pub struct TerrainPass;
+
+impl GenPass for TerrainPass {
+    fn stage(&self) -> GenStage {
+        GenStage::Terrain
+    }
+
+    fn apply(&self, _area: &mut Area, ctx: &mut GenContext) {
+        let w = ctx.width;
+        let h = ctx.height;
+
+        let perlin = Perlin::new();
+        let billow = Billow::new();
+
+        let xseed = ctx.world_seed.0;
+        let yseed = ctx.world_seed.1;
+        let aseed = ctx.world_seed.2;
+        let bseed = ctx.world_seed.3;
+
+        for y in 0..h {
+            for x in 0..w {
+                let (ux, uy) = ctx.global_unit(x, y);
+                let noise = perlin.get([xseed + ux / ZOOM_FACTOR, yseed + uy / ZOOM_FACTOR]);
+                let adjustment = billow.get([aseed + ux / ADJUSTMENT_ZOOM_FACTOR, bseed + uy / ADJUSTMENT_ZOOM_FACTOR]) * NOISE_DISPLACEMENT;
+
+                let mut tilefloat = noise_ease(noise + adjustment);
+
+                if tilefloat >= 1.0 {
+                    tilefloat = 0.99;
+                }else if tilefloat < 0.0 {
+                    tilefloat = 0.0;
+                }
+
+                ctx.elevation[x + y * w] = tilefloat;
+            }
+        }
+    }
+}
+
+fn noise_ease(raw: f64) -> f64{
+    let abs = raw.abs();
+
+    abs
+}
@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+use tiled::{ObjectShape, PropertyValue, TiledError};
+
+use amethyst::utils::application_root_dir;
+
+use crate::game_state::{Area, EntityType, Rect, SpawnPoint, Tile, TileBlock, TILE_SIZE};
+use crate::worldgen::{GenContext, GenPass, GenStage};
+
+//the top three bits of a gid are flip flags
+const GID_MASK: u32 = 0x1FFF_FFFF;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct LandmarkDef {
+    pub location: (i32, i32),
+    pub map: String,
+}
+
+//landmarks.ron: hand authored .tmx areas pinned to world coordinates
+#[derive(Clone, Debug, Deserialize, Default)]
+pub struct LandmarkSet {
+    pub landmarks: Vec<LandmarkDef>,
+}
+
+//tileset written next to exported maps, relative to res/maps/export
+const EXPORT_TILES_IMAGE: &str = "../../textures/tiles.png";
+
+//a .tmx map converted to game tiles; y runs north like the rest of the game
+#[derive(Clone, Debug)]
+pub struct TiledArea {
+    pub width: usize,
+    pub height: usize,
+    pub tiles: Vec<Option<TileBlock>>,
+    pub structures: Vec<Rect>,
+    pub spawn_points: Vec<SpawnPoint>,
+}
+
+//value of an attribute in the text of an xml tag, which may start right at the attribute
+fn xml_attr<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let key = format!("{}=\"", name);
+    let mut from = 0;
+    while let Some(found) = tag[from..].find(&key) {
+        let at = from + found;
+        if at == 0 || tag[..at].ends_with(char::is_whitespace) {
+            let start = at + key.len();
+            let len = tag[start..].find('"')?;
+            return Some(&tag[start..start + len]);
+        }
+        from = at + key.len();
+    }
+    None
+}
+
+//passable properties set on tileset tiles, by first gid and tile id; tiled 0.8 masks the wrong bits out of
+//tile ids, so they are read from the xml here rather than from the parsed tilesets
+fn passable_overrides(path: &Path) -> Result<HashMap<(u32, u32), bool>, TiledError> {
+    let read = |p: &Path| fs::read_to_string(p).map_err(|e| TiledError::Other(format!("could not read {:?}: {}", p, e)));
+    let map = read(path)?;
+    let mut overrides = HashMap::new();
+
+    for chunk in map.split("<tileset").skip(1) {
+        let tag = &chunk[..chunk.find('>').unwrap_or_else(|| chunk.len())];
+        let first_gid = match xml_attr(tag, "firstgid").and_then(|g| g.parse::<u32>().ok()) {
+            Some(g) => g,
+            None => continue,
+        };
+        let tileset = match xml_attr(tag, "source") {
+            Some(source) => read(&path.with_file_name(source))?,
+            None => chunk[..chunk.find("</tileset>").unwrap_or_else(|| chunk.len())].to_string(),
+        };
+        for tile in tileset.split("<tile ").skip(1) {
+            let tile = &tile[..tile.find("</tile>").unwrap_or_else(|| tile.len())];
+            let id = match xml_attr(tile, "id").and_then(|i| i.parse::<u32>().ok()) {
+                Some(i) => i,
+                None => continue,
+            };
+            for property in tile.split("<property").skip(1) {
+                if xml_attr(property, "name") == Some("passable") {
+                    overrides.insert((first_gid, id), xml_attr(property, "value") == Some("true"));
+                }
+            }
+        }
+    }
+    Ok(overrides)
+}
+
+fn tile_from_gid(tmx: &tiled::Map, overrides: &HashMap<(u32, u32), bool>, gid: u32) -> Option<TileBlock> {
+    let tileset = tmx.tilesets.iter().filter(|t| t.first_gid <= gid).max_by_key(|t| t.first_gid)?;
+    let local = gid - tileset.first_gid;
+    if local >= Tile::Size as u32 {
+        return None;
+    }
+    let tile: Tile = num::FromPrimitive::from_u32(local)?;
+
+    let passable = overrides.get(&(tileset.first_gid, local)).cloned().unwrap_or_else(|| tile.default_passable());
+    Some(TileBlock::new(tile, passable))
+}
+
+pub fn import_tmx(path: &Path) -> Result<TiledArea, TiledError> {
+    let tmx = tiled::parse_file(path)?;
+    let overrides = passable_overrides(path)?;
+    let w = tmx.width as usize;
+    let h = tmx.height as usize;
+
+    let mut tiles = vec![None; w * h];
+
+    //later layers paint over earlier ones; empty cells keep what is below
+    for layer in tmx.layers.iter() {
+        for (row, cells) in layer.tiles.iter().enumerate().take(h) {
+            let y = h - 1 - row;
+            for (x, raw) in cells.iter().enumerate().take(w) {
+                let gid = raw & GID_MASK;
+                if gid == 0 {
+                    continue;
+                }
+                match tile_from_gid(&tmx, &overrides, gid) {
+                    Some(block) => tiles[x + y * w] = Some(block),
+                    None => return Err(TiledError::Other(format!("unknown tile gid {} in {:?}", gid, path))),
+                }
+            }
+        }
+    }
+
+    let tw = tmx.tile_width as f32;
+    let th = tmx.tile_height as f32;
+
+    let mut structures = Vec::new();
+    let mut spawn_points = Vec::new();
+
+    for group in tmx.object_groups.iter() {
+        for object in group.objects.iter() {
+            let tx = (object.x / tw) as usize;
+            let mut row = (object.y / th) as usize;
+            //tile objects are anchored at their bottom edge
+            if object.gid != 0 {
+                row = row.saturating_sub(1);
+            }
+            if tx >= w || row >= h {
+                continue;
+            }
+            let ty = h - 1 - row;
+
+            match object.obj_type.as_str() {
+                "structure" => {
+                    if let ObjectShape::Rect { width, height } = object.shape {
+                        let rw = (width / tw) as usize;
+                        let rh = (height / th) as usize;
+                        let bottom = row + rh;
+                        if rw == 0 || rh == 0 || bottom > h {
+                            continue;
+                        }
+                        structures.push(Rect::new((tx, h - bottom), (rw, rh)));
+                    }
+                }
+                "person" => {
+                    let mut point = SpawnPoint::new((tx, ty), EntityType::Person);
+                    if let Some(PropertyValue::StringValue(tree)) = object.properties.get("behaviour") {
+                        point.behaviour = Some(tree.clone());
+                    }
+                    spawn_points.push(point);
+                }
+                "plant" => spawn_points.push(SpawnPoint::new((tx, ty), EntityType::Plant)),
+                _ => {}
+            }
+        }
+    }
+
+    Ok(TiledArea {
+        width: w,
+        height: h,
+        tiles,
+        structures,
+        spawn_points,
+    })
+}
+
+//paints landmark maps over the terrain of their area, centred, and keeps procedural structures out of them
+pub struct TiledPass {
+    landmarks: HashMap<(i32, i32), TiledArea>,
+}
+
+impl TiledPass {
+    pub fn new(landmarks: HashMap<(i32, i32), TiledArea>) -> TiledPass {
+        TiledPass {
+            landmarks,
+        }
+    }
+    //map paths are relative to the application root; maps that fail to import or don't fit in an area of
+    //the given size are skipped
+    pub fn load(set: &LandmarkSet, (w, h): (usize, usize)) -> TiledPass {
+        let mut landmarks = HashMap::new();
+        let root = match application_root_dir() {
+            Ok(r) => r,
+            Err(e) => {
+                println!("could not find application root for landmarks: {:?}", e);
+                return TiledPass::new(landmarks);
+            }
+        };
+        for def in set.landmarks.iter() {
+            match import_tmx(&root.join(&def.map)) {
+                Ok(area) if area.width > w || area.height > h => {
+                    println!("landmark {} at {:?} is {}x{}, larger than the {}x{} area", def.map, def.location, area.width, area.height, w, h);
+                }
+                Ok(area) => {
+                    landmarks.insert(def.location, area);
+                }
+                Err(e) => println!("failed to import landmark {} at {:?}: {}", def.map, def.location, e),
+            }
+        }
+        TiledPass::new(landmarks)
+    }
+    //behaviour trees the landmarks' spawn points ask for
+    pub fn behaviours(&self) -> Vec<&str> {
+        self.landmarks.values()
+            .flat_map(|l| l.spawn_points.iter())
+            .filter_map(|p| p.behaviour.as_ref().map(|b| b.as_str()))
+            .collect()
+    }
+}
+
+impl GenPass for TiledPass {
+    fn stage(&self) -> GenStage {
+        GenStage::Structures
+    }
+
+    fn apply(&self, area: &mut Area, ctx: &mut GenContext) {
+        let tmx = match self.landmarks.get(&ctx.surface_location()) {
+            Some(t) => t,
+            None => return,
+        };
+
+        let w = ctx.width;
+        let h = ctx.height;
+        let ox = w.saturating_sub(tmx.width) / 2;
+        let oy = h.saturating_sub(tmx.height) / 2;
+
+        for y in 0..tmx.height {
+            for x in 0..tmx.width {
+                if x + ox >= w || y + oy >= h {
+                    continue;
+                }
+                if let Some(block) = tmx.tiles[x + y * tmx.width] {
+                    area.tiles[(x + ox) + (y + oy) * w] = block;
+                }
+            }
+        }
+
+        //anything hanging off the area is cut back to it, so later passes can index its tiles
+        for rect in tmx.structures.iter() {
+            let (x, y) = (rect.x + ox, rect.y + oy);
+            if x >= w || y >= h {
+                continue;
+            }
+            area.structures.push(Rect::new((x, y), (rect.w.min(w - x), rect.h.min(h - y))));
+        }
+        for point in tmx.spawn_points.iter() {
+            let (x, y) = (point.pos.0 + ox, point.pos.1 + oy);
+            if x >= w || y >= h {
+                continue;
+            }
+            area.spawn_points.push(SpawnPoint {
+                pos: (x, y),
+                ..point.clone()
+            });
+        }
+
+        ctx.handcrafted = true;
+    }
+}
+
+//the tiles.png tileset as a .tsx, flagging impassable tiles so an edited map imports the same way
+pub fn tileset_tsx(image_source: &str) -> String {
+    let count = Tile::Size as usize;
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!("<tileset version=\"1.2\" tiledversion=\"1.2.4\" name=\"tiles\" tilewidth=\"{}\" tileheight=\"{}\" tilecount=\"{}\" columns=\"{}\">\n", TILE_SIZE, TILE_SIZE, count, count));
+    out.push_str(&format!(" <image source=\"{}\" width=\"{}\" height=\"{}\"/>\n", image_source, count * TILE_SIZE, TILE_SIZE));
+    for id in 0..count {
+        let tile: Tile = num::FromPrimitive::from_usize(id).unwrap();
+        if !tile.default_passable() {
+            out.push_str(&format!(" <tile id=\"{}\">\n  <properties>\n   <property name=\"passable\" type=\"bool\" value=\"false\"/>\n  </properties>\n </tile>\n", id));
+        }
+    }
+    out.push_str("</tileset>\n");
+    out
+}
+
+//an area as a .tmx: one tile layer plus an object layer of structures and the given entity positions
+pub fn area_tmx(area: &Area, (w, h): (usize, usize), entities: &[SpawnPoint]) -> String {
+    let mut out = String::new();
+    out.push_str("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str(&format!("<map version=\"1.2\" tiledversion=\"1.2.4\" orientation=\"orthogonal\" renderorder=\"right-down\" width=\"{}\" height=\"{}\" tilewidth=\"{}\" tileheight=\"{}\" infinite=\"0\" nextlayerid=\"3\" nextobjectid=\"{}\">\n", w, h, TILE_SIZE, TILE_SIZE, area.structures.len() + entities.len() + 1));
+    out.push_str(" <tileset firstgid=\"1\" source=\"tiles.tsx\"/>\n");
+
+    //tiled rows run top down, ours run bottom up
+    out.push_str(&format!(" <layer id=\"1\" name=\"ground\" width=\"{}\" height=\"{}\">\n  <data encoding=\"csv\">\n", w, h));
+    let mut rows = Vec::new();
+    for row in 0..h {
+        let y = h - 1 - row;
+        let cells: Vec<String> = (0..w).map(|x| (area.tiles[x + y * w].tile as usize + 1).to_string()).collect();
+        rows.push(cells.join(","));
+    }
+    out.push_str(&rows.join(",\n"));
+    out.push_str("\n</data>\n </layer>\n");
+
+    out.push_str(" <objectgroup id=\"2\" name=\"objects\">\n");
+    let mut id = 1;
+    for rect in area.structures.iter() {
+        let top = h.saturating_sub(rect.y + rect.h);
+        out.push_str(&format!("  <object id=\"{}\" type=\"structure\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\"/>\n", id, rect.x * TILE_SIZE, top * TILE_SIZE, rect.w * TILE_SIZE, rect.h * TILE_SIZE));
+        id += 1;
+    }
+    for point in entities.iter() {
+        let etype = match point.etype {
+            EntityType::Person => "person",
+            EntityType::Plant => "plant",
+            _ => continue,
+        };
+        let (tx, ty) = point.pos;
+        if tx >= w || ty >= h {
+            continue;
+        }
+        let px = tx * TILE_SIZE + TILE_SIZE / 2;
+        let py = (h - 1 - ty) * TILE_SIZE + TILE_SIZE / 2;
+        match point.behaviour.as_ref() {
+            Some(tree) => out.push_str(&format!("  <object id=\"{}\" type=\"{}\" x=\"{}\" y=\"{}\">\n   <properties>\n    <property name=\"behaviour\" value=\"{}\"/>\n   </properties>\n  </object>\n", id, etype, px, py, escape_attr(tree))),
+            None => out.push_str(&format!("  <object id=\"{}\" type=\"{}\" x=\"{}\" y=\"{}\"/>\n", id, etype, px, py)),
+        }
+        id += 1;
+    }
+    out.push_str(" </objectgroup>\n</map>\n");
+    out
+}
+
+//makes text safe to put inside a double quoted xml attribute
+fn escape_attr(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+//writes area_<x>_<y>.tmx (area_<x>_<y>_<layer>.tmx below the surface) and its tiles.tsx into dir,
+//returning the map path
+pub fn export_tmx(dir: &Path, area: &Area, location: (i32, i32, i32), dim: (usize, usize), entities: &[SpawnPoint]) -> io::Result<PathBuf> {
+    fs::create_dir_all(dir)?;
+    fs::write(dir.join("tiles.tsx"), tileset_tsx(EXPORT_TILES_IMAGE))?;
+    let name = if location.2 == 0 {
+        format!("area_{}_{}.tmx", location.0, location.1)
+    }else{
+        format!("area_{}_{}_{}.tmx", location.0, location.1, location.2)
+    };
+    let path = dir.join(name);
+    fs::write(&path, area_tmx(area, dim, entities))?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::game_state::WorldRng;
+
+    fn area((w, h): (usize, usize)) -> Area {
+        let mut area = Area::new();
+        area.tiles = (0..w * h).map(|i| {
+            let tile: Tile = num::FromPrimitive::from_usize(i % Tile::Size as usize).unwrap();
+            TileBlock::new(tile, tile.default_passable())
+        }).collect();
+        area
+    }
+
+    const TSX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<tileset version="1.2" tiledversion="1.2.4" name="tiles" tilewidth="16" tileheight="16" tilecount="13" columns="13">
+ <image source="tiles.png" width="208" height="16"/>
+ <tile id="1">
+  <properties>
+   <property name="passable" type="bool" value="false"/>
+  </properties>
+ </tile>
+</tileset>
+"#;
+
+    //top row plain, grassy made impassable by the tileset, and a wall; bottom row an empty cell and plain ground
+    const TMX: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<map version="1.2" tiledversion="1.2.4" orientation="orthogonal" renderorder="right-down" width="3" height="2" tilewidth="16" tileheight="16" infinite="0" nextlayerid="3" nextobjectid="3">
+ <tileset firstgid="1" source="tiles.tsx"/>
+ <layer id="1" name="ground" width="3" height="2">
+  <data encoding="csv">
+1,2,12,
+0,1,1
+</data>
+ </layer>
+ <objectgroup id="2" name="objects">
+  <object id="1" type="structure" x="16" y="0" width="32" height="16"/>
+  <object id="2" type="person" x="8" y="24"/>
+ </objectgroup>
+</map>
+"#;
+
+    #[test]
+    fn imports_tiles_objects_and_tileset_passability() {
+        let dir = std::env::temp_dir().join(format!("bittenoff_tmx_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("tiles.tsx"), TSX).unwrap();
+        fs::write(dir.join("landmark.tmx"), TMX).unwrap();
+        let imported = import_tmx(&dir.join("landmark.tmx"));
+        fs::remove_dir_all(&dir).unwrap();
+        let imported = imported.unwrap();
+
+        assert_eq!((imported.width, imported.height), (3, 2));
+        let tiles: Vec<_> = imported.tiles.iter().map(|t| t.map(|b| (b.tile as usize, b.passable))).collect();
+        assert_eq!(tiles, vec![
+            None, Some((Tile::Plain as usize, true)), Some((Tile::Plain as usize, true)),
+            Some((Tile::Plain as usize, true)), Some((Tile::Grassy as usize, false)), Some((Tile::WoodWall as usize, false)),
+        ]);
+        let rects: Vec<_> = imported.structures.iter().map(|r| (r.x, r.y, r.w, r.h)).collect();
+        assert_eq!(rects, vec![(1, 1, 2, 1)]);
+        let points: Vec<_> = imported.spawn_points.iter().map(|p| (p.pos, p.etype)).collect();
+        assert_eq!(points, vec![((0, 0), EntityType::Person)]);
+    }
+
+    #[test]
+    fn exported_areas_import_unchanged() {
+        let dim = (7, 5);
+        let mut exported = area(dim);
+        exported.structures.push(Rect::new((1, 0), (3, 2)));
+        let mut guard = SpawnPoint::new((2, 4), EntityType::Person);
+        guard.behaviour = Some("guard".to_string());
+        let entities = [guard, SpawnPoint::new((6, 0), EntityType::Plant)];
+
+        let dir = std::env::temp_dir().join(format!("bittenoff_tmx_export_{}", std::process::id()));
+        let path = export_tmx(&dir, &exported, (3, -2, 0), dim, &entities).unwrap();
+        let imported = import_tmx(&path);
+        fs::remove_dir_all(&dir).unwrap();
+        let imported = imported.unwrap();
+
+        assert_eq!((imported.width, imported.height), dim);
+        for (a, b) in exported.tiles.iter().zip(imported.tiles.iter()) {
+            let b = b.unwrap();
+            assert_eq!((a.tile as usize, a.passable), (b.tile as usize, b.passable));
+        }
+        let rects: Vec<_> = imported.structures.iter().map(|r| (r.x, r.y, r.w, r.h)).collect();
+        assert_eq!(rects, vec![(1, 0, 3, 2)]);
+        let points: Vec<_> = imported.spawn_points.iter().map(|p| (p.pos, p.etype, p.behaviour.clone())).collect();
+        assert_eq!(points, vec![((2, 4), EntityType::Person, Some("guard".to_string())), ((6, 0), EntityType::Plant, None)]);
+    }
+
+    #[test]
+    fn behaviour_names_are_escaped_on_export() {
+        let dim = (4, 3);
+        let mut odd = SpawnPoint::new((1, 1), EntityType::Person);
+        odd.behaviour = Some("say \"hi\" & <wave>".to_string());
+
+        let dir = std::env::temp_dir().join(format!("bittenoff_tmx_escape_{}", std::process::id()));
+        let path = export_tmx(&dir, &area(dim), (0, 0, 0), dim, &[odd]).unwrap();
+        let imported = import_tmx(&path);
+        fs::remove_dir_all(&dir).unwrap();
+        let imported = imported.unwrap();
+
+        let names: Vec<_> = imported.spawn_points.iter().map(|p| p.behaviour.clone()).collect();
+        assert_eq!(names, vec![Some("say \"hi\" & <wave>".to_string())]);
+    }
+
+    #[test]
+    fn landmarks_are_clipped_to_the_area() {
+        let dim = (6, 4);
+        let landmark = TiledArea {
+            width: 6,
+            height: 4,
+            tiles: vec![None; 24],
+            structures: vec![Rect::new((4, 1), (5, 5)), Rect::new((6, 0), (2, 2))],
+            spawn_points: vec![SpawnPoint::new((5, 3), EntityType::Person), SpawnPoint::new((9, 1), EntityType::Plant)],
+        };
+        let mut landmarks = HashMap::new();
+        landmarks.insert((0, 0), landmark);
+
+        let mut target = area(dim);
+        let mut ctx = GenContext::new((0, 0, 0), dim, (0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0), &WorldRng::new(1));
+        TiledPass::new(landmarks).apply(&mut target, &mut ctx);
+
+        let rects: Vec<_> = target.structures.iter().map(|r| (r.x, r.y, r.w, r.h)).collect();
+        assert_eq!(rects, vec![(4, 1, 2, 3)]);
+        let points: Vec<_> = target.spawn_points.iter().map(|p| p.pos).collect();
+        assert_eq!(points, vec![(5, 3)]);
+        assert!(ctx.handcrafted);
+    }
+}
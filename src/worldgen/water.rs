@@ -0,0 +1,216 @@
+use std::f64::consts::PI;
+
+use noise::{NoiseFn, Perlin};
+use rand::Rng;
+
+use crate::game_state::{Area, Tile, TileBlock, RngStream, ZOOM_FACTOR, LAKE_RESOLUTION_FACTOR, LAKE_LEVEL, SHORE_DEPTH, RIVER_SPACING, RIVER_SOURCE_CHANCE, RIVER_SOURCE_LEVEL, RIVER_STEP, RIVER_LENGTH, RIVER_WIDTH};
+use crate::worldgen::{GenContext, GenPass, GenStage};
+
+//floods low lying basins into lakes and runs rivers from springs on high ground down into them, widening on
+//the way. everything is worked out in global tile coordinates, so water carries on across area edges
+pub struct WaterPass;
+
+impl GenPass for WaterPass {
+    fn stage(&self) -> GenStage {
+        GenStage::Water
+    }
+
+    fn apply(&self, area: &mut Area, ctx: &mut GenContext) {
+        let w = ctx.width;
+        let h = ctx.height;
+        let relief = Relief::new(ctx);
+
+        let mut rivers = vec![None; w * h];
+        for path in river_paths(&relief, ctx).iter() {
+            draw_river(&mut rivers, path, ctx);
+        }
+
+        for y in 0..h {
+            for x in 0..w {
+                let index = x + y * w;
+                let basin = -relief.ground(ctx.global_tile(x, y));
+
+                let water = if basin > LAKE_LEVEL {
+                    Some(Tile::DeepWater)
+                }else if basin > LAKE_LEVEL - SHORE_DEPTH {
+                    Some(Tile::ShallowWater)
+                }else{
+                    rivers[index]
+                };
+
+                if let Some(tile) = water {
+                    area.tiles[index] = TileBlock::new(tile, tile.default_passable());
+                }
+            }
+        }
+    }
+}
+
+//the lie of the land, sampled anywhere in the world
+struct Relief {
+    perlin: Perlin,
+    seed: (f64, f64),
+    span: (f64, f64), //tiles per area, counting shared edges once
+}
+
+impl Relief {
+    fn new(ctx: &GenContext) -> Relief {
+        Relief {
+            perlin: Perlin::new(),
+            seed: (ctx.world_seed.0, ctx.world_seed.1),
+            span: ((ctx.width - 1) as f64, (ctx.height - 1) as f64),
+        }
+    }
+    //the terrain noise without its small scale roughness, so shores and rivers run smoothly
+    fn elevation(&self, (gx, gy): (f64, f64)) -> f64 {
+        let (ux, uy) = (gx / self.span.0, gy / self.span.1);
+        self.perlin.get([self.seed.0 + ux / ZOOM_FACTOR, self.seed.1 + uy / ZOOM_FACTOR]).abs()
+    }
+    //height as water sees it: the elevation, sunk wherever a third noise field has a basin. below -LAKE_LEVEL
+    //it's under a lake
+    fn ground(&self, p: (f64, f64)) -> f64 {
+        let (ux, uy) = (p.0 / self.span.0, p.1 / self.span.1);
+        let basin = self.perlin.get([self.seed.0 - ux * LAKE_RESOLUTION_FACTOR, self.seed.1 - uy * LAKE_RESOLUTION_FACTOR]);
+        self.elevation(p) - basin
+    }
+    //follows the steepest way down from the source until it reaches a lake, a hollow or the end of its length
+    fn trace(&self, source: (f64, f64)) -> Vec<(f64, f64)> {
+        let mut path = vec![source];
+        let mut here = (source, self.ground(source));
+        for _i in 0..RIVER_LENGTH {
+            if -here.1 > LAKE_LEVEL {
+                break;
+            }
+            let lowest = (0..8)
+                .map(|d| {
+                    let angle = d as f64 * PI / 4.0;
+                    let p = ((here.0).0 + RIVER_STEP * angle.cos(), (here.0).1 + RIVER_STEP * angle.sin());
+                    (p, self.ground(p))
+                })
+                .fold(here, |low, next| if next.1 < low.1 { next } else { low });
+            if lowest.0 == here.0 {
+                break;
+            }
+            here = lowest;
+            path.push(here.0);
+        }
+        path
+    }
+}
+
+//every river that could reach the area; a river can't run further than its length from its source, so
+//neighbouring areas look at enough of the same sources to agree on their shared edge
+fn river_paths(relief: &Relief, ctx: &GenContext) -> Vec<Vec<(f64, f64)>> {
+    let reach = (RIVER_LENGTH as f64 * RIVER_STEP + RIVER_WIDTH) as i64;
+    let (x0, y0) = ctx.global_index(0, 0);
+    let (x1, y1) = ctx.global_index(ctx.width - 1, ctx.height - 1);
+
+    let mut paths = Vec::new();
+    for cy in (y0 - reach).div_euclid(RIVER_SPACING)..=(y1 + reach).div_euclid(RIVER_SPACING) {
+        for cx in (x0 - reach).div_euclid(RIVER_SPACING)..=(x1 + reach).div_euclid(RIVER_SPACING) {
+            let mut rng = ctx.world_rng.area_rng((cx as i32, cy as i32), RngStream::Rivers);
+            if rng.gen::<f32>() >= RIVER_SOURCE_CHANCE {
+                continue;
+            }
+            let source = ((cx * RIVER_SPACING + rng.gen_range(0, RIVER_SPACING)) as f64, (cy * RIVER_SPACING + rng.gen_range(0, RIVER_SPACING)) as f64);
+            if relief.elevation(source) < RIVER_SOURCE_LEVEL {
+                continue;
+            }
+            paths.push(relief.trace(source));
+        }
+    }
+    paths
+}
+
+//a trickle at the source, growing to its full width over the river's length; deep water only runs down the
+//middle once it's wide enough
+fn river_width(step: f64) -> f64 {
+    1.0 + (RIVER_WIDTH - 1.0) * step / RIVER_LENGTH as f64
+}
+
+fn draw_river(rivers: &mut Vec<Option<Tile>>, path: &[(f64, f64)], ctx: &GenContext) {
+    let (w, h) = (ctx.width, ctx.height);
+    let (ox, oy) = ctx.global_index(0, 0);
+
+    for (i, seg) in path.windows(2).enumerate() {
+        let (a, b) = (seg[0], seg[1]);
+        let (wa, wb) = (river_width(i as f64), river_width(i as f64 + 1.0));
+        let reach = wb / 2.0 + 1.0;
+
+        //the tiles around the segment that lie in the area
+        let left = ((a.0.min(b.0) - reach).floor() as i64 - ox).max(0);
+        let right = ((a.0.max(b.0) + reach).ceil() as i64 - ox).min(w as i64 - 1);
+        let bottom = ((a.1.min(b.1) - reach).floor() as i64 - oy).max(0);
+        let top = ((a.1.max(b.1) + reach).ceil() as i64 - oy).min(h as i64 - 1);
+
+        let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+        let length = dx * dx + dy * dy;
+        for y in bottom..=top {
+            for x in left..=right {
+                let (px, py) = ((x + ox) as f64, (y + oy) as f64);
+                let t = (((px - a.0) * dx + (py - a.1) * dy) / length).max(0.0).min(1.0);
+                let (cx, cy) = (a.0 + t * dx - px, a.1 + t * dy - py);
+                let dist = (cx * cx + cy * cy).sqrt();
+                let half = (wa + t * (wb - wa)) / 2.0;
+
+                let index = x as usize + y as usize * w;
+                if dist < half - 1.0 {
+                    rivers[index] = Some(Tile::DeepWater);
+                }else if dist < half + 0.5 && rivers[index].is_none() {
+                    rivers[index] = Some(Tile::ShallowWater);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::worldgen::generator::tests::context;
+
+    //the river tiles of an area, as drawn from every river that reaches it
+    fn rivers(ctx: &GenContext) -> Vec<Option<Tile>> {
+        let relief = Relief::new(ctx);
+        let mut rivers = vec![None; ctx.width * ctx.height];
+        for path in river_paths(&relief, ctx).iter() {
+            draw_river(&mut rivers, path, ctx);
+        }
+        rivers
+    }
+
+    #[test]
+    fn rivers_never_run_uphill() {
+        let ctx = context(7, (0, 0, 0));
+        let relief = Relief::new(&ctx);
+        let paths = river_paths(&relief, &ctx);
+        assert!(!paths.is_empty());
+        for path in paths.iter() {
+            for step in path.windows(2) {
+                assert!(relief.ground(step[1]) <= relief.ground(step[0]));
+            }
+        }
+    }
+
+    #[test]
+    fn rivers_line_up_across_area_edges() {
+        let mut crossed = 0;
+        for i in -3..3 {
+            let here = context(6, (i, i, 0));
+            let (w, h) = (here.width, here.height);
+            let tiles = rivers(&here);
+            let east = rivers(&context(6, (i + 1, i, 0)));
+            let north = rivers(&context(6, (i, i + 1, 0)));
+            for y in 0..h {
+                assert_eq!(tiles[w - 1 + y * w], east[y * w], "river tiles differ east of {} at row {}", i, y);
+                crossed += tiles[w - 1 + y * w].is_some() as usize;
+            }
+            for x in 0..w {
+                assert_eq!(tiles[x + (h - 1) * w], north[x], "river tiles differ north of {} at column {}", i, x);
+                crossed += tiles[x + (h - 1) * w].is_some() as usize;
+            }
+        }
+        //otherwise there was nothing to compare
+        assert!(crossed > 0);
+    }
+}